@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::machine::ExecutedInstruction;
+
+type Address = (u16, u16);
+
+#[derive(Default)]
+struct Counts {
+    // Executions that moved a word to or from an odd address.
+    misaligned: u64,
+    clocks: u64,
+}
+
+// Instructions that moved words to or from odd addresses, operands, stack and string elements
+// alike, and the clocks the extra bus cycles cost them. Nothing is found on the 8088, where
+// alignment doesn't matter.
+#[derive(Default)]
+pub struct AlignmentLint {
+    instructions: HashMap<Address, (Counts, String)>,
+}
+
+impl AlignmentLint {
+    pub fn record(&mut self, executed: &ExecutedInstruction) {
+        if executed.alignment_penalty == 0 {
+            return;
+        }
+        let (counts, _) = self
+            .instructions
+            .entry((executed.segment, executed.offset))
+            .or_insert_with(|| (Counts::default(), executed.instruction.to_string()));
+        counts.misaligned += 1;
+        counts.clocks += executed.alignment_penalty as u64;
+    }
+
+    // The costliest instructions first.
+    pub fn report(&self) -> String {
+        let clocks: u64 = self
+            .instructions
+            .values()
+            .map(|(counts, _)| counts.clocks)
+            .sum();
+        let mut text = format!(
+            "Misaligned word accesses: {} instructions, {} clocks lost\n",
+            self.instructions.len(),
+            clocks
+        );
+        if self.instructions.is_empty() {
+            return text;
+        }
+        text += "  address      times     clocks  instruction\n";
+        let mut instructions: Vec<_> = self.instructions.iter().collect();
+        instructions
+            .sort_by_key(|(address, (counts, _))| (std::cmp::Reverse(counts.clocks), **address));
+        for ((segment, offset), (counts, instruction)) in instructions {
+            writeln!(
+                text,
+                "  {:04x}:{:04x}  {:>9}  {:>9}  {}",
+                segment, offset, counts.misaligned, counts.clocks, instruction
+            )
+            .unwrap();
+        }
+        text
+    }
+}
@@ -0,0 +1,218 @@
+use std::fmt::Write;
+
+use crate::constants::{CS, SP, SS};
+use crate::machine::Machine;
+use crate::memory::physical_address;
+use crate::symbols::Symbols;
+
+type Address = (u16, u16);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FrameKind {
+    Near,
+    Far,
+    Interrupt(u8),
+}
+
+impl FrameKind {
+    fn description(&self) -> String {
+        match self {
+            FrameKind::Near => String::from("near call"),
+            FrameKind::Far => String::from("far call"),
+            FrameKind::Interrupt(vector) => format!("int {:#04x}", vector),
+        }
+    }
+}
+
+pub struct Frame {
+    pub kind: FrameKind,
+    // Where the call or interrupt went.
+    pub target: Address,
+    pub return_address: Address,
+    // ss:sp of the pushed return address.
+    pub slot: Address,
+}
+
+// The stack a program may use, in its stack segment. sp ranges from top, the empty stack, down
+// to lowest. A top of 0 is the end of the segment.
+pub struct StackLimit {
+    pub segment: u16,
+    pub lowest: u16,
+    pub top: u16,
+}
+
+// The calls and interrupts the simulator has entered and not yet returned from, kept beside the
+// real stack so a backtrace doesn't depend on what the program left in memory. Returns that
+// don't match the frame they leave, and sp moving past the stack limit, are reported as
+// problems for the run loop to show.
+#[derive(Default)]
+pub struct CallStack {
+    pub frames: Vec<Frame>,
+    pub limit: Option<StackLimit>,
+    // Problems the run loop hasn't shown yet.
+    problems: Vec<String>,
+    // Problems found during the whole run.
+    pub problem_count: u64,
+    // Set while sp is past the limit, so each excursion is reported once.
+    outside_limit: bool,
+}
+
+impl CallStack {
+    pub fn call(
+        &mut self,
+        kind: FrameKind,
+        target: Address,
+        return_address: Address,
+        slot: Address,
+    ) {
+        // Also keeps runaway recursion from growing the frames once sp wraps around.
+        self.drop_left_frames(slot, true);
+        self.frames.push(Frame {
+            kind,
+            target,
+            return_address,
+            slot,
+        });
+    }
+
+    // A return at `at` popped its return address from slot and went to returned_to. retf and
+    // iret may leave either kind of far frame, a handler can end with retf 2 and a far call
+    // after pushf can be left with iret.
+    pub fn ret(&mut self, name: &str, at: Address, slot: Address, returned_to: Address) {
+        self.drop_left_frames(slot, false);
+        // Nothing to check after the program's entry or on another stack.
+        let Some(frame) = self.frames.last() else {
+            return;
+        };
+        if frame.slot.0 != slot.0 {
+            return;
+        }
+        if frame.slot != slot {
+            let text = format!(
+                "{} at {} left {} bytes on the stack and returned to {}, expected {}",
+                name,
+                address_text(at),
+                frame.slot.1 - slot.1,
+                address_text(returned_to),
+                address_text(frame.return_address)
+            );
+            self.problem(text);
+            return;
+        }
+
+        let frame = self.frames.pop().unwrap();
+        if (frame.kind == FrameKind::Near) != (name == "ret") {
+            let text = format!(
+                "{} at {} returns from a {} to {}",
+                name,
+                address_text(at),
+                frame.kind.description(),
+                address_text(frame.target)
+            );
+            self.problem(text);
+        } else if returned_to != frame.return_address {
+            let text = format!(
+                "{} at {} returned to {}, expected {}, the return address was overwritten",
+                name,
+                address_text(at),
+                address_text(returned_to),
+                address_text(frame.return_address)
+            );
+            self.problem(text);
+        }
+    }
+
+    // Frames sp has already moved past were left some other way, by a jump out of a handler or
+    // by popping the return address of a call made to find ip. A new return address in the
+    // slot of a frame replaces it.
+    fn drop_left_frames(&mut self, slot: Address, replaced: bool) {
+        while let Some(frame) = self.frames.last()
+            && frame.slot.0 == slot.0
+            && (frame.slot.1 < slot.1 || (replaced && frame.slot.1 == slot.1))
+        {
+            self.frames.pop();
+        }
+    }
+
+    // Called after every instruction with the instruction's address.
+    pub fn check_limit(&mut self, at: Address, ss: u16, sp: u16) {
+        let Some(limit) = &self.limit else {
+            return;
+        };
+        if ss != limit.segment {
+            return;
+        }
+        // Bytes in use, negative once more was popped than pushed.
+        let used = limit.top.wrapping_sub(sp) as i16;
+        let text = if used < 0 {
+            format!(
+                "Stack underflow at {}: sp {:#06x} is above the top of the stack {:#06x}",
+                address_text(at),
+                sp,
+                limit.top
+            )
+        } else if used as u16 > limit.top.wrapping_sub(limit.lowest) {
+            format!(
+                "Stack overflow at {}: sp {:#06x} is below the stack limit {:#06x}",
+                address_text(at),
+                sp,
+                limit.lowest
+            )
+        } else {
+            self.outside_limit = false;
+            return;
+        };
+        if !self.outside_limit {
+            self.outside_limit = true;
+            self.problem(text);
+        }
+    }
+
+    pub fn take_problems(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.problems)
+    }
+
+    fn problem(&mut self, text: String) {
+        self.problems.push(text);
+        self.problem_count += 1;
+    }
+}
+
+pub fn address_text((segment, offset): Address) -> String {
+    format!("{:04x}:{:04x}", segment, offset)
+}
+
+// `#0  1000:0123 sum+0x3` for where execution is, then one line for each frame with where it
+// returns to and what entered it.
+pub fn backtrace(machine: &Machine, symbols: &Symbols) -> String {
+    let describe = |address: Address| {
+        let mut text = address_text(address);
+        if let Some(name) = symbols.describe(physical_address(address.0, address.1)) {
+            text += &format!(" {}", name);
+        }
+        text
+    };
+    let cs_ip = (machine.segment_registers[CS], machine.ip);
+    let ss = machine.segment_registers[SS];
+    let sp = machine.simulation_registers[SP] as u16;
+
+    let mut text = format!("#0  {}\n", describe(cs_ip));
+    let live = machine
+        .call_stack
+        .frames
+        .iter()
+        .rev()
+        .filter(|frame| frame.slot.0 != ss || frame.slot.1 >= sp);
+    for (depth, frame) in live.enumerate() {
+        writeln!(
+            text,
+            "#{:<2} {}, after the {} to {}",
+            depth + 1,
+            describe(frame.return_address),
+            frame.kind.description(),
+            describe(frame.target)
+        )
+        .unwrap();
+    }
+    text
+}
@@ -5,23 +5,142 @@ pub const REGISTER_NAMES: [[&str; 8]; 2] = [
     ["ax", "cx", "dx", "bx", "sp", "bp", "si", "di"],
 ];
 
-pub const EFFECTIVE_MEMOERY_ADDRESS: [&str; 8] = [
-    "bx + si", "bx + di", "bp + si", "bp + di", "si", "di", "bp", "bx",
-];
+pub const AX: usize = 0;
+pub const CX: usize = 1;
+pub const DX: usize = 2;
+pub const BX: usize = 3;
+pub const SP: usize = 4;
+pub const BP: usize = 5;
+pub const SI: usize = 6;
+pub const DI: usize = 7;
+
+// Byte registers, indexed like REGISTER_NAMES[0].
+pub const AL: usize = 0;
+pub const CL: usize = 1;
+pub const DL: usize = 2;
+pub const AH: usize = 4;
+pub const CH: usize = 5;
+pub const DH: usize = 6;
+pub const BH: usize = 7;
+
+pub const SEGMENT_REGISTER_NAMES: [&str; 4] = ["es", "cs", "ss", "ds"];
 
-pub const ACCUMULATOR_NAMES: [&str; 2] = ["al", "ax"];
+pub const ES: usize = 0;
+pub const CS: usize = 1;
+pub const SS: usize = 2;
+pub const DS: usize = 3;
+
+pub const EFFECTIVE_MEMOERY_ADDRESS: [&str; 8] =
+    ["bx+si", "bx+di", "bp+si", "bp+di", "si", "di", "bp", "bx"];
 
 pub const MOVE_IMMEDIATE_TO_REGISTER_INSTRUCTION: u8 = 0b10110000;
 
 pub const IMMEDIATE_TO_REGISTER_MEMORY_INSTRUCTION: u8 = 0b10000000;
 pub const IMMEDIATE_TO_REGISTER_MEMORY_INSTRUCTION_MOV: u8 = 0b11000110;
 
-pub const IMMEDIATE_TO_ACCUMULATOR_INSTRUCTIONS: [(u8, &str); 3] = [
+pub const IMMEDIATE_TO_REGISTER_MEMORY_INSTRUCTIONS: [&str; 8] =
+    ["add", "or", "adc", "sbb", "and", "sub", "xor", "cmp"];
+
+pub const REGISTER_MEMORY_TO_REGISTER_MEMORY_INSTRUCTIONS: [(u8, &str); 9] = [
+    (0b10001000, "mov"),
+    (0b00000000, "add"),
+    (0b00001000, "or"),
+    (0b00010000, "adc"),
+    (0b00011000, "sbb"),
+    (0b00100000, "and"),
+    (0b00101000, "sub"),
+    (0b00110000, "xor"),
+    (0b00111000, "cmp"),
+];
+
+// test and xchg between a register and r/m, 100001 x w without a direction bit.
+pub const TEST_INSTRUCTION: u8 = 0b10000100;
+pub const XCHG_INSTRUCTION: u8 = 0b10000110;
+// xchg of ax with the register in the low three bits, xchg ax, ax is nop.
+pub const XCHG_ACCUMULATOR_INSTRUCTION: u8 = 0b10010000;
+pub const LEA_INSTRUCTION: u8 = 0b10001101;
+pub const LES_INSTRUCTION: u8 = 0b11000100;
+pub const LDS_INSTRUCTION: u8 = 0b11000101;
+// mov between the accumulator and a direct address, 101000 d w with d set for the store.
+pub const MOVE_ACCUMULATOR_MEMORY_INSTRUCTION: u8 = 0b10100000;
+
+pub const MOVE_TO_SEGMENT_REGISTER_INSTRUCTION: u8 = 0b10001110;
+pub const MOVE_FROM_SEGMENT_REGISTER_INSTRUCTION: u8 = 0b10001100;
+
+pub const IMMEDIATE_TO_ACCUMULATOR_INSTRUCTIONS: [(u8, &str); 9] = [
     (0b00000100, "add"),
+    (0b00001100, "or"),
+    (0b00010100, "adc"),
+    (0b00011100, "sbb"),
+    (0b00100100, "and"),
     (0b00101100, "sub"),
+    (0b00110100, "xor"),
     (0b00111100, "cmp"),
+    (0b10101000, "test"),
 ];
 
+// inc and dec with the register in the low three bits.
+pub const INC_REGISTER_INSTRUCTION: u8 = 0b01000000;
+pub const DEC_REGISTER_INSTRUCTION: u8 = 0b01001000;
+
+// in and out with the port as an immediate byte or in dx, 1110 x1 d w with d set for out.
+pub const IN_OUT_IMMEDIATE_INSTRUCTION: u8 = 0b11100100;
+pub const IN_OUT_DX_INSTRUCTION: u8 = 0b11101100;
+
+pub const NO_OPERAND_INSTRUCTIONS: [(u8, &str); 9] = [
+    (0b00100111, "daa"),
+    (0b00101111, "das"),
+    (0b00110111, "aaa"),
+    (0b00111111, "aas"),
+    (0b10011000, "cbw"),
+    (0b10011001, "cwd"),
+    (0b10011110, "sahf"),
+    (0b10011111, "lahf"),
+    (0b11010111, "xlatb"),
+];
+
+// aam and aad take the number base as an immediate byte, 10 unless assembled by hand.
+pub const ASCII_ADJUST_INSTRUCTIONS: [(u8, &str); 2] = [(0b11010100, "aam"), (0b11010101, "aad")];
+
+// String instructions read ds:si and write es:di, stepping both by the operand size.
+pub const STRING_INSTRUCTIONS: [(u8, &str); 10] = [
+    (0b10100100, "movsb"),
+    (0b10100101, "movsw"),
+    (0b10100110, "cmpsb"),
+    (0b10100111, "cmpsw"),
+    (0b10101010, "stosb"),
+    (0b10101011, "stosw"),
+    (0b10101100, "lodsb"),
+    (0b10101101, "lodsw"),
+    (0b10101110, "scasb"),
+    (0b10101111, "scasw"),
+];
+
+// Prefixes: 001 sr 110 replaces the segment of the memory operand, and the repeat prefixes run
+// a string instruction cx times. cmps and scas also stop once ZF differs from the low bit.
+pub const SEGMENT_OVERRIDE_PREFIX: u8 = 0b00100110;
+pub const REPEAT_PREFIX: u8 = 0b11110010;
+
+pub const INTERRUPT_INSTRUCTION: u8 = 0b11001101;
+pub const INTERRUPT_3_INSTRUCTION: u8 = 0b11001100;
+pub const INTERRUPT_ON_OVERFLOW_INSTRUCTION: u8 = 0b11001110;
+pub const INTERRUPT_RETURN_INSTRUCTION: u8 = 0b11001111;
+pub const HALT_INSTRUCTION: u8 = 0b11110100;
+
+pub const FLAG_INSTRUCTIONS: [(u8, &str); 7] = [
+    (0b11110101, "cmc"),
+    (0b11111000, "clc"),
+    (0b11111001, "stc"),
+    (0b11111010, "cli"),
+    (0b11111011, "sti"),
+    (0b11111100, "cld"),
+    (0b11111101, "std"),
+];
+
+pub const UNARY_INSTRUCTION: u8 = 0b11110110;
+pub const UNARY_INSTRUCTIONS: [&str; 8] =
+    ["test", "test", "not", "neg", "mul", "imul", "div", "idiv"];
+
 pub const RETURN_INSTRUCTIONS: [(u8, &str); 20] = [
     (0b01110100, "je"),
     (0b01111100, "jl"),
@@ -44,3 +163,217 @@ pub const RETURN_INSTRUCTIONS: [(u8, &str); 20] = [
     (0b11100000, "loopnz"),
     (0b11100011, "jcxz"),
 ];
+
+// Transfers of control without a condition, and the stack instructions.
+pub const CALL_INSTRUCTION: u8 = 0b11101000;
+pub const JUMP_INSTRUCTION: u8 = 0b11101001;
+pub const JUMP_SHORT_INSTRUCTION: u8 = 0b11101011;
+pub const CALL_FAR_INSTRUCTION: u8 = 0b10011010;
+pub const JUMP_FAR_INSTRUCTION: u8 = 0b11101010;
+// ret and retf, with the bytes of arguments to drop when the low bit is clear.
+pub const RETURN_NEAR_INSTRUCTION: u8 = 0b11000010;
+pub const RETURN_FAR_INSTRUCTION: u8 = 0b11001010;
+pub const PUSH_REGISTER_INSTRUCTION: u8 = 0b01010000;
+pub const POP_REGISTER_INSTRUCTION: u8 = 0b01011000;
+// 000 sr 11x, with the segment register in the middle and pop in the low bit.
+pub const PUSH_SEGMENT_INSTRUCTION: u8 = 0b00000110;
+pub const POP_SEGMENT_INSTRUCTION: u8 = 0b00000111;
+pub const PUSH_FLAGS_INSTRUCTION: u8 = 0b10011100;
+pub const POP_FLAGS_INSTRUCTION: u8 = 0b10011101;
+pub const POP_MEMORY_INSTRUCTION: u8 = 0b10001111;
+// The reg field selects the operation, 3 and 5 are the far call and jmp. The byte form only has
+// inc and dec.
+pub const INDIRECT_INSTRUCTION: u8 = 0b11111111;
+pub const INDIRECT_BYTE_INSTRUCTION: u8 = 0b11111110;
+pub const INDIRECT_INSTRUCTIONS: [&str; 8] =
+    ["inc", "dec", "call", "call", "jmp", "jmp", "push", ""];
+
+// 8087 instructions are escapes to the coprocessor, 11011xxx followed by a mod-reg-r/m byte.
+pub const ESCAPE_INSTRUCTION: u8 = 0b11011000;
+pub const WAIT_INSTRUCTION: u8 = 0b10011011;
+
+// Memory forms, indexed by the low three opcode bits and the reg field, with the operand size in
+// bytes. Empty names are not valid encodings.
+pub const FPU_MEMORY_INSTRUCTIONS: [[(&str, u16); 8]; 8] = [
+    [
+        ("fadd", 4),
+        ("fmul", 4),
+        ("fcom", 4),
+        ("fcomp", 4),
+        ("fsub", 4),
+        ("fsubr", 4),
+        ("fdiv", 4),
+        ("fdivr", 4),
+    ],
+    [
+        ("fld", 4),
+        ("", 0),
+        ("fst", 4),
+        ("fstp", 4),
+        ("fldenv", 14),
+        ("fldcw", 2),
+        ("fnstenv", 14),
+        ("fnstcw", 2),
+    ],
+    [
+        ("fiadd", 4),
+        ("fimul", 4),
+        ("ficom", 4),
+        ("ficomp", 4),
+        ("fisub", 4),
+        ("fisubr", 4),
+        ("fidiv", 4),
+        ("fidivr", 4),
+    ],
+    [
+        ("fild", 4),
+        ("", 0),
+        ("fist", 4),
+        ("fistp", 4),
+        ("", 0),
+        ("fld", 10),
+        ("", 0),
+        ("fstp", 10),
+    ],
+    [
+        ("fadd", 8),
+        ("fmul", 8),
+        ("fcom", 8),
+        ("fcomp", 8),
+        ("fsub", 8),
+        ("fsubr", 8),
+        ("fdiv", 8),
+        ("fdivr", 8),
+    ],
+    [
+        ("fld", 8),
+        ("", 0),
+        ("fst", 8),
+        ("fstp", 8),
+        ("frstor", 94),
+        ("", 0),
+        ("fnsave", 94),
+        ("fnstsw", 2),
+    ],
+    [
+        ("fiadd", 2),
+        ("fimul", 2),
+        ("ficom", 2),
+        ("ficomp", 2),
+        ("fisub", 2),
+        ("fisubr", 2),
+        ("fidiv", 2),
+        ("fidivr", 2),
+    ],
+    [
+        ("fild", 2),
+        ("", 0),
+        ("fist", 2),
+        ("fistp", 2),
+        ("fbld", 10),
+        ("fild", 8),
+        ("fbstp", 10),
+        ("fistp", 8),
+    ],
+];
+
+#[derive(Clone, Copy)]
+pub enum FpuOperands {
+    // st(i) alone.
+    Register,
+    // st0 is the destination and st(i) the source.
+    FromRegister,
+    // st(i) is the destination and st0 the source.
+    ToRegister,
+}
+
+// Register forms: opcode, reg field and name, the r/m field selects st(i).
+pub const FPU_REGISTER_INSTRUCTIONS: [(u8, u8, &str, FpuOperands); 25] = [
+    (0xd8, 0, "fadd", FpuOperands::FromRegister),
+    (0xd8, 1, "fmul", FpuOperands::FromRegister),
+    (0xd8, 2, "fcom", FpuOperands::Register),
+    (0xd8, 3, "fcomp", FpuOperands::Register),
+    (0xd8, 4, "fsub", FpuOperands::FromRegister),
+    (0xd8, 5, "fsubr", FpuOperands::FromRegister),
+    (0xd8, 6, "fdiv", FpuOperands::FromRegister),
+    (0xd8, 7, "fdivr", FpuOperands::FromRegister),
+    (0xd9, 0, "fld", FpuOperands::Register),
+    (0xd9, 1, "fxch", FpuOperands::Register),
+    // With st(i) as the destination the reversed and plain subtractions swap encodings.
+    (0xdc, 0, "fadd", FpuOperands::ToRegister),
+    (0xdc, 1, "fmul", FpuOperands::ToRegister),
+    (0xdc, 4, "fsubr", FpuOperands::ToRegister),
+    (0xdc, 5, "fsub", FpuOperands::ToRegister),
+    (0xdc, 6, "fdivr", FpuOperands::ToRegister),
+    (0xdc, 7, "fdiv", FpuOperands::ToRegister),
+    (0xdd, 0, "ffree", FpuOperands::Register),
+    (0xdd, 2, "fst", FpuOperands::Register),
+    (0xdd, 3, "fstp", FpuOperands::Register),
+    (0xde, 0, "faddp", FpuOperands::ToRegister),
+    (0xde, 1, "fmulp", FpuOperands::ToRegister),
+    (0xde, 4, "fsubrp", FpuOperands::ToRegister),
+    (0xde, 5, "fsubp", FpuOperands::ToRegister),
+    (0xde, 6, "fdivrp", FpuOperands::ToRegister),
+    (0xde, 7, "fdivp", FpuOperands::ToRegister),
+];
+
+// Register forms where the whole second byte selects an instruction without operands.
+pub const FPU_NO_OPERAND_INSTRUCTIONS: [(u8, u8, &str); 29] = [
+    (0xd9, 0xd0, "fnop"),
+    (0xd9, 0xe0, "fchs"),
+    (0xd9, 0xe1, "fabs"),
+    (0xd9, 0xe4, "ftst"),
+    (0xd9, 0xe5, "fxam"),
+    (0xd9, 0xe8, "fld1"),
+    (0xd9, 0xe9, "fldl2t"),
+    (0xd9, 0xea, "fldl2e"),
+    (0xd9, 0xeb, "fldpi"),
+    (0xd9, 0xec, "fldlg2"),
+    (0xd9, 0xed, "fldln2"),
+    (0xd9, 0xee, "fldz"),
+    (0xd9, 0xf0, "f2xm1"),
+    (0xd9, 0xf1, "fyl2x"),
+    (0xd9, 0xf2, "fptan"),
+    (0xd9, 0xf3, "fpatan"),
+    (0xd9, 0xf4, "fxtract"),
+    (0xd9, 0xf6, "fdecstp"),
+    (0xd9, 0xf7, "fincstp"),
+    (0xd9, 0xf8, "fprem"),
+    (0xd9, 0xf9, "fyl2xp1"),
+    (0xd9, 0xfa, "fsqrt"),
+    (0xd9, 0xfc, "frndint"),
+    (0xd9, 0xfd, "fscale"),
+    (0xdb, 0xe0, "fneni"),
+    (0xdb, 0xe1, "fndisi"),
+    (0xdb, 0xe2, "fnclex"),
+    (0xdb, 0xe3, "fninit"),
+    (0xde, 0xd9, "fcompp"),
+];
+
+// Instructions added by the 80186.
+pub const PUSH_IMMEDIATE_INSTRUCTION: u8 = 0b01101000;
+pub const IMUL_IMMEDIATE_INSTRUCTION: u8 = 0b01101001;
+pub const BOUND_INSTRUCTION: u8 = 0b01100010;
+pub const ENTER_INSTRUCTION: u8 = 0b11001000;
+pub const SHIFT_IMMEDIATE_INSTRUCTION: u8 = 0b11000000;
+pub const SHIFT_INSTRUCTIONS: [&str; 8] = ["rol", "ror", "rcl", "rcr", "shl", "shr", "", "sar"];
+
+// Shifts and rotates by 1, or by cl when v is set: 110100 v w.
+pub const SHIFT_INSTRUCTION: u8 = 0b11010000;
+
+pub const NO_OPERAND_80186_INSTRUCTIONS: [(u8, &str); 7] = [
+    (0b01100000, "pusha"),
+    (0b01100001, "popa"),
+    (0b01101100, "insb"),
+    (0b01101101, "insw"),
+    (0b01101110, "outsb"),
+    (0b01101111, "outsw"),
+    (0b11001001, "leave"),
+];
+
+// Encodings the 8086 executes although Intel never documented them. 0110xxxx repeats the
+// conditional jumps of 0111xxxx, and the unused shift operation sets the operand to all ones.
+pub const JUMP_ALIAS_INSTRUCTION: u8 = 0b01100000;
+pub const POP_CS_INSTRUCTION: u8 = 0b00001111;
+pub const SALC_INSTRUCTION: u8 = 0b11010110;
+pub const SET_MINUS_ONE_INSTRUCTIONS: [&str; 2] = ["setmo", "setmoc"];
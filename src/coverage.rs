@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::machine::ExecutedInstruction;
+use crate::memory::{MEMORY_SIZE, physical_address};
+use crate::nasm_listing::SourceLine;
+
+// Executed instructions and conditional jump outcomes, mapped back to the lines of a nasm
+// listing whose offset 0 was loaded at the physical address base.
+pub struct Coverage {
+    lines: Vec<SourceLine>,
+    base: usize,
+    // Executions per physical address of an instruction.
+    executed: HashMap<usize, u64>,
+    // How often each conditional jump was taken and not taken.
+    branches: HashMap<usize, (u64, u64)>,
+}
+
+enum LineCoverage {
+    NotCode,
+    Executed(u64),
+    Never,
+}
+
+impl Coverage {
+    pub fn new(lines: Vec<SourceLine>, base: usize) -> Coverage {
+        Coverage {
+            lines,
+            base,
+            executed: HashMap::new(),
+            branches: HashMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, executed: &ExecutedInstruction, after: (u16, u16)) {
+        let address = physical_address(executed.segment, executed.offset);
+        *self.executed.entry(address).or_default() += 1;
+
+        let instruction = &executed.instruction;
+        if instruction.is_jump() {
+            let next = executed.offset.wrapping_add(instruction.length);
+            let (taken, not_taken) = self.branches.entry(address).or_default();
+            if after == (executed.segment, next) {
+                *not_taken += 1;
+            } else {
+                *taken += 1;
+            }
+        }
+    }
+
+    fn address(&self, line: &SourceLine) -> Option<usize> {
+        Some((self.base + line.offset? as usize) % MEMORY_SIZE)
+    }
+
+    fn line_coverage(&self, line: &SourceLine) -> LineCoverage {
+        if !line.is_code() {
+            return LineCoverage::NotCode;
+        }
+        match self
+            .address(line)
+            .and_then(|address| self.executed.get(&address))
+        {
+            Some(count) => LineCoverage::Executed(*count),
+            None => LineCoverage::Never,
+        }
+    }
+
+    fn branch(&self, line: &SourceLine) -> Option<(u64, u64)> {
+        self.branches.get(&self.address(line)?).copied()
+    }
+
+    // gcov style: executions or ##### in front of every listing line, and a note on conditional
+    // jumps that only ever went one way.
+    pub fn annotated(&self) -> String {
+        let mut text = String::new();
+        for line in &self.lines {
+            let count = match self.line_coverage(line) {
+                LineCoverage::NotCode => String::from("-"),
+                LineCoverage::Executed(count) => count.to_string(),
+                LineCoverage::Never => String::from("#####"),
+            };
+            write!(text, "{:>9}:{:>5}:{}", count, line.number, line.source).unwrap();
+            match self.branch(line) {
+                Some((_, 0)) => text += "    ; branch always taken",
+                Some((0, _)) => text += "    ; branch never taken",
+                _ => {}
+            }
+            text += "\n";
+        }
+        text + &self.summary()
+    }
+
+    pub fn summary(&self) -> String {
+        let mut lines_found = 0;
+        let mut lines_hit = 0;
+        for line in &self.lines {
+            match self.line_coverage(line) {
+                LineCoverage::NotCode => {}
+                LineCoverage::Executed(_) => {
+                    lines_found += 1;
+                    lines_hit += 1;
+                }
+                LineCoverage::Never => lines_found += 1,
+            }
+        }
+        let one_way = self
+            .lines
+            .iter()
+            .filter_map(|line| self.branch(line))
+            .filter(|(taken, not_taken)| *taken == 0 || *not_taken == 0)
+            .count();
+        format!(
+            "Coverage: {} of {} lines executed ({:.1}%), {} branches taken only one way\n",
+            lines_hit,
+            lines_found,
+            100.0 * lines_hit as f64 / lines_found.max(1) as f64,
+            one_way
+        )
+    }
+
+    // The tracefile format of lcov and genhtml, for the assembly source at source_path.
+    pub fn lcov(&self, source_path: &str) -> String {
+        let mut text = format!("TN:\nSF:{}\n", source_path);
+        let (mut lines_found, mut lines_hit) = (0, 0);
+        let (mut branches_found, mut branches_hit) = (0, 0);
+        for line in &self.lines {
+            let count = match self.line_coverage(line) {
+                LineCoverage::NotCode => continue,
+                LineCoverage::Executed(count) => count,
+                LineCoverage::Never => 0,
+            };
+            lines_found += 1;
+            lines_hit += (count > 0) as usize;
+            writeln!(text, "DA:{},{}", line.number, count).unwrap();
+
+            if !self.is_conditional_jump(line) {
+                continue;
+            }
+            let branch = self.branch(line);
+            for (index, outcome) in [branch.map(|b| b.0), branch.map(|b| b.1)]
+                .into_iter()
+                .enumerate()
+            {
+                branches_found += 1;
+                branches_hit += outcome.is_some_and(|count| count > 0) as usize;
+                let outcome = outcome.map_or(String::from("-"), |count| count.to_string());
+                writeln!(text, "BRDA:{},0,{},{}", line.number, index, outcome).unwrap();
+            }
+        }
+        writeln!(text, "BRF:{}\nBRH:{}", branches_found, branches_hit).unwrap();
+        writeln!(text, "LF:{}\nLH:{}", lines_found, lines_hit).unwrap();
+        text + "end_of_record\n"
+    }
+
+    // Jumps that never ran are only known from the source text.
+    fn is_conditional_jump(&self, line: &SourceLine) -> bool {
+        if self.branch(line).is_some() {
+            return true;
+        }
+        let mnemonic = line.mnemonic();
+        (mnemonic.starts_with('j') && mnemonic != "jmp") || mnemonic.starts_with("loop")
+    }
+}
@@ -0,0 +1,176 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+use crate::call_stack::backtrace;
+use crate::constants::CS;
+use crate::interrupts::dos::flush_stdout;
+use crate::machine::Machine;
+use crate::memory::physical_address;
+use crate::snapshot::{load_state, save_state};
+use crate::symbols::Symbols;
+use crate::trace::{RegisterState, TraceOptions, registers};
+
+// Lines of listing shown on each side by list.
+const LIST_CONTEXT: usize = 5;
+
+// A command line debugger reading from stdin. It stops at breakpoints, after steps and after
+// stack or --memcheck problems, shows where execution is in terms of labels and source lines,
+// and takes commands until told to go on. At the end of the input the program runs to completion.
+pub struct Debugger {
+    // Physical addresses, with the text they were given as.
+    breakpoints: Vec<(usize, String)>,
+    stopped: bool,
+    // Instructions left to execute before stopping again.
+    steps: u32,
+    end_of_input: bool,
+    // Problems of the machine already stopped at.
+    problems_seen: u64,
+}
+
+impl Debugger {
+    pub fn new(stop_at_start: bool) -> Debugger {
+        Debugger {
+            breakpoints: Vec::new(),
+            stopped: stop_at_start,
+            steps: 0,
+            end_of_input: false,
+            problems_seen: 0,
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, text: &str, symbols: &Symbols) -> Result<(), String> {
+        let address = symbols
+            .resolve(text)
+            .ok_or_else(|| format!("Unknown location {}", text))?;
+        self.breakpoints.push((address, text.to_string()));
+        Ok(())
+    }
+
+    // Called before every instruction. Returns false when the user quit.
+    pub fn before_step(&mut self, machine: &mut Machine, symbols: &Symbols) -> bool {
+        let address = current_address(machine);
+        if self.end_of_input {
+            return true;
+        }
+        if self.steps > 0 {
+            self.steps -= 1;
+            self.stopped |= self.steps == 0;
+        }
+        if let Some((_, text)) = self.breakpoints.iter().find(|(at, _)| *at == address) {
+            println!("Breakpoint at {}", text);
+            self.stopped = true;
+        }
+        if machine.problem_count() > self.problems_seen {
+            self.problems_seen = machine.problem_count();
+            println!("Stopped after a reported problem");
+            self.stopped = true;
+        }
+        if !self.stopped {
+            return true;
+        }
+
+        flush_stdout();
+        println!("{}", location(machine, symbols));
+        loop {
+            print!("(perf) ");
+            io::stdout().flush().unwrap();
+            let mut line = String::new();
+            if io::stdin().lock().read_line(&mut line).unwrap_or(0) == 0 {
+                println!();
+                self.end_of_input = true;
+                return true;
+            }
+            let mut words = line.split_whitespace();
+            let command = words.next().unwrap_or("");
+            let argument = words.next();
+            match (command, argument) {
+                ("" | "h" | "help", _) => println!(
+                    "break <where>, delete <where>, info, step [n], continue, registers, list, \
+                     backtrace, save <file>, load <file>, quit"
+                ),
+                ("b" | "break", Some(text)) => match self.add_breakpoint(text, symbols) {
+                    Ok(()) => println!("Breakpoint at {}", text),
+                    Err(error) => println!("{}", error),
+                },
+                ("d" | "delete", Some(text)) => match symbols.resolve(text) {
+                    Some(address) => self.breakpoints.retain(|(at, _)| *at != address),
+                    None => println!("Unknown location {}", text),
+                },
+                ("i" | "info", _) => {
+                    for (address, text) in &self.breakpoints {
+                        println!("{:05x}  {}", address, text);
+                    }
+                }
+                ("s" | "step", count) => {
+                    match count.map_or(Ok(1), str::parse) {
+                        Ok(count) if count > 0 => self.steps = count,
+                        _ => {
+                            println!("Expected a number of instructions");
+                            continue;
+                        }
+                    }
+                    self.stopped = false;
+                    return true;
+                }
+                ("c" | "continue", _) => {
+                    self.stopped = false;
+                    return true;
+                }
+                ("r" | "registers", _) => {
+                    let options = TraceOptions {
+                        show_clocks: false,
+                        show_ip: true,
+                    };
+                    let state = RegisterState::capture(machine);
+                    print!("{}", registers(&state, &options));
+                }
+                ("bt" | "backtrace", _) => print!("{}", backtrace(machine, symbols)),
+                ("l" | "list", _) => match symbols.line_at(current_address(machine)) {
+                    Some((number, _)) => print!("{}", symbols.source_around(number, LIST_CONTEXT)),
+                    None => println!("No source for {:05x}", current_address(machine)),
+                },
+                ("save", Some(path)) => {
+                    let result = save_state(machine).and_then(|state| {
+                        fs::write(path, state).map_err(|error| error.to_string())
+                    });
+                    match result {
+                        Ok(()) => println!("Saved the state to {}", path),
+                        Err(error) => println!("{}", error),
+                    }
+                }
+                ("load", Some(path)) => {
+                    let result = fs::read(path)
+                        .map_err(|error| error.to_string())
+                        .and_then(|state| load_state(machine, &state));
+                    match result {
+                        Ok(()) => println!("{}", location(machine, symbols)),
+                        Err(error) => println!("{}", error),
+                    }
+                }
+                ("q" | "quit", _) => return false,
+                (command, _) => println!("Unknown command {}, try help", command),
+            }
+        }
+    }
+}
+
+fn current_address(machine: &Machine) -> usize {
+    physical_address(machine.segment_registers[CS], machine.ip)
+}
+
+// `1000:0106 top+0x3, line 12: add bx, 1` followed by the decoded instruction.
+fn location(machine: &Machine, symbols: &Symbols) -> String {
+    let cs = machine.segment_registers[CS];
+    let address = current_address(machine);
+    let mut text = format!("{:04x}:{:04x}", cs, machine.ip);
+    if let Some(name) = symbols.describe(address) {
+        text += &format!(" {}", name);
+    }
+    if let Some((number, source)) = symbols.line_at(address) {
+        text += &format!(", line {}: {}", number, source);
+    }
+    match machine.decode_at(cs, machine.ip) {
+        Some(instruction) => text + &format!("\n    {}", instruction),
+        None => text,
+    }
+}
@@ -0,0 +1,765 @@
+use std::io::Read;
+
+use crate::constants::{
+    ASCII_ADJUST_INSTRUCTIONS, BOUND_INSTRUCTION, CALL_FAR_INSTRUCTION, CALL_INSTRUCTION, CL, CS,
+    DEC_REGISTER_INSTRUCTION, DX, EFFECTIVE_MEMOERY_ADDRESS, ENTER_INSTRUCTION, ESCAPE_INSTRUCTION,
+    FLAG_INSTRUCTIONS, FPU_MEMORY_INSTRUCTIONS, FPU_NO_OPERAND_INSTRUCTIONS,
+    FPU_REGISTER_INSTRUCTIONS, FpuOperands, HALT_INSTRUCTION,
+    IMMEDIATE_TO_ACCUMULATOR_INSTRUCTIONS, IMMEDIATE_TO_REGISTER_MEMORY_INSTRUCTION,
+    IMMEDIATE_TO_REGISTER_MEMORY_INSTRUCTION_MOV, IMMEDIATE_TO_REGISTER_MEMORY_INSTRUCTIONS,
+    IMUL_IMMEDIATE_INSTRUCTION, IN_OUT_DX_INSTRUCTION, IN_OUT_IMMEDIATE_INSTRUCTION,
+    INC_REGISTER_INSTRUCTION, INDIRECT_BYTE_INSTRUCTION, INDIRECT_INSTRUCTION,
+    INDIRECT_INSTRUCTIONS, INTERRUPT_3_INSTRUCTION, INTERRUPT_INSTRUCTION,
+    INTERRUPT_ON_OVERFLOW_INSTRUCTION, INTERRUPT_RETURN_INSTRUCTION, JUMP_ALIAS_INSTRUCTION,
+    JUMP_FAR_INSTRUCTION, JUMP_INSTRUCTION, JUMP_SHORT_INSTRUCTION, LDS_INSTRUCTION,
+    LEA_INSTRUCTION, LES_INSTRUCTION, MOVE_ACCUMULATOR_MEMORY_INSTRUCTION,
+    MOVE_FROM_SEGMENT_REGISTER_INSTRUCTION, MOVE_IMMEDIATE_TO_REGISTER_INSTRUCTION,
+    MOVE_TO_SEGMENT_REGISTER_INSTRUCTION, NO_OPERAND_80186_INSTRUCTIONS, NO_OPERAND_INSTRUCTIONS,
+    POP_CS_INSTRUCTION, POP_FLAGS_INSTRUCTION, POP_MEMORY_INSTRUCTION, POP_REGISTER_INSTRUCTION,
+    POP_SEGMENT_INSTRUCTION, PUSH_FLAGS_INSTRUCTION, PUSH_IMMEDIATE_INSTRUCTION,
+    PUSH_REGISTER_INSTRUCTION, PUSH_SEGMENT_INSTRUCTION,
+    REGISTER_MEMORY_TO_REGISTER_MEMORY_INSTRUCTIONS, REGISTER_NAMES, REPEAT_PREFIX,
+    RETURN_FAR_INSTRUCTION, RETURN_INSTRUCTIONS, RETURN_NEAR_INSTRUCTION, SALC_INSTRUCTION,
+    SEGMENT_OVERRIDE_PREFIX, SEGMENT_REGISTER_NAMES, SET_MINUS_ONE_INSTRUCTIONS,
+    SHIFT_IMMEDIATE_INSTRUCTION, SHIFT_INSTRUCTION, SHIFT_INSTRUCTIONS, STRING_INSTRUCTIONS,
+    TEST_INSTRUCTION, UNARY_INSTRUCTION, UNARY_INSTRUCTIONS, WAIT_INSTRUCTION,
+    XCHG_ACCUMULATOR_INSTRUCTION, XCHG_INSTRUCTION,
+};
+use crate::formatter::{FormatOptions, immediate};
+use crate::instruction::Instruction;
+use crate::machine::Cpu;
+use crate::rm::Rm;
+
+// The longest 8086 instruction is 6 bytes, plus a segment override and a repeat prefix.
+pub const MAX_INSTRUCTION_LENGTH: usize = 8;
+
+pub fn decode(bytes: &[u8], cpu: Cpu) -> Option<Instruction> {
+    let mut file = bytes;
+    let mut instruction = decode_instruction(&mut file, cpu)?;
+    instruction.length = (bytes.len() - file.len()) as u16;
+    Some(instruction)
+}
+
+fn decode_instruction(file: &mut &[u8], cpu: Cpu) -> Option<Instruction> {
+    let mut segment_override = None;
+    let mut repeat_while_zero = None;
+    let mut current_byte = read_byte(file)?;
+    while is_prefix(current_byte) {
+        if SEGMENT_OVERRIDE_PREFIX == current_byte & 0b11100111 {
+            segment_override = Some(((current_byte & 0b11000) >> 3) as usize);
+        } else {
+            repeat_while_zero = Some(current_byte & 0b1 != 0);
+        }
+        current_byte = read_byte(file)?;
+    }
+    let mut instruction = Instruction {
+        name: "",
+        w: 1,
+        destination: None,
+        source: None,
+        immediate_value: None,
+        second_immediate: None,
+        signed_immediate: false,
+        accumulator_form: false,
+        segment_override: None,
+        repeat: None,
+        memory_size: None,
+        undocumented: false,
+        far: false,
+        length: 0,
+    };
+
+    if MOVE_IMMEDIATE_TO_REGISTER_INSTRUCTION == current_byte & 0b11110000 {
+        let w = ((0b1000 & current_byte) >> 3) as usize;
+        let reg = 0b111 & current_byte as usize;
+
+        instruction.name = "mov";
+        instruction.w = w;
+        instruction.destination = Some(Rm::Reg { w, reg });
+        instruction.immediate_value = Some(read_date(file, w == 0)?);
+    } else if IMMEDIATE_TO_REGISTER_MEMORY_INSTRUCTION == current_byte & 0b11111100 {
+        let next_byte = read_byte(file)?;
+
+        let mod_value = (0b11000000 & next_byte) >> 6;
+        let w = (0b1 & current_byte) as usize;
+        let one_byte = (current_byte & 0b11) != 0b01;
+        let rm = Rm::new(file, mod_value, w, (0b111 & next_byte) as usize)?;
+        let operation_index = ((next_byte & 0b111000) >> 3) as usize;
+
+        instruction.name = IMMEDIATE_TO_REGISTER_MEMORY_INSTRUCTIONS[operation_index];
+        instruction.w = w;
+        instruction.destination = Some(rm);
+        instruction.immediate_value = Some(read_date(file, one_byte)?);
+        instruction.signed_immediate = w == 1 && one_byte;
+    } else if IMMEDIATE_TO_REGISTER_MEMORY_INSTRUCTION_MOV == current_byte & 0b11111110 {
+        let next_byte = read_byte(file)?;
+
+        let mod_value = (0b11000000 & next_byte) >> 6;
+        let w = (current_byte & 0b1) as usize;
+        let rm = Rm::new(file, mod_value, w, (0b111 & next_byte) as usize)?;
+
+        instruction.name = "mov";
+        instruction.w = w;
+        instruction.destination = Some(rm);
+        instruction.immediate_value = Some(read_date(file, w != 1)?);
+    } else if let Some(operation) = REGISTER_MEMORY_TO_REGISTER_MEMORY_INSTRUCTIONS
+        .iter()
+        .find(|i| i.0 == current_byte & 0b11111100)
+    {
+        let next_byte = read_byte(file)?;
+
+        let w = 0b00000001 & current_byte as usize;
+        let d = (0b00000010 & current_byte) >> 1;
+
+        let mod_value = (0b11000000 & next_byte) >> 6;
+        let reg = Rm::Reg {
+            w,
+            reg: ((0b111000 & next_byte) >> 3) as usize,
+        };
+        let rm = Rm::new(file, mod_value, w, 0b111 & next_byte as usize)?;
+
+        let (source, destination) = if d == 0 { (reg, rm) } else { (rm, reg) };
+
+        instruction.name = operation.1;
+        instruction.w = w;
+        instruction.destination = Some(destination);
+        instruction.source = Some(source);
+    } else if TEST_INSTRUCTION == current_byte & 0b11111110
+        || XCHG_INSTRUCTION == current_byte & 0b11111110
+    {
+        let next_byte = read_byte(file)?;
+
+        let w = (current_byte & 0b1) as usize;
+        let mod_value = (0b11000000 & next_byte) >> 6;
+        let reg = Rm::Reg {
+            w,
+            reg: ((0b111000 & next_byte) >> 3) as usize,
+        };
+        let rm = Rm::new(file, mod_value, w, (0b111 & next_byte) as usize)?;
+
+        // Like ndisasm, xchg names the register first.
+        instruction.w = w;
+        if TEST_INSTRUCTION == current_byte & 0b11111110 {
+            instruction.name = "test";
+            instruction.destination = Some(rm);
+            instruction.source = Some(reg);
+        } else {
+            instruction.name = "xchg";
+            instruction.destination = Some(reg);
+            instruction.source = Some(rm);
+        }
+    } else if XCHG_ACCUMULATOR_INSTRUCTION == current_byte & 0b11111000 {
+        let reg = (current_byte & 0b111) as usize;
+        if reg == 0 {
+            instruction.name = "nop";
+        } else {
+            instruction.name = "xchg";
+            instruction.destination = Some(Rm::Reg { w: 1, reg: 0 });
+            instruction.source = Some(Rm::Reg { w: 1, reg });
+        }
+    } else if current_byte == LEA_INSTRUCTION
+        || current_byte == LES_INSTRUCTION
+        || current_byte == LDS_INSTRUCTION
+    {
+        let next_byte = read_byte(file)?;
+
+        let mod_value = (0b11000000 & next_byte) >> 6;
+        // The source is an address, or a far pointer for lds and les.
+        if mod_value == 0b11 {
+            return None;
+        }
+        let reg = ((0b111000 & next_byte) >> 3) as usize;
+        let rm = Rm::new(file, mod_value, 1, (0b111 & next_byte) as usize)?;
+
+        instruction.name = match current_byte {
+            LEA_INSTRUCTION => "lea",
+            LES_INSTRUCTION => "les",
+            _ => "lds",
+        };
+        instruction.destination = Some(Rm::Reg { w: 1, reg });
+        instruction.source = Some(rm);
+    } else if MOVE_ACCUMULATOR_MEMORY_INSTRUCTION == current_byte & 0b11111100 {
+        let w = (current_byte & 0b1) as usize;
+        let accumulator = Rm::Reg { w, reg: 0 };
+        let memory = Rm::DirectMemory(read_date(file, false)? as u16);
+
+        let (source, destination) = if current_byte & 0b10 == 0 {
+            (memory, accumulator)
+        } else {
+            (accumulator, memory)
+        };
+
+        instruction.name = "mov";
+        instruction.w = w;
+        instruction.destination = Some(destination);
+        instruction.source = Some(source);
+        instruction.accumulator_form = true;
+    } else if current_byte == MOVE_TO_SEGMENT_REGISTER_INSTRUCTION
+        || current_byte == MOVE_FROM_SEGMENT_REGISTER_INSTRUCTION
+    {
+        let next_byte = read_byte(file)?;
+
+        let mod_value = (0b11000000 & next_byte) >> 6;
+        let segment_register = Rm::SegmentReg(((0b11000 & next_byte) >> 3) as usize);
+        let rm = Rm::new(file, mod_value, 1, 0b111 & next_byte as usize)?;
+
+        let (source, destination) = if current_byte == MOVE_TO_SEGMENT_REGISTER_INSTRUCTION {
+            (rm, segment_register)
+        } else {
+            (segment_register, rm)
+        };
+
+        instruction.name = "mov";
+        instruction.destination = Some(destination);
+        instruction.source = Some(source);
+    } else if let Some(operation) = IMMEDIATE_TO_ACCUMULATOR_INSTRUCTIONS
+        .iter()
+        .find(|i| i.0 == current_byte & 0b11111110)
+    {
+        let w = (0b1 & current_byte) as usize;
+
+        instruction.name = operation.1;
+        instruction.w = w;
+        instruction.destination = Some(Rm::Reg { w, reg: 0 });
+        instruction.immediate_value = Some(read_date(file, w == 0)?);
+    } else if let Some(operation) = RETURN_INSTRUCTIONS.iter().find(|i| i.0 == current_byte) {
+        instruction.name = operation.1;
+        instruction.immediate_value = Some(read_date(file, true)?);
+    } else if INC_REGISTER_INSTRUCTION == current_byte & 0b11111000
+        || DEC_REGISTER_INSTRUCTION == current_byte & 0b11111000
+    {
+        instruction.name = if INC_REGISTER_INSTRUCTION == current_byte & 0b11111000 {
+            "inc"
+        } else {
+            "dec"
+        };
+        instruction.destination = Some(Rm::Reg {
+            w: 1,
+            reg: (current_byte & 0b111) as usize,
+        });
+    } else if IN_OUT_IMMEDIATE_INSTRUCTION == current_byte & 0b11110100 {
+        let w = (current_byte & 0b1) as usize;
+        let accumulator = Rm::Reg { w, reg: 0 };
+        let out = current_byte & 0b10 != 0;
+
+        instruction.name = if out { "out" } else { "in" };
+        instruction.w = w;
+        // out to an immediate port has the accumulator as its only register operand.
+        if IN_OUT_DX_INSTRUCTION == current_byte & 0b11111100 {
+            let port = Rm::Reg { w: 1, reg: DX };
+            let (source, destination) = if out {
+                (accumulator, port)
+            } else {
+                (port, accumulator)
+            };
+            instruction.destination = Some(destination);
+            instruction.source = Some(source);
+        } else {
+            if out {
+                instruction.source = Some(accumulator);
+            } else {
+                instruction.destination = Some(accumulator);
+            }
+            instruction.immediate_value = Some(read_byte(file)? as i16);
+        }
+    } else if let Some(operation) = NO_OPERAND_INSTRUCTIONS.iter().find(|i| i.0 == current_byte) {
+        instruction.name = operation.1;
+    } else if let Some(operation) = ASCII_ADJUST_INSTRUCTIONS
+        .iter()
+        .find(|i| i.0 == current_byte)
+    {
+        instruction.name = operation.1;
+        instruction.immediate_value = Some(read_byte(file)? as i16);
+    } else if let Some(operation) = STRING_INSTRUCTIONS.iter().find(|i| i.0 == current_byte) {
+        instruction.name = operation.1;
+        instruction.w = (current_byte & 0b1) as usize;
+    } else if !cpu.has_80186_instructions()
+        && JUMP_ALIAS_INSTRUCTION == current_byte & 0b11110000
+        && let Some(operation) = RETURN_INSTRUCTIONS
+            .iter()
+            .find(|i| i.0 == current_byte | 0b00010000)
+    {
+        instruction.name = operation.1;
+        instruction.immediate_value = Some(read_date(file, true)?);
+        instruction.undocumented = true;
+    } else if !cpu.has_80186_instructions() && current_byte == POP_CS_INSTRUCTION {
+        instruction.name = "pop";
+        instruction.destination = Some(Rm::SegmentReg(CS));
+        instruction.undocumented = true;
+    } else if current_byte == SALC_INSTRUCTION {
+        instruction.name = "salc";
+        instruction.undocumented = true;
+    } else if current_byte == INTERRUPT_INSTRUCTION {
+        instruction.name = "int";
+        instruction.immediate_value = Some(read_byte(file)? as i16);
+    } else if current_byte == INTERRUPT_3_INSTRUCTION {
+        instruction.name = "int3";
+    } else if current_byte == INTERRUPT_ON_OVERFLOW_INSTRUCTION {
+        instruction.name = "into";
+    } else if current_byte == INTERRUPT_RETURN_INSTRUCTION {
+        instruction.name = "iret";
+    } else if current_byte == HALT_INSTRUCTION {
+        instruction.name = "hlt";
+    } else if current_byte == CALL_INSTRUCTION || current_byte == JUMP_INSTRUCTION {
+        instruction.name = if current_byte == CALL_INSTRUCTION {
+            "call"
+        } else {
+            "jmp"
+        };
+        instruction.immediate_value = Some(read_date(file, false)?);
+    } else if current_byte == JUMP_SHORT_INSTRUCTION {
+        instruction.name = "jmp";
+        instruction.immediate_value = Some(read_date(file, true)?);
+    } else if current_byte == CALL_FAR_INSTRUCTION || current_byte == JUMP_FAR_INSTRUCTION {
+        instruction.name = if current_byte == CALL_FAR_INSTRUCTION {
+            "call"
+        } else {
+            "jmp"
+        };
+        instruction.immediate_value = Some(read_date(file, false)?);
+        instruction.second_immediate = Some(read_date(file, false)?);
+        instruction.far = true;
+    } else if RETURN_NEAR_INSTRUCTION == current_byte & 0b11111110
+        || RETURN_FAR_INSTRUCTION == current_byte & 0b11111110
+    {
+        instruction.far = RETURN_FAR_INSTRUCTION == current_byte & 0b11111110;
+        instruction.name = if instruction.far { "retf" } else { "ret" };
+        if current_byte & 0b1 == 0 {
+            instruction.immediate_value = Some(read_date(file, false)?);
+        }
+    } else if PUSH_REGISTER_INSTRUCTION == current_byte & 0b11111000
+        || POP_REGISTER_INSTRUCTION == current_byte & 0b11111000
+    {
+        instruction.name = if PUSH_REGISTER_INSTRUCTION == current_byte & 0b11111000 {
+            "push"
+        } else {
+            "pop"
+        };
+        instruction.destination = Some(Rm::Reg {
+            w: 1,
+            reg: (current_byte & 0b111) as usize,
+        });
+    } else if PUSH_SEGMENT_INSTRUCTION == current_byte & 0b11100111
+        || (POP_SEGMENT_INSTRUCTION == current_byte & 0b11100111
+            && current_byte != POP_CS_INSTRUCTION)
+    {
+        instruction.name = if PUSH_SEGMENT_INSTRUCTION == current_byte & 0b11100111 {
+            "push"
+        } else {
+            "pop"
+        };
+        instruction.destination = Some(Rm::SegmentReg(((current_byte & 0b11000) >> 3) as usize));
+    } else if current_byte == PUSH_FLAGS_INSTRUCTION {
+        instruction.name = "pushf";
+    } else if current_byte == POP_FLAGS_INSTRUCTION {
+        instruction.name = "popf";
+    } else if current_byte == INDIRECT_INSTRUCTION
+        || current_byte == INDIRECT_BYTE_INSTRUCTION
+        || current_byte == POP_MEMORY_INSTRUCTION
+    {
+        let next_byte = read_byte(file)?;
+
+        let mod_value = (0b11000000 & next_byte) >> 6;
+        let w = (current_byte & 0b1) as usize;
+        let operation_index = ((next_byte & 0b111000) >> 3) as usize;
+        let name = match current_byte {
+            POP_MEMORY_INSTRUCTION if operation_index == 0 => "pop",
+            POP_MEMORY_INSTRUCTION => "",
+            INDIRECT_BYTE_INSTRUCTION if operation_index > 1 => "",
+            _ => INDIRECT_INSTRUCTIONS[operation_index],
+        };
+        let far = current_byte == INDIRECT_INSTRUCTION && matches!(operation_index, 3 | 5);
+        // A far pointer can't be held in a register.
+        if name.is_empty() || (far && mod_value == 0b11) {
+            return None;
+        }
+
+        instruction.name = name;
+        instruction.w = w;
+        instruction.destination = Some(Rm::new(file, mod_value, w, (0b111 & next_byte) as usize)?);
+        instruction.far = far;
+    } else if let Some(operation) = FLAG_INSTRUCTIONS.iter().find(|i| i.0 == current_byte) {
+        instruction.name = operation.1;
+    } else if UNARY_INSTRUCTION == current_byte & 0b11111110 {
+        let next_byte = read_byte(file)?;
+
+        let mod_value = (0b11000000 & next_byte) >> 6;
+        let w = (current_byte & 0b1) as usize;
+        let rm = Rm::new(file, mod_value, w, (0b111 & next_byte) as usize)?;
+        let operation_index = ((next_byte & 0b111000) >> 3) as usize;
+
+        instruction.name = UNARY_INSTRUCTIONS[operation_index];
+        instruction.w = w;
+        instruction.destination = Some(rm);
+        if operation_index < 2 {
+            instruction.immediate_value = Some(read_date(file, w == 0)?);
+        }
+        instruction.undocumented = operation_index == 1;
+    } else if SHIFT_INSTRUCTION == current_byte & 0b11111100 {
+        let next_byte = read_byte(file)?;
+
+        let mod_value = (0b11000000 & next_byte) >> 6;
+        let w = (current_byte & 0b1) as usize;
+        let by_cl = current_byte & 0b10 != 0;
+        let rm = Rm::new(file, mod_value, w, (0b111 & next_byte) as usize)?;
+        let operation_index = ((next_byte & 0b111000) >> 3) as usize;
+
+        instruction.name = SHIFT_INSTRUCTIONS[operation_index];
+        if instruction.name.is_empty() {
+            if cpu.has_80186_instructions() {
+                return None;
+            }
+            instruction.name = SET_MINUS_ONE_INSTRUCTIONS[by_cl as usize];
+            instruction.undocumented = true;
+        }
+        instruction.w = w;
+        instruction.destination = Some(rm);
+        if by_cl {
+            instruction.source = Some(Rm::Reg { w: 0, reg: CL });
+        } else {
+            instruction.immediate_value = Some(1);
+        }
+    } else if ESCAPE_INSTRUCTION == current_byte & 0b11111000 {
+        let next_byte = read_byte(file)?;
+
+        let mod_value = (0b11000000 & next_byte) >> 6;
+        let reg = (next_byte & 0b111000) >> 3;
+        let rm = (0b111 & next_byte) as usize;
+
+        if mod_value != 0b11 {
+            let (name, size) =
+                FPU_MEMORY_INSTRUCTIONS[(current_byte & 0b111) as usize][reg as usize];
+            if name.is_empty() {
+                return None;
+            }
+
+            instruction.name = name;
+            instruction.destination = Some(Rm::new(file, mod_value, 1, rm)?);
+            instruction.memory_size = Some(size);
+        } else if let Some(operation) = FPU_NO_OPERAND_INSTRUCTIONS
+            .iter()
+            .find(|i| i.0 == current_byte && i.1 == next_byte)
+        {
+            instruction.name = operation.2;
+        } else {
+            let operation = FPU_REGISTER_INSTRUCTIONS
+                .iter()
+                .find(|i| i.0 == current_byte && i.1 == reg)?;
+            let (destination, source) = match operation.3 {
+                FpuOperands::Register => (Rm::FpuReg(rm), None),
+                FpuOperands::FromRegister => (Rm::FpuReg(0), Some(Rm::FpuReg(rm))),
+                FpuOperands::ToRegister => (Rm::FpuReg(rm), Some(Rm::FpuReg(0))),
+            };
+
+            instruction.name = operation.2;
+            instruction.destination = Some(destination);
+            instruction.source = source;
+        }
+    } else if current_byte == WAIT_INSTRUCTION {
+        instruction.name = "wait";
+    } else if cpu.has_80186_instructions()
+        && PUSH_IMMEDIATE_INSTRUCTION == current_byte & 0b11111101
+    {
+        let one_byte = current_byte & 0b10 != 0;
+
+        instruction.name = "push";
+        instruction.immediate_value = Some(read_date(file, one_byte)?);
+        instruction.signed_immediate = one_byte;
+    } else if cpu.has_80186_instructions()
+        && IMUL_IMMEDIATE_INSTRUCTION == current_byte & 0b11111101
+    {
+        let next_byte = read_byte(file)?;
+
+        let mod_value = (0b11000000 & next_byte) >> 6;
+        let reg = ((0b111000 & next_byte) >> 3) as usize;
+        let rm = Rm::new(file, mod_value, 1, (0b111 & next_byte) as usize)?;
+        let one_byte = current_byte & 0b10 != 0;
+
+        instruction.name = "imul";
+        instruction.destination = Some(Rm::Reg { w: 1, reg });
+        instruction.source = Some(rm);
+        instruction.immediate_value = Some(read_date(file, one_byte)?);
+        instruction.signed_immediate = one_byte;
+    } else if cpu.has_80186_instructions() && current_byte == BOUND_INSTRUCTION {
+        let next_byte = read_byte(file)?;
+
+        let mod_value = (0b11000000 & next_byte) >> 6;
+        // The bounds are a pair of words in memory.
+        if mod_value == 0b11 {
+            return None;
+        }
+        let reg = ((0b111000 & next_byte) >> 3) as usize;
+        let rm = Rm::new(file, mod_value, 1, (0b111 & next_byte) as usize)?;
+
+        instruction.name = "bound";
+        instruction.destination = Some(Rm::Reg { w: 1, reg });
+        instruction.source = Some(rm);
+    } else if cpu.has_80186_instructions() && current_byte == ENTER_INSTRUCTION {
+        instruction.name = "enter";
+        instruction.immediate_value = Some(read_date(file, false)?);
+        instruction.second_immediate = Some(read_byte(file)? as i16);
+    } else if cpu.has_80186_instructions()
+        && SHIFT_IMMEDIATE_INSTRUCTION == current_byte & 0b11111110
+    {
+        let next_byte = read_byte(file)?;
+
+        let mod_value = (0b11000000 & next_byte) >> 6;
+        let w = (current_byte & 0b1) as usize;
+        let rm = Rm::new(file, mod_value, w, (0b111 & next_byte) as usize)?;
+        let name = SHIFT_INSTRUCTIONS[((next_byte & 0b111000) >> 3) as usize];
+        if name.is_empty() {
+            return None;
+        }
+
+        instruction.name = name;
+        instruction.w = w;
+        instruction.destination = Some(rm);
+        instruction.immediate_value = Some(read_byte(file)? as i16);
+    } else if cpu.has_80186_instructions()
+        && let Some(operation) = NO_OPERAND_80186_INSTRUCTIONS
+            .iter()
+            .find(|i| i.0 == current_byte)
+    {
+        instruction.name = operation.1;
+        if matches!(operation.1, "insb" | "insw" | "outsb" | "outsw") {
+            instruction.w = (current_byte & 0b1) as usize;
+        }
+    } else {
+        return None;
+    }
+
+    instruction.segment_override = segment_override;
+    instruction.repeat = repeat_while_zero.map(|zero| match instruction.name {
+        _ if !zero => "repne",
+        "cmpsb" | "cmpsw" | "scasb" | "scasw" => "repe",
+        _ => "rep",
+    });
+    Some(instruction)
+}
+
+// What the reg field of a mod-reg-r/m byte selects.
+enum RegField {
+    Register,
+    SegmentRegister,
+    Operation(&'static [&'static str; 8]),
+    Unused,
+    // The 8087 opcode bits, with st(i) in r/m for register forms.
+    Escape,
+}
+
+// Breaks the opcode and mod-reg-r/m bytes of a decoded instruction into their fields,
+// for example `100010 d=1 w=1 | mod=11 reg=011(bx) r/m=001(cx)`.
+pub fn explain(bytes: &[u8], instruction: &Instruction) -> String {
+    let prefixes: Vec<String> = bytes
+        .iter()
+        .take_while(|byte| is_prefix(**byte))
+        .map(|byte| {
+            if SEGMENT_OVERRIDE_PREFIX == byte & 0b11100111 {
+                let sr = ((byte & 0b11000) >> 3) as usize;
+                format!("001 sr={:02b}({}) 110 | ", sr, SEGMENT_REGISTER_NAMES[sr])
+            } else {
+                format!("1111001 z={} | ", byte & 0b1)
+            }
+        })
+        .collect();
+    let bytes = &bytes[prefixes.len()..];
+    let opcode = bytes[0];
+    let w = (opcode & 0b1) as usize;
+
+    let (text, reg_field) = if MOVE_IMMEDIATE_TO_REGISTER_INSTRUCTION == opcode & 0b11110000 {
+        let w = ((opcode & 0b1000) >> 3) as usize;
+        let reg = (opcode & 0b111) as usize;
+        let text = format!("1011 w={} reg={:03b}({})", w, reg, REGISTER_NAMES[w][reg]);
+        (text, None)
+    } else if IMMEDIATE_TO_REGISTER_MEMORY_INSTRUCTION == opcode & 0b11111100 {
+        let text = format!("100000 s={} w={}", (opcode & 0b10) >> 1, w);
+        let operations = &IMMEDIATE_TO_REGISTER_MEMORY_INSTRUCTIONS;
+        (text, Some(RegField::Operation(operations)))
+    } else if IMMEDIATE_TO_REGISTER_MEMORY_INSTRUCTION_MOV == opcode & 0b11111110 {
+        (format!("1100011 w={}", w), Some(RegField::Unused))
+    } else if REGISTER_MEMORY_TO_REGISTER_MEMORY_INSTRUCTIONS
+        .iter()
+        .any(|i| i.0 == opcode & 0b11111100)
+    {
+        let text = format!("{:06b} d={} w={}", opcode >> 2, (opcode & 0b10) >> 1, w);
+        (text, Some(RegField::Register))
+    } else if TEST_INSTRUCTION == opcode & 0b11111110 || XCHG_INSTRUCTION == opcode & 0b11111110 {
+        (
+            format!("{:07b} w={}", opcode >> 1, w),
+            Some(RegField::Register),
+        )
+    } else if opcode == LEA_INSTRUCTION || opcode == LES_INSTRUCTION || opcode == LDS_INSTRUCTION {
+        (format!("{:08b}", opcode), Some(RegField::Register))
+    } else if MOVE_ACCUMULATOR_MEMORY_INSTRUCTION == opcode & 0b11111100 {
+        let text = format!("101000 d={} w={}", (opcode & 0b10) >> 1, w);
+        (text, None)
+    } else if opcode == MOVE_TO_SEGMENT_REGISTER_INSTRUCTION
+        || opcode == MOVE_FROM_SEGMENT_REGISTER_INSTRUCTION
+    {
+        (format!("{:08b}", opcode), Some(RegField::SegmentRegister))
+    } else if IMMEDIATE_TO_ACCUMULATOR_INSTRUCTIONS
+        .iter()
+        .any(|i| i.0 == opcode & 0b11111110)
+    {
+        (format!("{:07b} w={}", opcode >> 1, w), None)
+    } else if UNARY_INSTRUCTION == opcode & 0b11111110 {
+        let text = format!("1111011 w={}", w);
+        (text, Some(RegField::Operation(&UNARY_INSTRUCTIONS)))
+    } else if SHIFT_IMMEDIATE_INSTRUCTION == opcode & 0b11111110
+        && SHIFT_INSTRUCTIONS.contains(&instruction.name)
+    {
+        let text = format!("1100000 w={}", w);
+        (text, Some(RegField::Operation(&SHIFT_INSTRUCTIONS)))
+    } else if SHIFT_INSTRUCTION == opcode & 0b11111100 {
+        let text = format!("110100 v={} w={}", (opcode & 0b10) >> 1, w);
+        (text, Some(RegField::Operation(&SHIFT_INSTRUCTIONS)))
+    } else if IMUL_IMMEDIATE_INSTRUCTION == opcode & 0b11111101 && instruction.name == "imul" {
+        let text = format!("011010 s={} 1", (opcode & 0b10) >> 1);
+        (text, Some(RegField::Register))
+    } else if opcode == BOUND_INSTRUCTION && instruction.name == "bound" {
+        (format!("{:08b}", opcode), Some(RegField::Register))
+    } else if opcode == INDIRECT_INSTRUCTION || opcode == INDIRECT_BYTE_INSTRUCTION {
+        let text = format!("1111111 w={}", w);
+        (text, Some(RegField::Operation(&INDIRECT_INSTRUCTIONS)))
+    } else if opcode == POP_MEMORY_INSTRUCTION {
+        (format!("{:08b}", opcode), Some(RegField::Unused))
+    } else if INC_REGISTER_INSTRUCTION == opcode & 0b11110000
+        || XCHG_ACCUMULATOR_INSTRUCTION == opcode & 0b11111000
+    {
+        let reg = (opcode & 0b111) as usize;
+        let text = format!(
+            "{:05b} reg={:03b}({})",
+            opcode >> 3,
+            reg,
+            REGISTER_NAMES[1][reg]
+        );
+        (text, None)
+    } else if let Some(Rm::Reg { reg, .. }) = instruction.destination
+        && matches!(instruction.name, "push" | "pop")
+    {
+        let text = format!(
+            "{:05b} reg={:03b}({})",
+            opcode >> 3,
+            reg,
+            REGISTER_NAMES[1][reg]
+        );
+        (text, None)
+    } else if let Some(Rm::SegmentReg(reg)) = instruction.destination
+        && matches!(instruction.name, "push" | "pop")
+    {
+        let text = format!(
+            "000 sr={:02b}({}) {:03b}",
+            reg,
+            SEGMENT_REGISTER_NAMES[reg],
+            opcode & 0b111
+        );
+        (text, None)
+    } else if ESCAPE_INSTRUCTION == opcode & 0b11111000 {
+        let text = format!("11011 esc={:03b}", opcode & 0b111);
+        (text, Some(RegField::Escape))
+    } else {
+        (format!("{:08b}", opcode), None)
+    };
+    let mut text = prefixes.concat() + &text;
+
+    if let Some(reg_field) = reg_field {
+        let modrm = bytes[1];
+        let mod_value = modrm >> 6;
+        let reg = ((modrm & 0b111000) >> 3) as usize;
+        let rm = (modrm & 0b111) as usize;
+        let escape = matches!(reg_field, RegField::Escape);
+        // Segment register moves, bound and the address loads always work on words.
+        let words = matches!(instruction.name, "bound" | "lea" | "lds" | "les");
+        let w = if matches!(reg_field, RegField::SegmentRegister) || words {
+            1
+        } else {
+            w
+        };
+
+        let reg_text = match reg_field {
+            RegField::Register => format!("reg={:03b}({})", reg, REGISTER_NAMES[w][reg]),
+            RegField::SegmentRegister => {
+                format!(
+                    "sr={:02b}({})",
+                    reg & 0b11,
+                    SEGMENT_REGISTER_NAMES[reg & 0b11]
+                )
+            }
+            // The gap in the shift operations is setmo or setmoc.
+            RegField::Operation(names) if names[reg].is_empty() => {
+                format!("op={:03b}({})", reg, instruction.name)
+            }
+            RegField::Operation(names) => format!("op={:03b}({})", reg, names[reg]),
+            RegField::Unused => format!("{:03b}", reg),
+            RegField::Escape => format!("op={:03b}", reg),
+        };
+        let rm_name = if mod_value == 0b11 && escape {
+            format!("st{}", rm)
+        } else if mod_value == 0b11 {
+            String::from(REGISTER_NAMES[w][rm])
+        } else if mod_value == 0b00 && rm == 0b110 {
+            String::from("direct")
+        } else {
+            String::from(EFFECTIVE_MEMOERY_ADDRESS[rm])
+        };
+        text += &format!(
+            " | mod={:02b} {} r/m={:03b}({})",
+            mod_value, reg_text, rm, rm_name
+        );
+    }
+
+    let mut operands = Vec::new();
+    match instruction.memory_operand() {
+        Some(Rm::DirectMemory(address)) => operands.push(format!("disp={}", address)),
+        Some(Rm::MemoryWithDisplacment { displacment, .. }) => {
+            operands.push(format!("disp={}", *displacment as i16))
+        }
+        _ => {}
+    }
+    // A shift by 1 has no data byte, the count is implied by the opcode.
+    if let Some(immediate_value) = instruction.immediate_value
+        && SHIFT_INSTRUCTION != opcode & 0b11111100
+    {
+        if instruction.is_relative() {
+            operands.push(format!("ip-inc={}", immediate_value));
+        } else if instruction.far
+            && let Some(segment) = instruction.second_immediate
+        {
+            operands.push(format!(
+                "ip={:#x} cs={:#x}",
+                immediate_value as u16, segment as u16
+            ));
+        } else {
+            operands.push(format!(
+                "data={}",
+                immediate(instruction, immediate_value, &FormatOptions::default())
+            ));
+        }
+    }
+    if !operands.is_empty() {
+        text += &format!(" | {}", operands.join(" "));
+    }
+    text
+}
+
+pub fn is_prefix(byte: u8) -> bool {
+    SEGMENT_OVERRIDE_PREFIX == byte & 0b11100111 || REPEAT_PREFIX == byte & 0b11111110
+}
+
+fn read_byte(file: &mut impl Read) -> Option<u8> {
+    let mut data = [0u8];
+    file.read_exact(&mut data).ok()?;
+    Some(data[0])
+}
+
+fn read_date(file: &mut impl Read, one_byte: bool) -> Option<i16> {
+    if one_byte {
+        let mut data = [0u8];
+        file.read_exact(&mut data).ok()?;
+        Some((data[0] as i8) as i16)
+    } else {
+        let mut data = [0u8, 0u8];
+        file.read_exact(&mut data).ok()?;
+        Some(((data[1] as i16) << 8) | data[0] as i16)
+    }
+}
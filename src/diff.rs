@@ -0,0 +1,142 @@
+use std::fmt::Write;
+
+// Lines of unchanged context shown around each change.
+const CONTEXT: usize = 3;
+// Past this many cells the middle of two texts is shown as replaced wholesale instead of
+// searching for the smallest edit.
+const MAX_TABLE: usize = 25_000_000;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Edit {
+    Keep,
+    Remove,
+    Add,
+}
+
+// A unified diff of two texts, empty when they are the same.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let edits = edits(&old, &new);
+    if edits.iter().all(|edit| *edit == Edit::Keep) {
+        return String::new();
+    }
+
+    // Line numbers in both texts before every edit.
+    let mut positions = Vec::with_capacity(edits.len());
+    let (mut old_line, mut new_line) = (0, 0);
+    for edit in &edits {
+        positions.push((old_line, new_line));
+        match edit {
+            Edit::Keep => {
+                old_line += 1;
+                new_line += 1;
+            }
+            Edit::Remove => old_line += 1,
+            Edit::Add => new_line += 1,
+        }
+    }
+
+    let mut text = format!("--- {}\n+++ {}\n", old_name, new_name);
+    let mut index = 0;
+    while let Some(change) = (index..edits.len()).find(|i| edits[*i] != Edit::Keep) {
+        // A hunk runs until more than twice the context of unchanged lines.
+        let start = change.saturating_sub(CONTEXT);
+        let mut end = change;
+        let mut unchanged = 0;
+        while end < edits.len() && unchanged <= 2 * CONTEXT {
+            unchanged = if edits[end] == Edit::Keep {
+                unchanged + 1
+            } else {
+                0
+            };
+            end += 1;
+        }
+        let end = end - unchanged.saturating_sub(CONTEXT);
+
+        let (old_start, new_start) = positions[start];
+        let count = |kind: Edit| {
+            edits[start..end]
+                .iter()
+                .filter(|edit| **edit == Edit::Keep || **edit == kind)
+                .count()
+        };
+        writeln!(
+            text,
+            "@@ -{},{} +{},{} @@",
+            old_start + 1,
+            count(Edit::Remove),
+            new_start + 1,
+            count(Edit::Add)
+        )
+        .unwrap();
+        for i in start..end {
+            let (old_line, new_line) = positions[i];
+            match edits[i] {
+                Edit::Keep => writeln!(text, " {}", old[old_line]),
+                Edit::Remove => writeln!(text, "-{}", old[old_line]),
+                Edit::Add => writeln!(text, "+{}", new[new_line]),
+            }
+            .unwrap();
+        }
+        index = end;
+    }
+    text
+}
+
+// The shortest edit script by longest common subsequence, after trimming the common ends.
+fn edits(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut edits = vec![Edit::Keep; prefix];
+    if (old_middle.len() + 1) * (new_middle.len() + 1) > MAX_TABLE {
+        edits.extend(vec![Edit::Remove; old_middle.len()]);
+        edits.extend(vec![Edit::Add; new_middle.len()]);
+    } else {
+        edits.extend(middle_edits(old_middle, new_middle));
+    }
+    edits.extend(vec![Edit::Keep; suffix]);
+    edits
+}
+
+fn middle_edits(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    // common[i][j] is the longest common subsequence of old[i..] and new[j..].
+    let width = new.len() + 1;
+    let mut common = vec![0u32; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i * width + j] = if old[i] == new[j] {
+                common[(i + 1) * width + j + 1] + 1
+            } else {
+                common[(i + 1) * width + j].max(common[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            edits.push(Edit::Keep);
+            i += 1;
+            j += 1;
+        } else if j == new.len()
+            || (i < old.len() && common[(i + 1) * width + j] >= common[i * width + j + 1])
+        {
+            edits.push(Edit::Remove);
+            i += 1;
+        } else {
+            edits.push(Edit::Add);
+            j += 1;
+        }
+    }
+    edits
+}
@@ -1,27 +1,146 @@
 use std::fmt::Display;
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct Flags {
+    pub cf: bool,
+    pub pf: bool,
+    pub af: bool,
     pub zf: bool,
     pub sf: bool,
+    pub tf: bool,
+    pub if_: bool,
+    pub df: bool,
+    pub of: bool,
 }
 
+// Bits 1 and 12-15 of the flags register always read as set on the 8086.
+const RESERVED_BITS: u16 = 0xf002;
+
 impl Flags {
     pub fn update_from_number(&mut self, number: i16) {
         self.zf = number == 0;
         self.sf = number < 0;
     }
+
+    pub fn update_from_value(&mut self, value: i16, w: usize) {
+        if w == 1 {
+            self.update_from_number(value);
+        } else {
+            self.update_from_number(value as i8 as i16);
+        }
+        // Parity only looks at the low byte, set when it has an even number of ones.
+        self.pf = (value as u8).count_ones().is_multiple_of(2);
+    }
+
+    pub fn update_from_addition(&mut self, left: i16, right: i16, w: usize) -> i16 {
+        self.update_from_addition_with_carry(left, right, false, w)
+    }
+
+    // adc adds the carry in as well, add is the same without it.
+    pub fn update_from_addition_with_carry(
+        &mut self,
+        left: i16,
+        right: i16,
+        carry: bool,
+        w: usize,
+    ) -> i16 {
+        let (mask, sign) = operand_masks(w);
+        let left = left as u16 as u32 & mask;
+        let right = right as u16 as u32 & mask;
+        let result = left + right + carry as u32;
+
+        self.cf = result > mask;
+        self.af = (left ^ right ^ result) & 0x10 != 0;
+        self.of = (left ^ result) & (right ^ result) & sign != 0;
+        self.update_from_value(result as i16, w);
+        result as i16
+    }
+
+    pub fn update_from_subtraction(&mut self, left: i16, right: i16, w: usize) -> i16 {
+        self.update_from_subtraction_with_borrow(left, right, false, w)
+    }
+
+    pub fn update_from_subtraction_with_borrow(
+        &mut self,
+        left: i16,
+        right: i16,
+        borrow: bool,
+        w: usize,
+    ) -> i16 {
+        let (mask, sign) = operand_masks(w);
+        let left = left as u16 as u32 & mask;
+        let right = right as u16 as u32 & mask;
+        let result = left.wrapping_sub(right).wrapping_sub(borrow as u32) & mask;
+
+        self.cf = right + borrow as u32 > left;
+        self.af = (left ^ right ^ result) & 0x10 != 0;
+        self.of = (left ^ right) & (left ^ result) & sign != 0;
+        self.update_from_value(result as i16, w);
+        result as i16
+    }
+
+    // and, or, xor and test clear CF and OF.
+    pub fn update_from_logic(&mut self, value: i16, w: usize) -> i16 {
+        self.cf = false;
+        self.of = false;
+        self.update_from_value(value, w);
+        value
+    }
+
+    pub fn to_word(&self) -> u16 {
+        RESERVED_BITS
+            | self.cf as u16
+            | (self.pf as u16) << 2
+            | (self.af as u16) << 4
+            | (self.zf as u16) << 6
+            | (self.sf as u16) << 7
+            | (self.tf as u16) << 8
+            | (self.if_ as u16) << 9
+            | (self.df as u16) << 10
+            | (self.of as u16) << 11
+    }
+
+    pub fn from_word(word: u16) -> Flags {
+        Flags {
+            cf: word & 1 != 0,
+            pf: word & (1 << 2) != 0,
+            af: word & (1 << 4) != 0,
+            zf: word & (1 << 6) != 0,
+            sf: word & (1 << 7) != 0,
+            tf: word & (1 << 8) != 0,
+            if_: word & (1 << 9) != 0,
+            df: word & (1 << 10) != 0,
+            of: word & (1 << 11) != 0,
+        }
+    }
+}
+
+pub fn operand_masks(w: usize) -> (u32, u32) {
+    if w == 1 {
+        (0xffff, 0x8000)
+    } else {
+        (0xff, 0x80)
+    }
 }
 
 impl Display for Flags {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut string = String::from("");
 
-        if self.zf {
-            string += "Z";
-        }
-        if self.sf {
-            string += "S";
+        for (set, name) in [
+            (self.cf, "C"),
+            (self.pf, "P"),
+            (self.af, "A"),
+            (self.zf, "Z"),
+            (self.sf, "S"),
+            (self.tf, "T"),
+            (self.if_, "I"),
+            (self.df, "D"),
+            (self.of, "O"),
+        ] {
+            if set {
+                string += name;
+            }
         }
 
         write!(f, "{}", string)
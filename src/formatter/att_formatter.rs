@@ -0,0 +1,150 @@
+use crate::constants::{REGISTER_NAMES, SEGMENT_REGISTER_NAMES};
+use crate::instruction::Instruction;
+use crate::rm::{MAPPTING_TO_EFFECTIVE_MEMORY_ADDRESS, Rm};
+
+use super::{FormatOptions, Formatter, immediate, jump_offset, number, signed_number};
+
+// AT&T syntax as accepted by GNU as: `movw %bx, 4(%bx,%si)`, source operand first.
+pub struct AttFormatter;
+
+fn hex_digits(value: u32) -> String {
+    format!("{:#x}", value)
+}
+
+impl AttFormatter {
+    fn operand(&self, rm: &Rm, segment: Option<usize>, options: &FormatOptions) -> String {
+        let segment = segment.map_or(String::new(), |segment| {
+            format!("%{}:", SEGMENT_REGISTER_NAMES[segment])
+        });
+        match rm {
+            Rm::Reg { w, reg } => format!("%{}", REGISTER_NAMES[*w][*reg]),
+            Rm::SegmentReg(reg) => format!("%{}", SEGMENT_REGISTER_NAMES[*reg]),
+            Rm::FpuReg(0) => String::from("%st"),
+            Rm::FpuReg(i) => format!("%st({})", i),
+            Rm::DirectMemory(address) => {
+                format!(
+                    "{}{}",
+                    segment,
+                    number(*address as i32, options, hex_digits)
+                )
+            }
+            Rm::MemoryNoDisplacment(rm) => format!("{}{}", segment, self.registers(*rm)),
+            Rm::MemoryWithDisplacment {
+                rm: 6,
+                displacment: 0,
+            } => format!("{}{}", segment, self.registers(6)),
+            Rm::MemoryWithDisplacment { rm, displacment } => format!(
+                "{}{}{}",
+                segment,
+                number(*displacment as i16 as i32, options, hex_digits),
+                self.registers(*rm)
+            ),
+        }
+    }
+
+    // 8087 suffixes name the memory format: s, l and t for reals, s, l and ll for integers.
+    fn fpu_suffix(&self, instruction: &Instruction) -> &'static str {
+        let integer = instruction.name.starts_with("fi");
+        match (integer, instruction.memory_size) {
+            (true, Some(2)) => "s",
+            (true, Some(4)) => "l",
+            (true, Some(8)) => "ll",
+            (false, Some(4)) => "s",
+            (false, Some(8)) => "l",
+            (false, Some(10)) if instruction.name != "fbld" && instruction.name != "fbstp" => "t",
+            _ => "",
+        }
+    }
+
+    fn registers(&self, rm: usize) -> String {
+        let (base, index) = MAPPTING_TO_EFFECTIVE_MEMORY_ADDRESS[rm];
+        match index {
+            Some(index) => format!(
+                "(%{},%{})",
+                REGISTER_NAMES[1][base], REGISTER_NAMES[1][index]
+            ),
+            None => format!("(%{})", REGISTER_NAMES[1][base]),
+        }
+    }
+}
+
+impl Formatter for AttFormatter {
+    fn format_instruction(&self, instruction: &Instruction, options: &FormatOptions) -> String {
+        if instruction.is_relative() {
+            let offset = signed_number(jump_offset(instruction), options, hex_digits);
+            return format!("{} .{}", instruction.name, offset);
+        }
+        // Far transfers are lcall, ljmp and lret.
+        let name = match instruction.name {
+            "retf" => "lret",
+            "call" if instruction.far => "lcall",
+            "jmp" if instruction.far => "ljmp",
+            name => name,
+        };
+        if let (Some(offset), Some(segment)) =
+            (instruction.immediate_value, instruction.second_immediate)
+            && instruction.far
+        {
+            return format!(
+                "{} ${}, ${}",
+                name,
+                number(segment as u16 as i32, options, hex_digits),
+                number(offset as u16 as i32, options, hex_digits)
+            );
+        }
+
+        let mut operands = Vec::new();
+        if let Some(value) = instruction.immediate_value {
+            let value = immediate(instruction, value, options);
+            operands.push(format!("${}", number(value, options, hex_digits)));
+        }
+        // GNU as keeps the Intel operand order for enter.
+        if let Some(level) = instruction.second_immediate {
+            operands.push(format!("${}", number(level as i32, options, hex_digits)));
+        }
+        if let Some(source) = &instruction.source {
+            operands.push(self.operand(source, instruction.segment_override, options));
+        }
+        if let Some(destination) = &instruction.destination {
+            // Indirect calls and jumps mark their target with a star.
+            let indirect = if matches!(name, "call" | "jmp" | "lcall" | "ljmp") {
+                "*"
+            } else {
+                ""
+            };
+            operands.push(format!(
+                "{}{}",
+                indirect,
+                self.operand(destination, instruction.segment_override, options)
+            ));
+        }
+
+        // The port out writes to goes last like any destination.
+        if name == "out" && instruction.immediate_value.is_some() {
+            operands.rotate_left(1);
+        }
+
+        // Instructions with a register or memory operand carry the operand size in the suffix.
+        let suffix = match (&instruction.destination, instruction.w) {
+            _ if instruction.is_fpu() => self.fpu_suffix(instruction),
+            (None, _) => "",
+            (Some(_), 0) => "b",
+            (Some(_), _) => "w",
+        };
+        // The far forms already say they load a far pointer.
+        let suffix = if instruction.far { "" } else { suffix };
+        if operands.is_empty() {
+            String::from(name)
+        } else {
+            format!("{}{} {}", name, suffix, operands.join(", "))
+        }
+    }
+
+    fn comment(&self) -> &'static str {
+        "#"
+    }
+
+    fn mode_directive(&self) -> &'static str {
+        ".code16"
+    }
+}
@@ -0,0 +1,115 @@
+use crate::constants::{DS, EFFECTIVE_MEMOERY_ADDRESS, REGISTER_NAMES, SEGMENT_REGISTER_NAMES};
+use crate::instruction::Instruction;
+use crate::rm::Rm;
+
+use super::{
+    FormatOptions, Formatter, immediate, jump_offset, number, signed_number, size_keyword,
+};
+
+// MASM/TASM syntax: `mov word ptr [bx+si+4], 0ah`.
+pub struct MasmFormatter;
+
+// Hex numbers need a leading digit so they are not taken for names.
+fn hex_digits(value: u32) -> String {
+    let digits = format!("{:x}", value);
+    if digits.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        format!("0{}h", digits)
+    } else {
+        format!("{}h", digits)
+    }
+}
+
+impl MasmFormatter {
+    fn operand(&self, rm: &Rm, segment: Option<usize>, options: &FormatOptions) -> String {
+        let prefix = segment.map_or(String::new(), |segment| {
+            format!("{}:", SEGMENT_REGISTER_NAMES[segment])
+        });
+        match rm {
+            Rm::Reg { w, reg } => String::from(REGISTER_NAMES[*w][*reg]),
+            Rm::SegmentReg(reg) => String::from(SEGMENT_REGISTER_NAMES[*reg]),
+            Rm::FpuReg(i) => format!("st({})", i),
+            // Without the segment MASM would read a bare [1000] as the constant 1000.
+            Rm::DirectMemory(address) => format!(
+                "{}:[{}]",
+                SEGMENT_REGISTER_NAMES[segment.unwrap_or(DS)],
+                number(*address as i32, options, hex_digits)
+            ),
+            Rm::MemoryNoDisplacment(rm) => {
+                format!("{}[{}]", prefix, EFFECTIVE_MEMOERY_ADDRESS[*rm])
+            }
+            Rm::MemoryWithDisplacment {
+                rm: 6,
+                displacment: 0,
+            } => format!("{}[bp]", prefix),
+            Rm::MemoryWithDisplacment { rm, displacment } => format!(
+                "{}[{}{}]",
+                prefix,
+                EFFECTIVE_MEMOERY_ADDRESS[*rm],
+                signed_number(*displacment as i16 as i32, options, hex_digits)
+            ),
+        }
+    }
+}
+
+impl Formatter for MasmFormatter {
+    fn format_instruction(&self, instruction: &Instruction, options: &FormatOptions) -> String {
+        if instruction.is_relative() {
+            let offset = signed_number(jump_offset(instruction), options, hex_digits);
+            return format!("{} ${}", instruction.name, offset);
+        }
+        if let (Some(offset), Some(segment)) =
+            (instruction.immediate_value, instruction.second_immediate)
+            && instruction.far
+        {
+            return format!(
+                "{} far ptr {}:{}",
+                instruction.name,
+                number(segment as u16 as i32, options, hex_digits),
+                number(offset as u16 as i32, options, hex_digits)
+            );
+        }
+
+        let mut operands = Vec::new();
+        if let Some(destination) = &instruction.destination {
+            // A register operand already gives the size.
+            let operand = self.operand(destination, instruction.segment_override, options);
+            operands.push(match size_keyword(instruction) {
+                _ if destination.is_memory() && instruction.far => format!("dword ptr {}", operand),
+                Some(size) if destination.is_memory() && instruction.source.is_none() => {
+                    let size = if size == "tword" { "tbyte" } else { size };
+                    format!("{} ptr {}", size, operand)
+                }
+                _ => operand,
+            });
+        }
+        if let Some(source) = &instruction.source {
+            operands.push(self.operand(source, instruction.segment_override, options));
+        }
+        if let Some(value) = instruction.immediate_value {
+            let value = number(immediate(instruction, value, options), options, hex_digits);
+            // out names the port before the accumulator.
+            if instruction.name == "out" {
+                operands.insert(0, value);
+            } else {
+                operands.push(value);
+            }
+        }
+        if let Some(level) = instruction.second_immediate {
+            operands.push(number(level as i32, options, hex_digits));
+        }
+
+        if operands.is_empty() {
+            String::from(instruction.name)
+        } else {
+            format!("{} {}", instruction.name, operands.join(", "))
+        }
+    }
+
+    fn comment(&self) -> &'static str {
+        ";"
+    }
+
+    fn mode_directive(&self) -> &'static str {
+        ".8086"
+    }
+}
@@ -0,0 +1,101 @@
+use crate::constants::SEGMENT_REGISTER_NAMES;
+use crate::instruction::Instruction;
+
+pub mod att_formatter;
+pub mod masm_formatter;
+pub mod nasm_formatter;
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Signedness {
+    // Only byte immediates sign-extended to a word are signed, like sim86.
+    #[default]
+    Auto,
+    Signed,
+    Unsigned,
+}
+
+#[derive(Default)]
+pub struct FormatOptions {
+    pub hex: bool,
+    pub signedness: Signedness,
+}
+
+pub trait Formatter {
+    // The instruction without its prefixes, a segment override goes with the memory operand.
+    fn format_instruction(&self, instruction: &Instruction, options: &FormatOptions) -> String;
+    fn comment(&self) -> &'static str;
+    // Directive that puts the assembler in 16-bit 8086 mode.
+    fn mode_directive(&self) -> &'static str;
+
+    // Puts the repeat prefix in front, and a segment override no operand took.
+    fn format(&self, instruction: &Instruction, options: &FormatOptions) -> String {
+        let text = self.format_instruction(instruction, options);
+        let segment = instruction
+            .segment_override
+            .filter(|_| instruction.memory_operand().is_none())
+            .map(|segment| SEGMENT_REGISTER_NAMES[segment]);
+        let prefixes: Vec<&str> = segment.into_iter().chain(instruction.repeat).collect();
+        if prefixes.is_empty() {
+            text
+        } else {
+            format!("{} {}", prefixes.join(" "), text)
+        }
+    }
+
+    // Flags encodings missing from Intel's manuals with a trailing comment.
+    fn format_marked(&self, instruction: &Instruction, options: &FormatOptions) -> String {
+        let text = self.format(instruction, options);
+        if instruction.undocumented {
+            format!("{} {} undocumented", text, self.comment())
+        } else {
+            text
+        }
+    }
+}
+
+pub fn immediate(instruction: &Instruction, value: i16, options: &FormatOptions) -> i32 {
+    let signed = match options.signedness {
+        Signedness::Auto => instruction.signed_immediate,
+        Signedness::Signed => true,
+        Signedness::Unsigned => false,
+    };
+    match (signed, instruction.w) {
+        (true, 0) => value as i8 as i32,
+        (true, _) => value as i32,
+        (false, 0) => value as u8 as i32,
+        (false, _) => value as u16 as i32,
+    }
+}
+
+// The size of a memory operand, None for 8087 environment and state blocks.
+pub fn size_keyword(instruction: &Instruction) -> Option<&'static str> {
+    match instruction.memory_size {
+        None if instruction.w == 0 => Some("byte"),
+        None | Some(2) => Some("word"),
+        Some(4) => Some("dword"),
+        Some(8) => Some("qword"),
+        Some(10) => Some("tword"),
+        Some(_) => None,
+    }
+}
+
+// Jump displacements are relative to the end of the instruction, assemblers count from its start.
+pub fn jump_offset(instruction: &Instruction) -> i32 {
+    instruction.immediate_value.unwrap() as i32 + instruction.length as i32
+}
+
+// Formats the magnitude with hex_digits when hex output is on and puts any sign in front.
+pub fn number(value: i32, options: &FormatOptions, hex_digits: fn(u32) -> String) -> String {
+    let sign = if value < 0 { "-" } else { "" };
+    if options.hex {
+        format!("{}{}", sign, hex_digits(value.unsigned_abs()))
+    } else {
+        value.to_string()
+    }
+}
+
+// Same as number but always with a sign, for displacements and $-relative jumps.
+pub fn signed_number(value: i32, options: &FormatOptions, hex_digits: fn(u32) -> String) -> String {
+    let sign = if value < 0 { "-" } else { "+" };
+    format!("{}{}", sign, number(value.abs(), options, hex_digits))
+}
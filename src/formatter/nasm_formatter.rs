@@ -0,0 +1,105 @@
+use crate::constants::{EFFECTIVE_MEMOERY_ADDRESS, REGISTER_NAMES, SEGMENT_REGISTER_NAMES};
+use crate::instruction::Instruction;
+use crate::rm::Rm;
+
+use super::{
+    FormatOptions, Formatter, immediate, jump_offset, number, signed_number, size_keyword,
+};
+
+// nasm syntax as printed by sim86: `mov word [bx+si+4], 10`.
+pub struct NasmFormatter;
+
+fn hex_digits(value: u32) -> String {
+    format!("{:#x}", value)
+}
+
+impl NasmFormatter {
+    fn operand(&self, rm: &Rm, segment: Option<usize>, options: &FormatOptions) -> String {
+        let segment = segment.map_or(String::new(), |segment| {
+            format!("{}:", SEGMENT_REGISTER_NAMES[segment])
+        });
+        match rm {
+            Rm::Reg { w, reg } => String::from(REGISTER_NAMES[*w][*reg]),
+            Rm::SegmentReg(reg) => String::from(SEGMENT_REGISTER_NAMES[*reg]),
+            Rm::FpuReg(i) => format!("st{}", i),
+            Rm::DirectMemory(address) => format!(
+                "[{}{}]",
+                segment,
+                signed_number(*address as i32, options, hex_digits)
+            ),
+            Rm::MemoryNoDisplacment(rm) => {
+                format!("[{}{}]", segment, EFFECTIVE_MEMOERY_ADDRESS[*rm])
+            }
+            // [bp] needs a displacement byte, it is written without one all the same.
+            Rm::MemoryWithDisplacment {
+                rm: 6,
+                displacment: 0,
+            } => format!("[{}bp]", segment),
+            Rm::MemoryWithDisplacment { rm, displacment } => format!(
+                "[{}{}{}]",
+                segment,
+                EFFECTIVE_MEMOERY_ADDRESS[*rm],
+                signed_number(*displacment as i16 as i32, options, hex_digits)
+            ),
+        }
+    }
+}
+
+impl Formatter for NasmFormatter {
+    fn format_instruction(&self, instruction: &Instruction, options: &FormatOptions) -> String {
+        if instruction.is_relative() {
+            let offset = signed_number(jump_offset(instruction), options, hex_digits);
+            return format!("{} ${}", instruction.name, offset);
+        }
+        if let (Some(offset), Some(segment)) =
+            (instruction.immediate_value, instruction.second_immediate)
+            && instruction.far
+        {
+            return format!(
+                "{} {}:{}",
+                instruction.name,
+                number(segment as u16 as i32, options, hex_digits),
+                number(offset as u16 as i32, options, hex_digits)
+            );
+        }
+
+        let mut operands = Vec::new();
+        if let Some(destination) = &instruction.destination {
+            let operand = self.operand(destination, instruction.segment_override, options);
+            operands.push(match size_keyword(instruction) {
+                _ if destination.is_memory() && instruction.far => format!("far {}", operand),
+                Some(size) if destination.is_memory() => format!("{} {}", size, operand),
+                _ => operand,
+            });
+        }
+        if let Some(source) = &instruction.source {
+            operands.push(self.operand(source, instruction.segment_override, options));
+        }
+        if let Some(value) = instruction.immediate_value {
+            let value = number(immediate(instruction, value, options), options, hex_digits);
+            // out names the port before the accumulator.
+            if instruction.name == "out" {
+                operands.insert(0, value);
+            } else {
+                operands.push(value);
+            }
+        }
+        if let Some(level) = instruction.second_immediate {
+            operands.push(number(level as i32, options, hex_digits));
+        }
+
+        if operands.is_empty() {
+            String::from(instruction.name)
+        } else {
+            format!("{} {}", instruction.name, operands.join(", "))
+        }
+    }
+
+    fn comment(&self) -> &'static str {
+        ";"
+    }
+
+    fn mode_directive(&self) -> &'static str {
+        "bits 16"
+    }
+}
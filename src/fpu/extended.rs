@@ -0,0 +1,849 @@
+use std::cmp::Ordering;
+
+// 80-bit extended real: sign, 15-bit biased exponent and a 64-bit significand whose top bit is
+// the explicit integer bit. Arithmetic is done on integers so results are exact before rounding.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Extended {
+    pub sign: bool,
+    pub exponent: u16,
+    pub significand: u64,
+}
+
+pub const EXPONENT_BIAS: i32 = 16383;
+const MAX_EXPONENT: u16 = 0x7fff;
+const INTEGER_BIT: u64 = 1 << 63;
+const QUIET_BIT: u64 = 1 << 62;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Rounding {
+    Nearest,
+    Down,
+    Up,
+    Zero,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Exceptions {
+    pub invalid: bool,
+    pub denormal: bool,
+    pub zero_divide: bool,
+    pub overflow: bool,
+    pub underflow: bool,
+    pub precision: bool,
+}
+
+// A finite nonzero value as m * 2^(e - 63), with the top bit of m set.
+struct Unpacked {
+    sign: bool,
+    e: i32,
+    m: u64,
+}
+
+// A rounded value: biased exponent (0 for denormals) and the kept bits aligned to bit 63.
+struct Rounded {
+    biased: i32,
+    significand: u64,
+}
+
+impl Extended {
+    pub const ZERO: Extended = Extended {
+        sign: false,
+        exponent: 0,
+        significand: 0,
+    };
+    pub const ONE: Extended = Extended {
+        sign: false,
+        exponent: EXPONENT_BIAS as u16,
+        significand: INTEGER_BIT,
+    };
+    // The default NaN produced by masked invalid operations.
+    pub const INDEFINITE: Extended = Extended {
+        sign: true,
+        exponent: MAX_EXPONENT,
+        significand: INTEGER_BIT | QUIET_BIT,
+    };
+
+    pub fn infinity(sign: bool) -> Extended {
+        Extended {
+            sign,
+            exponent: MAX_EXPONENT,
+            significand: INTEGER_BIT,
+        }
+    }
+
+    pub fn zero(sign: bool) -> Extended {
+        Extended {
+            sign,
+            ..Extended::ZERO
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Extended {
+        let significand = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+        let top = u16::from_le_bytes([bytes[8], bytes[9]]);
+        Extended {
+            sign: top & 0x8000 != 0,
+            exponent: top & MAX_EXPONENT,
+            significand,
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; 10] {
+        let mut bytes = [0; 10];
+        bytes[..8].copy_from_slice(&self.significand.to_le_bytes());
+        let top = ((self.sign as u16) << 15) | self.exponent;
+        bytes[8..].copy_from_slice(&top.to_le_bytes());
+        bytes
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.exponent == 0 && self.significand == 0
+    }
+
+    pub fn is_denormal(&self) -> bool {
+        self.exponent == 0 && self.significand != 0
+    }
+
+    pub fn is_infinity(&self) -> bool {
+        self.exponent == MAX_EXPONENT && self.significand << 1 == 0
+    }
+
+    pub fn is_nan(&self) -> bool {
+        self.exponent == MAX_EXPONENT && self.significand << 1 != 0
+    }
+
+    pub fn is_signaling_nan(&self) -> bool {
+        self.is_nan() && self.significand & QUIET_BIT == 0
+    }
+
+    pub fn negate(self) -> Extended {
+        Extended {
+            sign: !self.sign,
+            ..self
+        }
+    }
+
+    pub fn abs(self) -> Extended {
+        Extended {
+            sign: false,
+            ..self
+        }
+    }
+
+    fn quiet(self) -> Extended {
+        Extended {
+            significand: self.significand | INTEGER_BIT | QUIET_BIT,
+            ..self
+        }
+    }
+
+    fn unpack(&self) -> Unpacked {
+        let mut e = if self.exponent == 0 {
+            1 - EXPONENT_BIAS
+        } else {
+            self.exponent as i32 - EXPONENT_BIAS
+        };
+        let shift = self.significand.leading_zeros();
+        e -= shift as i32;
+        Unpacked {
+            sign: self.sign,
+            e,
+            m: self.significand << shift,
+        }
+    }
+
+    // Exponent of the value when written as 1.xxx * 2^exponent, for finite nonzero values.
+    pub fn unbiased_exponent(&self) -> i32 {
+        self.unpack().e
+    }
+
+    // Propagates NaN operands: signaling NaNs are invalid, the result is the first NaN quieted.
+    fn nan_result(operands: &[Extended], exceptions: &mut Exceptions) -> Option<Extended> {
+        if operands.iter().any(|operand| operand.is_signaling_nan()) {
+            exceptions.invalid = true;
+        }
+        operands
+            .iter()
+            .find(|operand| operand.is_nan())
+            .map(|operand| operand.quiet())
+    }
+
+    pub fn add(
+        self,
+        other: Extended,
+        rounding: Rounding,
+        precision: u32,
+        exceptions: &mut Exceptions,
+    ) -> Extended {
+        if let Some(nan) = Extended::nan_result(&[self, other], exceptions) {
+            return nan;
+        }
+        check_denormals(&[self, other], exceptions);
+        match (self.is_infinity(), other.is_infinity()) {
+            (true, true) if self.sign != other.sign => {
+                exceptions.invalid = true;
+                return Extended::INDEFINITE;
+            }
+            (true, _) => return self,
+            (_, true) => return other,
+            _ => {}
+        }
+        if self.is_zero() && other.is_zero() {
+            let sign = if self.sign == other.sign {
+                self.sign
+            } else {
+                rounding == Rounding::Down
+            };
+            return Extended::zero(sign);
+        }
+        if self.is_zero() {
+            return other.round(rounding, precision, exceptions);
+        }
+        if other.is_zero() {
+            return self.round(rounding, precision, exceptions);
+        }
+
+        let (a, b) = (self.unpack(), other.unpack());
+        let (large, small) = if (a.e, a.m) >= (b.e, b.m) {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        let large_m = (large.m as u128) << 64;
+        let (small_m, sticky) = shift_right((small.m as u128) << 64, (large.e - small.e) as u32);
+
+        if large.sign == small.sign {
+            let (sum, carry) = large_m.overflowing_add(small_m);
+            if carry {
+                let sticky = sticky || sum & 1 != 0;
+                let m = (sum >> 1) | (1 << 127);
+                return round_pack(
+                    large.sign,
+                    large.e + 1,
+                    m,
+                    sticky,
+                    rounding,
+                    precision,
+                    exceptions,
+                );
+            }
+            round_pack(
+                large.sign, large.e, sum, sticky, rounding, precision, exceptions,
+            )
+        } else {
+            // The sticky bits of the smaller operand borrow from the difference.
+            let difference = large_m - small_m - sticky as u128;
+            if difference == 0 && !sticky {
+                return Extended::zero(rounding == Rounding::Down);
+            }
+            round_pack(
+                large.sign, large.e, difference, sticky, rounding, precision, exceptions,
+            )
+        }
+    }
+
+    pub fn subtract(
+        self,
+        other: Extended,
+        rounding: Rounding,
+        precision: u32,
+        exceptions: &mut Exceptions,
+    ) -> Extended {
+        if other.is_nan() {
+            return self.add(other, rounding, precision, exceptions);
+        }
+        self.add(other.negate(), rounding, precision, exceptions)
+    }
+
+    pub fn multiply(
+        self,
+        other: Extended,
+        rounding: Rounding,
+        precision: u32,
+        exceptions: &mut Exceptions,
+    ) -> Extended {
+        if let Some(nan) = Extended::nan_result(&[self, other], exceptions) {
+            return nan;
+        }
+        check_denormals(&[self, other], exceptions);
+        let sign = self.sign != other.sign;
+        if (self.is_infinity() && other.is_zero()) || (self.is_zero() && other.is_infinity()) {
+            exceptions.invalid = true;
+            return Extended::INDEFINITE;
+        }
+        if self.is_infinity() || other.is_infinity() {
+            return Extended::infinity(sign);
+        }
+        if self.is_zero() || other.is_zero() {
+            return Extended::zero(sign);
+        }
+
+        let (a, b) = (self.unpack(), other.unpack());
+        let m = a.m as u128 * b.m as u128;
+        round_pack(
+            sign,
+            a.e + b.e + 1,
+            m,
+            false,
+            rounding,
+            precision,
+            exceptions,
+        )
+    }
+
+    pub fn divide(
+        self,
+        other: Extended,
+        rounding: Rounding,
+        precision: u32,
+        exceptions: &mut Exceptions,
+    ) -> Extended {
+        if let Some(nan) = Extended::nan_result(&[self, other], exceptions) {
+            return nan;
+        }
+        check_denormals(&[self, other], exceptions);
+        let sign = self.sign != other.sign;
+        if (self.is_infinity() && other.is_infinity()) || (self.is_zero() && other.is_zero()) {
+            exceptions.invalid = true;
+            return Extended::INDEFINITE;
+        }
+        if self.is_infinity() {
+            return Extended::infinity(sign);
+        }
+        if other.is_zero() {
+            exceptions.zero_divide = true;
+            return Extended::infinity(sign);
+        }
+        if self.is_zero() || other.is_infinity() {
+            return Extended::zero(sign);
+        }
+
+        // Two rounds of long division give 128 quotient bits plus a sticky remainder.
+        let (a, b) = (self.unpack(), other.unpack());
+        let (numerator, e) = if a.m < b.m {
+            ((a.m as u128) << 64, a.e - b.e - 1)
+        } else {
+            ((a.m as u128) << 63, a.e - b.e)
+        };
+        let divisor = b.m as u128;
+        let high = numerator / divisor;
+        let remainder = numerator % divisor;
+        let low = (remainder << 64) / divisor;
+        let sticky = !(remainder << 64).is_multiple_of(divisor);
+        let m = (high << 64) | low;
+        round_pack(sign, e, m, sticky, rounding, precision, exceptions)
+    }
+
+    pub fn sqrt(self, rounding: Rounding, precision: u32, exceptions: &mut Exceptions) -> Extended {
+        if let Some(nan) = Extended::nan_result(&[self], exceptions) {
+            return nan;
+        }
+        check_denormals(&[self], exceptions);
+        if self.is_zero() {
+            return self;
+        }
+        if self.sign {
+            exceptions.invalid = true;
+            return Extended::INDEFINITE;
+        }
+        if self.is_infinity() {
+            return self;
+        }
+
+        // value = radicand * 2^exponent with an even exponent.
+        let unpacked = self.unpack();
+        let mut exponent = unpacked.e - 63;
+        let mut radicand = unpacked.m as u128;
+        if exponent % 2 != 0 {
+            radicand <<= 1;
+            exponent -= 1;
+        }
+
+        // Digit-by-digit square root of the 66-bit radicand padded with 66 zero bits.
+        let mut root: u128 = 0;
+        let mut remainder: u128 = 0;
+        for i in 0..66 {
+            let pair = if i < 33 {
+                (radicand >> (64 - 2 * i)) & 0b11
+            } else {
+                0
+            };
+            remainder = (remainder << 2) | pair;
+            let trial = (root << 2) | 1;
+            root <<= 1;
+            if remainder >= trial {
+                remainder -= trial;
+                root |= 1;
+            }
+        }
+        // root = sqrt(radicand) * 2^33
+        let e = exponent / 2 - 33 + 127;
+        round_pack(
+            false,
+            e,
+            root,
+            remainder != 0,
+            rounding,
+            precision,
+            exceptions,
+        )
+    }
+
+    // Rounds to the given precision, used when storing a value with more bits than allowed.
+    pub fn round(
+        self,
+        rounding: Rounding,
+        precision: u32,
+        exceptions: &mut Exceptions,
+    ) -> Extended {
+        if self.is_nan() || self.is_infinity() || self.is_zero() {
+            return self;
+        }
+        let unpacked = self.unpack();
+        round_pack(
+            unpacked.sign,
+            unpacked.e,
+            (unpacked.m as u128) << 64,
+            false,
+            rounding,
+            precision,
+            exceptions,
+        )
+    }
+
+    // None when either operand is a NaN, which the 8087 treats as an invalid comparison.
+    pub fn compare(self, other: Extended, exceptions: &mut Exceptions) -> Option<Ordering> {
+        if self.is_nan() || other.is_nan() {
+            exceptions.invalid = true;
+            return None;
+        }
+        check_denormals(&[self, other], exceptions);
+        Some(ordering(self, other))
+    }
+
+    pub fn from_i64(value: i64) -> Extended {
+        if value == 0 {
+            return Extended::ZERO;
+        }
+        let magnitude = value.unsigned_abs();
+        let shift = magnitude.leading_zeros();
+        Extended {
+            sign: value < 0,
+            exponent: (EXPONENT_BIAS + 63 - shift as i32) as u16,
+            significand: magnitude << shift,
+        }
+    }
+
+    // Rounds to an integer with the given rounding, None for NaNs, infinities and values that
+    // do not fit in an i64.
+    pub fn to_i64(self, rounding: Rounding, exceptions: &mut Exceptions) -> Option<i64> {
+        if self.is_nan() || self.is_infinity() {
+            return None;
+        }
+        if self.is_zero() {
+            return Some(0);
+        }
+        let (magnitude, inexact) = self.round_to_integer(rounding)?;
+        exceptions.precision |= inexact;
+        if self.sign {
+            if magnitude > 1 << 63 {
+                return None;
+            }
+            Some((magnitude as i64).wrapping_neg())
+        } else {
+            i64::try_from(magnitude).ok()
+        }
+    }
+
+    // The integer magnitude and whether anything was rounded off, None when it exceeds 2^64.
+    fn round_to_integer(&self, rounding: Rounding) -> Option<(u128, bool)> {
+        let unpacked = self.unpack();
+        if unpacked.e >= 64 {
+            return None;
+        }
+        if unpacked.e >= 63 {
+            return Some((unpacked.m as u128, false));
+        }
+        // Keep the integer part in the high 64 bits and the fraction in the low 64.
+        let value = (unpacked.m as u128) << 64;
+        let (fixed, sticky) = shift_right(value, (63 - unpacked.e) as u32);
+        let integer = fixed >> 64;
+        let fraction = fixed as u64;
+        let inexact = fraction != 0 || sticky;
+        let half = 1u64 << 63;
+        let round_up = match rounding {
+            Rounding::Nearest => {
+                fraction > half || (fraction == half && (sticky || integer & 1 == 1))
+            }
+            Rounding::Up => inexact && !self.sign,
+            Rounding::Down => inexact && self.sign,
+            Rounding::Zero => false,
+        };
+        Some((integer + round_up as u128, inexact))
+    }
+
+    // frndint: rounds to an integral value keeping the extended format.
+    pub fn round_integral(self, rounding: Rounding, exceptions: &mut Exceptions) -> Extended {
+        if let Some(nan) = Extended::nan_result(&[self], exceptions) {
+            return nan;
+        }
+        if self.is_infinity() || self.is_zero() {
+            return self;
+        }
+        check_denormals(&[self], exceptions);
+        match self.round_to_integer(rounding) {
+            None => self,
+            Some((magnitude, inexact)) => {
+                exceptions.precision |= inexact;
+                if magnitude == 0 {
+                    return Extended::zero(self.sign);
+                }
+                let value = if magnitude > u64::MAX as u128 {
+                    // Only 2^64 can come out of rounding up.
+                    Extended {
+                        sign: false,
+                        exponent: (EXPONENT_BIAS + 64) as u16,
+                        significand: INTEGER_BIT,
+                    }
+                } else {
+                    let shift = (magnitude as u64).leading_zeros();
+                    Extended {
+                        sign: false,
+                        exponent: (EXPONENT_BIAS + 63 - shift as i32) as u16,
+                        significand: (magnitude as u64) << shift,
+                    }
+                };
+                Extended {
+                    sign: self.sign,
+                    ..value
+                }
+            }
+        }
+    }
+
+    // value * 2^power, used by fscale.
+    pub fn scale(self, power: i32, rounding: Rounding, exceptions: &mut Exceptions) -> Extended {
+        if self.is_nan() || self.is_infinity() || self.is_zero() {
+            return self;
+        }
+        let unpacked = self.unpack();
+        round_pack(
+            unpacked.sign,
+            unpacked.e + power,
+            (unpacked.m as u128) << 64,
+            false,
+            rounding,
+            64,
+            exceptions,
+        )
+    }
+
+    // The significand as a value in [1, 2) with the sign kept, used by fxtract.
+    pub fn significand_part(self) -> Extended {
+        let unpacked = self.unpack();
+        Extended {
+            sign: self.sign,
+            exponent: EXPONENT_BIAS as u16,
+            significand: unpacked.m,
+        }
+    }
+
+    // Partial remainder of fprem: returns the remainder, the low quotient bits and whether the
+    // reduction is complete.
+    pub fn partial_remainder(self, divisor: Extended) -> (Extended, u64, bool) {
+        let (a, b) = (self.unpack(), divisor.unpack());
+        let difference = a.e - b.e;
+        if difference < 0 {
+            return (self, 0, true);
+        }
+        // The 8087 reduces the exponent by at most 63 per fprem.
+        let (shift, complete) = if difference < 64 {
+            (difference as u32, true)
+        } else {
+            (63, false)
+        };
+        let divisor_e = a.e - shift as i32;
+        let numerator = (a.m as u128) << shift;
+        let quotient = numerator / b.m as u128;
+        let remainder = (numerator % b.m as u128) as u64;
+        let value = if remainder == 0 {
+            Extended::zero(self.sign)
+        } else {
+            let shift = remainder.leading_zeros();
+            let e = divisor_e - shift as i32;
+            let mut exceptions = Exceptions::default();
+            round_pack(
+                self.sign,
+                e,
+                ((remainder << shift) as u128) << 64,
+                false,
+                Rounding::Nearest,
+                64,
+                &mut exceptions,
+            )
+        };
+        (value, quotient as u64, complete)
+    }
+
+    pub fn from_f32(bits: u32, exceptions: &mut Exceptions) -> Extended {
+        from_ieee(bits as u64, 8, 23, exceptions)
+    }
+
+    pub fn from_f64(bits: u64, exceptions: &mut Exceptions) -> Extended {
+        from_ieee(bits, 11, 52, exceptions)
+    }
+
+    pub fn to_f32(self, rounding: Rounding, exceptions: &mut Exceptions) -> u32 {
+        self.to_ieee(8, 23, rounding, exceptions) as u32
+    }
+
+    pub fn to_f64(self, rounding: Rounding, exceptions: &mut Exceptions) -> u64 {
+        self.to_ieee(11, 52, rounding, exceptions)
+    }
+
+    fn to_ieee(
+        self,
+        exponent_bits: u32,
+        fraction_bits: u32,
+        rounding: Rounding,
+        exceptions: &mut Exceptions,
+    ) -> u64 {
+        let sign = (self.sign as u64) << (exponent_bits + fraction_bits);
+        let max_exponent = (1u64 << exponent_bits) - 1;
+        let infinity = sign | (max_exponent << fraction_bits);
+        if self.is_nan() {
+            if self.is_signaling_nan() {
+                exceptions.invalid = true;
+            }
+            let payload = (self.significand << 1) >> (64 - fraction_bits);
+            return infinity | payload | (1 << (fraction_bits - 1));
+        }
+        if self.is_infinity() {
+            return infinity;
+        }
+        if self.is_zero() {
+            return sign;
+        }
+        check_denormals(&[self], exceptions);
+
+        let unpacked = self.unpack();
+        let bias = (1i32 << (exponent_bits - 1)) - 1;
+        let rounded = round_fields(
+            unpacked.sign,
+            unpacked.e + bias,
+            (unpacked.m as u128) << 64,
+            false,
+            rounding,
+            fraction_bits + 1,
+            max_exponent as i32,
+            exceptions,
+        );
+        match rounded {
+            None => infinity,
+            Some(rounded) => {
+                let fraction = (rounded.significand << 1) >> (64 - fraction_bits);
+                sign | ((rounded.biased as u64) << fraction_bits) | fraction
+            }
+        }
+    }
+
+    pub fn to_f64_value(self) -> f64 {
+        f64::from_bits(self.to_f64(Rounding::Nearest, &mut Exceptions::default()))
+    }
+
+    pub fn from_f64_value(value: f64) -> Extended {
+        Extended::from_f64(value.to_bits(), &mut Exceptions::default())
+    }
+}
+
+fn ordering(a: Extended, b: Extended) -> Ordering {
+    let magnitude = |value: &Extended| -> (i32, u64) {
+        if value.is_zero() {
+            (i32::MIN, 0)
+        } else if value.is_infinity() {
+            (i32::MAX, 0)
+        } else {
+            let unpacked = value.unpack();
+            (unpacked.e, unpacked.m)
+        }
+    };
+    let sign = |value: &Extended| !value.is_zero() && value.sign;
+    match (sign(&a), sign(&b)) {
+        (false, true) => Ordering::Greater,
+        (true, false) => Ordering::Less,
+        (false, false) => magnitude(&a).cmp(&magnitude(&b)),
+        (true, true) => magnitude(&b).cmp(&magnitude(&a)),
+    }
+}
+
+fn check_denormals(operands: &[Extended], exceptions: &mut Exceptions) {
+    if operands.iter().any(|operand| operand.is_denormal()) {
+        exceptions.denormal = true;
+    }
+}
+
+// Shifts right keeping track of whether any set bits were shifted out.
+fn shift_right(value: u128, shift: u32) -> (u128, bool) {
+    if shift == 0 {
+        (value, false)
+    } else if shift >= 128 {
+        (0, value != 0)
+    } else {
+        (value >> shift, value & ((1 << shift) - 1) != 0)
+    }
+}
+
+// Rounds m * 2^(e - 127) to an extended real with `precision` significant bits.
+fn round_pack(
+    sign: bool,
+    e: i32,
+    m: u128,
+    sticky: bool,
+    rounding: Rounding,
+    precision: u32,
+    exceptions: &mut Exceptions,
+) -> Extended {
+    if m == 0 {
+        return Extended::zero(sign);
+    }
+    match round_fields(
+        sign,
+        e + EXPONENT_BIAS,
+        m,
+        sticky,
+        rounding,
+        precision,
+        MAX_EXPONENT as i32,
+        exceptions,
+    ) {
+        Some(rounded) => Extended {
+            sign,
+            exponent: rounded.biased as u16,
+            significand: rounded.significand,
+        },
+        None => Extended::infinity(sign),
+    }
+}
+
+// Rounds to `precision` bits for a format whose biased exponent must stay below max_biased.
+// Returns None when the result overflows to infinity, or the largest finite value otherwise.
+#[allow(clippy::too_many_arguments)]
+fn round_fields(
+    sign: bool,
+    biased: i32,
+    m: u128,
+    sticky: bool,
+    rounding: Rounding,
+    precision: u32,
+    max_biased: i32,
+    exceptions: &mut Exceptions,
+) -> Option<Rounded> {
+    let shift = m.leading_zeros();
+    let mut m = m << shift;
+    let mut biased = biased - shift as i32;
+    let mut sticky = sticky;
+
+    let tiny = biased <= 0;
+    if tiny {
+        let (shifted, lost) = shift_right(m, (1 - biased) as u32);
+        m = shifted;
+        sticky |= lost;
+        biased = 0;
+    }
+
+    let discarded_bits = 128 - precision;
+    let kept = m >> discarded_bits;
+    let discarded = m & ((1u128 << discarded_bits) - 1);
+    let half = 1u128 << (discarded_bits - 1);
+    let inexact = discarded != 0 || sticky;
+    let round_up = match rounding {
+        Rounding::Nearest => discarded > half || (discarded == half && (sticky || kept & 1 == 1)),
+        Rounding::Up => inexact && !sign,
+        Rounding::Down => inexact && sign,
+        Rounding::Zero => false,
+    };
+
+    let mut kept = kept + round_up as u128;
+    if kept >> precision != 0 {
+        kept >>= 1;
+        biased += 1;
+    } else if biased == 0 && kept >> (precision - 1) != 0 {
+        // A denormal rounded up into the normal range.
+        biased = 1;
+    }
+
+    if inexact {
+        exceptions.precision = true;
+        if tiny {
+            exceptions.underflow = true;
+        }
+    }
+    if biased >= max_biased {
+        exceptions.overflow = true;
+        exceptions.precision = true;
+        let to_infinity = match rounding {
+            Rounding::Nearest => true,
+            Rounding::Up => !sign,
+            Rounding::Down => sign,
+            Rounding::Zero => false,
+        };
+        if to_infinity {
+            return None;
+        }
+        return Some(Rounded {
+            biased: max_biased - 1,
+            significand: u64::MAX << (64 - precision),
+        });
+    }
+
+    Some(Rounded {
+        biased,
+        significand: (kept as u64) << (64 - precision),
+    })
+}
+
+fn from_ieee(
+    bits: u64,
+    exponent_bits: u32,
+    fraction_bits: u32,
+    exceptions: &mut Exceptions,
+) -> Extended {
+    let sign = bits >> (exponent_bits + fraction_bits) & 1 == 1;
+    let max_exponent = (1u64 << exponent_bits) - 1;
+    let exponent = (bits >> fraction_bits) & max_exponent;
+    let fraction = bits & ((1u64 << fraction_bits) - 1);
+    let bias = (1i32 << (exponent_bits - 1)) - 1;
+
+    if exponent == max_exponent {
+        let significand = INTEGER_BIT | (fraction << (63 - fraction_bits));
+        let value = Extended {
+            sign,
+            exponent: MAX_EXPONENT,
+            significand,
+        };
+        if value.is_signaling_nan() {
+            exceptions.invalid = true;
+            return value.quiet();
+        }
+        return value;
+    }
+    if exponent == 0 {
+        if fraction == 0 {
+            return Extended::zero(sign);
+        }
+        exceptions.denormal = true;
+        // Denormals of the smaller formats are normal numbers in extended precision.
+        let shift = fraction.leading_zeros();
+        let e = 1 - bias - (shift as i32 - (63 - fraction_bits as i32));
+        return Extended {
+            sign,
+            exponent: (e + EXPONENT_BIAS) as u16,
+            significand: fraction << shift,
+        };
+    }
+    Extended {
+        sign,
+        exponent: (exponent as i32 - bias + EXPONENT_BIAS) as u16,
+        significand: INTEGER_BIT | (fraction << (63 - fraction_bits)),
+    }
+}
@@ -0,0 +1,815 @@
+use std::cmp::Ordering;
+
+use crate::constants::CS;
+use crate::decoder::is_prefix;
+use crate::instruction::Instruction;
+use crate::machine::Machine;
+use crate::memory::physical_address;
+use crate::rm::Rm;
+use crate::simulator::SimulatorOutput;
+
+use extended::{Exceptions, Extended, Rounding};
+
+pub mod extended;
+
+// Status word bits. The exception flags share their positions with the control word masks.
+const INVALID: u16 = 1 << 0;
+const DENORMAL: u16 = 1 << 1;
+const ZERO_DIVIDE: u16 = 1 << 2;
+const OVERFLOW: u16 = 1 << 3;
+const UNDERFLOW: u16 = 1 << 4;
+const PRECISION: u16 = 1 << 5;
+const EXCEPTIONS: u16 = 0x3f;
+const ERROR_SUMMARY: u16 = 1 << 7;
+const C0: u16 = 1 << 8;
+const C1: u16 = 1 << 9;
+const C2: u16 = 1 << 10;
+const C3: u16 = 1 << 14;
+const BUSY: u16 = 1 << 15;
+const TOP_SHIFT: u16 = 11;
+
+// Control word bit that keeps the 8087 from raising its interrupt.
+const INTERRUPT_ENABLE_MASK: u16 = 1 << 7;
+const DEFAULT_CONTROL: u16 = 0x037f;
+
+const ENVIRONMENT_SIZE: usize = 14;
+// Bytes written by fnsave.
+pub const SAVE_SIZE: usize = ENVIRONMENT_SIZE + 8 * 10;
+
+// Instructions that leave the saved instruction and operand pointers alone.
+const CONTROL_INSTRUCTIONS: [&str; 11] = [
+    "fninit", "fnclex", "fneni", "fndisi", "fldcw", "fnstcw", "fnstsw", "fldenv", "fnstenv",
+    "frstor", "fnsave",
+];
+
+// fldpi and friends load these roundings of the constants.
+const CONSTANTS: [(&str, Extended); 5] = [
+    ("fldl2t", constant(0x4000, 0xd49a784bcd1b8afe)),
+    ("fldl2e", constant(0x3fff, 0xb8aa3b295c17f0bc)),
+    ("fldpi", constant(0x4000, 0xc90fdaa22168c235)),
+    ("fldlg2", constant(0x3ffd, 0x9a209a84fbcff799)),
+    ("fldln2", constant(0x3ffe, 0xb17217f7d1cf79ac)),
+];
+
+const fn constant(exponent: u16, significand: u64) -> Extended {
+    Extended {
+        sign: false,
+        exponent,
+        significand,
+    }
+}
+
+// The 8087 numeric coprocessor: eight 80-bit registers used as a stack, with a control word
+// selecting rounding, precision and masked exceptions, and a status word reporting them.
+pub struct Fpu {
+    registers: [Extended; 8],
+    empty: [bool; 8],
+    top: usize,
+    pub control: u16,
+    // The status word without the stack top, which is kept in top.
+    status: u16,
+    // 20-bit addresses of the last instruction and its memory operand, with the low 11 opcode
+    // bits, as saved by fnstenv.
+    instruction_pointer: u32,
+    opcode: u16,
+    operand_pointer: u32,
+}
+
+impl Fpu {
+    pub fn new() -> Fpu {
+        Fpu {
+            registers: [Extended::ZERO; 8],
+            empty: [true; 8],
+            top: 0,
+            control: DEFAULT_CONTROL,
+            status: 0,
+            instruction_pointer: 0,
+            opcode: 0,
+            operand_pointer: 0,
+        }
+    }
+
+    pub fn status_word(&self) -> u16 {
+        (self.status & !(0b111 << TOP_SHIFT)) | ((self.top as u16) << TOP_SHIFT)
+    }
+
+    // Two bits per physical register: valid, zero, special or empty.
+    pub fn tag_word(&self) -> u16 {
+        (0..8).fold(0, |tags, register| {
+            let value = &self.registers[register];
+            let tag = if self.empty[register] {
+                0b11
+            } else if value.is_zero() {
+                0b01
+            } else if value.is_nan() || value.is_infinity() || value.is_denormal() {
+                0b10
+            } else {
+                0b00
+            };
+            tags | (tag << (2 * register))
+        })
+    }
+
+    // st(i), or None when the register is empty.
+    pub fn st(&self, i: usize) -> Option<Extended> {
+        let register = self.physical(i);
+        (!self.empty[register]).then_some(self.registers[register])
+    }
+
+    // Whether an unmasked exception is waiting to interrupt the CPU.
+    pub fn interrupt_requested(&self) -> bool {
+        self.status & ERROR_SUMMARY != 0 && self.control & INTERRUPT_ENABLE_MASK == 0
+    }
+
+    fn physical(&self, i: usize) -> usize {
+        (self.top + i) & 0b111
+    }
+
+    fn rounding(&self) -> Rounding {
+        match (self.control >> 10) & 0b11 {
+            0b00 => Rounding::Nearest,
+            0b01 => Rounding::Down,
+            0b10 => Rounding::Up,
+            _ => Rounding::Zero,
+        }
+    }
+
+    fn precision(&self) -> u32 {
+        match (self.control >> 8) & 0b11 {
+            0b00 => 24,
+            0b10 => 53,
+            _ => 64,
+        }
+    }
+
+    // Reading an empty register is a stack underflow, an invalid operation whose masked
+    // response is the indefinite NaN.
+    fn get(&self, i: usize, exceptions: &mut Exceptions) -> Extended {
+        self.st(i).unwrap_or_else(|| {
+            exceptions.invalid = true;
+            Extended::INDEFINITE
+        })
+    }
+
+    fn set(&mut self, i: usize, value: Extended) {
+        let register = self.physical(i);
+        self.registers[register] = value;
+        self.empty[register] = false;
+    }
+
+    fn pop(&mut self) {
+        self.empty[self.top] = true;
+        self.top = self.physical(1);
+    }
+
+    // Pushing onto a full register is a stack overflow.
+    fn push(&mut self, value: Extended, mut exceptions: Exceptions) {
+        let register = self.physical(7);
+        let value = if self.empty[register] {
+            value
+        } else {
+            exceptions.invalid = true;
+            Extended::INDEFINITE
+        };
+        if self.signal(exceptions) {
+            self.top = register;
+            self.set(0, value);
+        }
+    }
+
+    // Records the exceptions in the status word. Invalid operands, denormals and zero divides
+    // that are not masked leave the destination alone, so the result is stored only when this
+    // returns true.
+    fn signal(&mut self, exceptions: Exceptions) -> bool {
+        let raised = [
+            (exceptions.invalid, INVALID),
+            (exceptions.denormal, DENORMAL),
+            (exceptions.zero_divide, ZERO_DIVIDE),
+            (exceptions.overflow, OVERFLOW),
+            (exceptions.underflow, UNDERFLOW),
+            (exceptions.precision, PRECISION),
+        ]
+        .iter()
+        .filter(|(raised, _)| *raised)
+        .fold(0, |bits, (_, bit)| bits | bit);
+        self.status |= raised;
+
+        let unmasked = raised & !self.control & EXCEPTIONS;
+        if unmasked != 0 {
+            self.status |= ERROR_SUMMARY;
+        }
+        unmasked & (INVALID | DENORMAL | ZERO_DIVIDE) == 0
+    }
+
+    fn set_conditions(&mut self, c3: bool, c2: bool, c1: bool, c0: bool) {
+        self.status &= !(C3 | C2 | C1 | C0);
+        for (set, bit) in [(c3, C3), (c2, C2), (c1, C1), (c0, C0)] {
+            if set {
+                self.status |= bit;
+            }
+        }
+    }
+
+    fn compare(&mut self, left: Extended, right: Extended, mut exceptions: Exceptions) -> bool {
+        let ordering = left.compare(right, &mut exceptions);
+        if !self.signal(exceptions) {
+            return false;
+        }
+        match ordering {
+            Some(Ordering::Greater) => self.set_conditions(false, false, false, false),
+            Some(Ordering::Less) => self.set_conditions(false, false, false, true),
+            Some(Ordering::Equal) => self.set_conditions(true, false, false, false),
+            None => self.set_conditions(true, true, false, true),
+        }
+        true
+    }
+
+    fn arithmetic(
+        &self,
+        operation: &str,
+        left: Extended,
+        right: Extended,
+        exceptions: &mut Exceptions,
+    ) -> Extended {
+        let (rounding, precision) = (self.rounding(), self.precision());
+        match operation {
+            "add" => left.add(right, rounding, precision, exceptions),
+            "mul" => left.multiply(right, rounding, precision, exceptions),
+            "sub" => left.subtract(right, rounding, precision, exceptions),
+            "subr" => right.subtract(left, rounding, precision, exceptions),
+            "div" => left.divide(right, rounding, precision, exceptions),
+            _ => right.divide(left, rounding, precision, exceptions),
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Fpu {
+            instruction_pointer: self.instruction_pointer,
+            opcode: self.opcode,
+            operand_pointer: self.operand_pointer,
+            ..Fpu::new()
+        };
+    }
+
+    fn execute(&mut self, instruction: &Instruction, memory: &mut [u8], address: Option<usize>) {
+        let name = instruction.name;
+        let mut exceptions = Exceptions::default();
+        // fadd, fiadd and faddp all come down to the same operation.
+        let operation = name
+            .trim_start_matches("fi")
+            .trim_start_matches('f')
+            .trim_end_matches('p');
+
+        if let Some(address) = address {
+            let size = instruction.memory_size.unwrap() as usize;
+            let read = |memory: &[u8]| -> Vec<u8> {
+                (0..size)
+                    .map(|i| memory[(address + i) % memory.len()])
+                    .collect()
+            };
+            let write = |memory: &mut [u8], bytes: &[u8]| {
+                for (i, byte) in bytes.iter().enumerate() {
+                    let length = memory.len();
+                    memory[(address + i) % length] = *byte;
+                }
+            };
+            let integer = name.starts_with("fi");
+
+            match name {
+                "fld" | "fild" | "fbld" => {
+                    let value = if integer {
+                        Extended::from_i64(read_integer(&read(memory)))
+                    } else if name == "fbld" {
+                        read_bcd(&read(memory))
+                    } else {
+                        read_real(&read(memory), &mut exceptions)
+                    };
+                    self.push(value, exceptions);
+                }
+                "fst" | "fstp" | "fist" | "fistp" | "fbstp" => {
+                    let value = self.get(0, &mut exceptions);
+                    let bytes = if integer {
+                        self.integer_bytes(value, size, &mut exceptions)
+                    } else if name == "fbstp" {
+                        self.bcd_bytes(value, &mut exceptions)
+                    } else {
+                        self.real_bytes(value, size, &mut exceptions)
+                    };
+                    if self.signal(exceptions) {
+                        write(memory, &bytes);
+                        if name.ends_with('p') {
+                            self.pop();
+                        }
+                    }
+                }
+                "fcom" | "fcomp" | "ficom" | "ficomp" => {
+                    let bytes = read(memory);
+                    let operand = if integer {
+                        Extended::from_i64(read_integer(&bytes))
+                    } else {
+                        read_real(&bytes, &mut exceptions)
+                    };
+                    let value = self.get(0, &mut exceptions);
+                    if self.compare(value, operand, exceptions) && name.ends_with('p') {
+                        self.pop();
+                    }
+                }
+                "fldcw" => {
+                    let bytes = read(memory);
+                    self.control = u16::from_le_bytes([bytes[0], bytes[1]]);
+                }
+                "fnstcw" => write(memory, &self.control.to_le_bytes()),
+                "fnstsw" => write(memory, &self.status_word().to_le_bytes()),
+                "fldenv" => self.load_environment(&read(memory)),
+                "frstor" => self.restore(&read(memory)),
+                "fnstenv" => write(memory, &self.environment()),
+                "fnsave" => {
+                    write(memory, &self.save());
+                    self.reset();
+                }
+                _ => {
+                    let bytes = read(memory);
+                    let operand = if integer {
+                        Extended::from_i64(read_integer(&bytes))
+                    } else {
+                        read_real(&bytes, &mut exceptions)
+                    };
+                    let value = self.get(0, &mut exceptions);
+                    let result = self.arithmetic(operation, value, operand, &mut exceptions);
+                    if self.signal(exceptions) {
+                        self.set(0, result);
+                    }
+                }
+            }
+            return;
+        }
+
+        if let (Some(Rm::FpuReg(destination)), Some(Rm::FpuReg(source))) =
+            (&instruction.destination, &instruction.source)
+        {
+            let left = self.get(*destination, &mut exceptions);
+            let right = self.get(*source, &mut exceptions);
+            let result = self.arithmetic(operation, left, right, &mut exceptions);
+            if self.signal(exceptions) {
+                self.set(*destination, result);
+                if name.ends_with('p') {
+                    self.pop();
+                }
+            }
+            return;
+        }
+
+        if let Some(Rm::FpuReg(i)) = instruction.destination {
+            match name {
+                "fld" => {
+                    let value = self.get(i, &mut exceptions);
+                    self.push(value, exceptions);
+                }
+                "fxch" => {
+                    let value = self.get(i, &mut exceptions);
+                    let top = self.get(0, &mut exceptions);
+                    if self.signal(exceptions) {
+                        self.set(i, top);
+                        self.set(0, value);
+                    }
+                }
+                "fst" | "fstp" => {
+                    let value = self.get(0, &mut exceptions);
+                    if self.signal(exceptions) {
+                        self.set(i, value);
+                        if name == "fstp" {
+                            self.pop();
+                        }
+                    }
+                }
+                "ffree" => self.empty[self.physical(i)] = true,
+                _ => {
+                    let value = self.get(0, &mut exceptions);
+                    let operand = self.get(i, &mut exceptions);
+                    if self.compare(value, operand, exceptions) && name == "fcomp" {
+                        self.pop();
+                    }
+                }
+            }
+            return;
+        }
+
+        match name {
+            "fnop" => {}
+            "fninit" => self.reset(),
+            "fnclex" => self.status &= !(EXCEPTIONS | ERROR_SUMMARY | BUSY),
+            "fneni" => self.control &= !INTERRUPT_ENABLE_MASK,
+            "fndisi" => self.control |= INTERRUPT_ENABLE_MASK,
+            "fdecstp" => self.top = self.physical(7),
+            "fincstp" => self.top = self.physical(1),
+            "fld1" => self.push(Extended::ONE, exceptions),
+            "fldz" => self.push(Extended::ZERO, exceptions),
+            "fcompp" => {
+                let value = self.get(0, &mut exceptions);
+                let operand = self.get(1, &mut exceptions);
+                if self.compare(value, operand, exceptions) {
+                    self.pop();
+                    self.pop();
+                }
+            }
+            "ftst" => {
+                let value = self.get(0, &mut exceptions);
+                self.compare(value, Extended::ZERO, exceptions);
+            }
+            "fxam" => {
+                let register = self.physical(0);
+                let value = self.registers[register];
+                let (c3, c2, c0) = if self.empty[register] {
+                    (true, false, true)
+                } else if value.is_nan() {
+                    (false, false, true)
+                } else if value.is_infinity() {
+                    (false, true, true)
+                } else if value.is_zero() {
+                    (true, false, false)
+                } else if value.is_denormal() {
+                    (true, true, false)
+                } else if value.significand >> 63 == 0 {
+                    // Unnormals are not supported.
+                    (false, false, false)
+                } else {
+                    (false, true, false)
+                };
+                self.set_conditions(c3, c2, value.sign, c0);
+            }
+            "fchs" | "fabs" | "fsqrt" | "frndint" | "f2xm1" => {
+                let value = self.get(0, &mut exceptions);
+                let result = match name {
+                    "fchs" => value.negate(),
+                    "fabs" => value.abs(),
+                    "fsqrt" => value.sqrt(self.rounding(), self.precision(), &mut exceptions),
+                    "frndint" => value.round_integral(self.rounding(), &mut exceptions),
+                    _ => transcendental(&[value], &mut exceptions, |x| {
+                        (x[0] * std::f64::consts::LN_2).exp_m1()
+                    }),
+                };
+                if self.signal(exceptions) {
+                    self.set(0, result);
+                }
+            }
+            "fyl2x" | "fyl2xp1" | "fpatan" => {
+                let x = self.get(0, &mut exceptions);
+                let y = self.get(1, &mut exceptions);
+                let result = match name {
+                    "fyl2x" if x.sign && !x.is_zero() => invalid(&mut exceptions),
+                    "fyl2x" if x.is_zero() => {
+                        exceptions.zero_divide = true;
+                        Extended::infinity(!y.sign)
+                    }
+                    "fyl2x" => transcendental(&[x, y], &mut exceptions, |v| v[1] * v[0].log2()),
+                    "fyl2xp1" => transcendental(&[x, y], &mut exceptions, |v| {
+                        v[1] * v[0].ln_1p() / std::f64::consts::LN_2
+                    }),
+                    _ => transcendental(&[x, y], &mut exceptions, |v| v[1].atan2(v[0])),
+                };
+                if self.signal(exceptions) {
+                    self.set(1, result);
+                    self.pop();
+                }
+            }
+            "fptan" => {
+                // The tangent is returned as the ratio st1 / st0, with st0 = 1.
+                let value = self.get(0, &mut exceptions);
+                let result = transcendental(&[value], &mut exceptions, |x| x[0].tan());
+                if self.st(7).is_some() {
+                    exceptions.invalid = true;
+                }
+                if self.signal(exceptions) {
+                    self.set(0, result);
+                    self.push(Extended::ONE, Exceptions::default());
+                }
+            }
+            "fxtract" => {
+                let value = self.get(0, &mut exceptions);
+                let (exponent, significand) = if value.is_nan() {
+                    (value, value)
+                } else if value.is_infinity() {
+                    (invalid(&mut exceptions), Extended::INDEFINITE)
+                } else if value.is_zero() {
+                    exceptions.zero_divide = true;
+                    (Extended::infinity(true), value)
+                } else {
+                    check_denormal(value, &mut exceptions);
+                    let exponent = value.unbiased_exponent() as i64;
+                    (Extended::from_i64(exponent), value.significand_part())
+                };
+                if self.st(7).is_some() {
+                    exceptions.invalid = true;
+                }
+                if self.signal(exceptions) {
+                    self.set(0, exponent);
+                    self.push(significand, Exceptions::default());
+                }
+            }
+            "fprem" => {
+                let value = self.get(0, &mut exceptions);
+                let divisor = self.get(1, &mut exceptions);
+                if value.is_nan() || divisor.is_nan() {
+                    let result = value.add(divisor, Rounding::Nearest, 64, &mut exceptions);
+                    if self.signal(exceptions) {
+                        self.set(0, result);
+                    }
+                } else if value.is_infinity() || divisor.is_zero() {
+                    exceptions.invalid = true;
+                    if self.signal(exceptions) {
+                        self.set(0, Extended::INDEFINITE);
+                    }
+                } else if value.is_zero() || divisor.is_infinity() {
+                    self.set_conditions(false, false, false, false);
+                } else {
+                    check_denormal(value, &mut exceptions);
+                    check_denormal(divisor, &mut exceptions);
+                    let (remainder, quotient, complete) = value.partial_remainder(divisor);
+                    if self.signal(exceptions) {
+                        self.set(0, remainder);
+                        self.set_conditions(
+                            quotient & 0b10 != 0,
+                            !complete,
+                            quotient & 0b1 != 0,
+                            quotient & 0b100 != 0,
+                        );
+                    }
+                }
+            }
+            "fscale" => {
+                let value = self.get(0, &mut exceptions);
+                let power = self.get(1, &mut exceptions);
+                let result = if value.is_nan() || power.is_nan() {
+                    value.add(power, Rounding::Nearest, 64, &mut exceptions)
+                } else {
+                    match power.to_i64(Rounding::Zero, &mut Exceptions::default()) {
+                        Some(power) => {
+                            // Anything beyond the exponent range saturates the same way.
+                            let power = power.clamp(-0x10000, 0x10000) as i32;
+                            value.scale(power, self.rounding(), &mut exceptions)
+                        }
+                        None => invalid(&mut exceptions),
+                    }
+                };
+                if self.signal(exceptions) {
+                    self.set(0, result);
+                }
+            }
+            _ => {
+                let value = CONSTANTS
+                    .iter()
+                    .find(|(constant, _)| *constant == name)
+                    .map(|(_, value)| *value)
+                    .unwrap_or(Extended::INDEFINITE);
+                self.push(value, exceptions);
+            }
+        }
+    }
+
+    fn real_bytes(&self, value: Extended, size: usize, exceptions: &mut Exceptions) -> Vec<u8> {
+        match size {
+            4 => value
+                .to_f32(self.rounding(), exceptions)
+                .to_le_bytes()
+                .to_vec(),
+            8 => value
+                .to_f64(self.rounding(), exceptions)
+                .to_le_bytes()
+                .to_vec(),
+            _ => value.to_bytes().to_vec(),
+        }
+    }
+
+    // Values out of range store the integer indefinite, the most negative integer.
+    fn integer_bytes(&self, value: Extended, size: usize, exceptions: &mut Exceptions) -> Vec<u8> {
+        let integer = value
+            .to_i64(self.rounding(), exceptions)
+            .filter(|integer| match size {
+                2 => *integer as i16 as i64 == *integer,
+                4 => *integer as i32 as i64 == *integer,
+                _ => true,
+            })
+            .unwrap_or_else(|| {
+                exceptions.invalid = true;
+                i64::MIN >> (64 - 8 * size)
+            });
+        integer.to_le_bytes()[..size].to_vec()
+    }
+
+    // Packed BCD: 18 digits in the first nine bytes, low digits first, and a sign byte.
+    fn bcd_bytes(&self, value: Extended, exceptions: &mut Exceptions) -> Vec<u8> {
+        let Some(integer) = value
+            .to_i64(self.rounding(), exceptions)
+            .filter(|integer| integer.unsigned_abs() < 1_000_000_000_000_000_000)
+        else {
+            exceptions.invalid = true;
+            return vec![0, 0, 0, 0, 0, 0, 0, 0xc0, 0xff, 0xff];
+        };
+        let mut magnitude = integer.unsigned_abs();
+        let mut bytes = Vec::new();
+        for _ in 0..9 {
+            let low = magnitude % 10;
+            let high = (magnitude / 10) % 10;
+            bytes.push(((high << 4) | low) as u8);
+            magnitude /= 100;
+        }
+        bytes.push(if value.sign { 0x80 } else { 0 });
+        bytes
+    }
+
+    // The 94 byte image of fnsave: the environment followed by st(0) to st(7).
+    pub fn save(&self) -> Vec<u8> {
+        let mut bytes = self.environment();
+        for i in 0..8 {
+            bytes.extend(self.registers[self.physical(i)].to_bytes());
+        }
+        bytes
+    }
+
+    pub fn restore(&mut self, bytes: &[u8]) {
+        self.load_environment(bytes);
+        for i in 0..8 {
+            let start = ENVIRONMENT_SIZE + 10 * i;
+            let register = self.physical(i);
+            self.registers[register] = Extended::from_bytes(&bytes[start..start + 10]);
+        }
+    }
+
+    fn environment(&self) -> Vec<u8> {
+        let words = [
+            self.control,
+            self.status_word(),
+            self.tag_word(),
+            self.instruction_pointer as u16,
+            (((self.instruction_pointer >> 16) as u16) << 12) | self.opcode,
+            self.operand_pointer as u16,
+            ((self.operand_pointer >> 16) as u16) << 12,
+        ];
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    fn load_environment(&mut self, bytes: &[u8]) {
+        let word = |i: usize| u16::from_le_bytes([bytes[2 * i], bytes[2 * i + 1]]);
+        self.control = word(0);
+        self.status = word(1);
+        self.top = ((word(1) >> TOP_SHIFT) & 0b111) as usize;
+        for register in 0..8 {
+            self.empty[register] = (word(2) >> (2 * register)) & 0b11 == 0b11;
+        }
+        self.instruction_pointer = word(3) as u32 | (((word(4) >> 12) as u32) << 16);
+        self.opcode = word(4) & 0x7ff;
+        self.operand_pointer = word(5) as u32 | (((word(6) >> 12) as u32) << 16);
+    }
+}
+
+impl Default for Fpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn invalid(exceptions: &mut Exceptions) -> Extended {
+    exceptions.invalid = true;
+    Extended::INDEFINITE
+}
+
+fn check_denormal(value: Extended, exceptions: &mut Exceptions) {
+    if value.is_denormal() {
+        exceptions.denormal = true;
+    }
+}
+
+// The transcendental instructions are computed in double precision, which is close to but not
+// exactly what the 8087 microcode returns.
+fn transcendental(
+    operands: &[Extended],
+    exceptions: &mut Exceptions,
+    function: impl Fn(&[f64]) -> f64,
+) -> Extended {
+    if let Some(nan) = operands.iter().find(|operand| operand.is_nan()) {
+        if nan.is_signaling_nan() {
+            exceptions.invalid = true;
+        }
+        return Extended::INDEFINITE;
+    }
+    for operand in operands {
+        check_denormal(*operand, exceptions);
+    }
+    let values: Vec<f64> = operands
+        .iter()
+        .map(|operand| operand.to_f64_value())
+        .collect();
+    let result = function(&values);
+    if result.is_nan() {
+        return invalid(exceptions);
+    }
+    exceptions.precision = true;
+    Extended::from_f64_value(result)
+}
+
+fn read_real(bytes: &[u8], exceptions: &mut Exceptions) -> Extended {
+    match bytes.len() {
+        4 => Extended::from_f32(u32::from_le_bytes(bytes.try_into().unwrap()), exceptions),
+        8 => Extended::from_f64(u64::from_le_bytes(bytes.try_into().unwrap()), exceptions),
+        _ => Extended::from_bytes(bytes),
+    }
+}
+
+// Sign-extends a little-endian integer of 2, 4 or 8 bytes.
+fn read_integer(bytes: &[u8]) -> i64 {
+    let mut extended = [if bytes[bytes.len() - 1] & 0x80 != 0 {
+        0xff
+    } else {
+        0
+    }; 8];
+    extended[..bytes.len()].copy_from_slice(bytes);
+    i64::from_le_bytes(extended)
+}
+
+fn read_bcd(bytes: &[u8]) -> Extended {
+    let magnitude = bytes[..9].iter().rev().fold(0i64, |value, byte| {
+        value * 100 + ((byte >> 4) * 10 + (byte & 0xf)) as i64
+    });
+    let value = Extended::from_i64(magnitude);
+    if bytes[9] & 0x80 != 0 {
+        value.negate()
+    } else {
+        value
+    }
+}
+
+// Runs an escape instruction. Without a coprocessor the CPU only computes the address of the
+// memory operand. With one, the 8087 watches the bus and does the work; its own clocks overlap
+// with the CPU and are not counted. Unmasked exceptions interrupt through the NMI as on the PC.
+pub fn execute_fpu(
+    machine: &mut Machine,
+    instruction: &Instruction,
+) -> Result<SimulatorOutput, String> {
+    let memory_operand = instruction.memory_operand().cloned();
+    let number_of_cycles = match &memory_operand {
+        Some(rm) => 8 + rm.estimate_cycles(),
+        None => 2,
+    };
+    let output = SimulatorOutput {
+        number_of_cycles,
+        ..Default::default()
+    };
+    let Some(mut fpu) = machine.fpu.take() else {
+        return Ok(output);
+    };
+
+    let address = memory_operand.map(|rm| {
+        let segment = machine.segment_registers[instruction.segment(&rm)];
+        physical_address(
+            segment,
+            rm.calculate_memory_index(&machine.simulation_registers),
+        )
+    });
+    if !CONTROL_INSTRUCTIONS.contains(&instruction.name) {
+        let ip = machine.ip.wrapping_sub(instruction.length);
+        let cs = machine.segment_registers[CS];
+        // The escape follows any prefixes.
+        let mut escape = ip;
+        while is_prefix(machine.memory[physical_address(cs, escape)]) {
+            escape = escape.wrapping_add(1);
+        }
+        let opcode = machine.memory[physical_address(cs, escape)];
+        let modrm = machine.memory[physical_address(cs, escape.wrapping_add(1))];
+        fpu.instruction_pointer = physical_address(cs, ip) as u32;
+        fpu.opcode = (((opcode & 0b111) as u16) << 8) | modrm as u16;
+        if let Some(address) = address {
+            fpu.operand_pointer = address as u32;
+        }
+    }
+
+    let requested = fpu.interrupt_requested();
+    fpu.execute(instruction, &mut machine.memory, address);
+    if fpu.interrupt_requested() && !requested {
+        machine.raise_nmi();
+    }
+    machine.fpu = Some(fpu);
+    Ok(output)
+}
+
+// The non-empty stack registers and the control and status words, for the end of a trace.
+pub fn format_state(fpu: &Fpu) -> String {
+    let mut text = format!(
+        "{:>8}: {:#06x}\n{:>8}: {:#06x}\n",
+        "cw",
+        fpu.control,
+        "sw",
+        fpu.status_word()
+    );
+    for i in 0..8 {
+        if let Some(value) = fpu.st(i) {
+            text += &format!(
+                "{:>8}: {:04x} {:016x} ({})\n",
+                format!("st{}", i),
+                ((value.sign as u16) << 15) | value.exponent,
+                value.significand,
+                value.to_f64_value()
+            );
+        }
+    }
+    text
+}
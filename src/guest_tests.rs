@@ -0,0 +1,361 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::constants::{CS, DS, ES, SS};
+use crate::interrupts::dos::flush_stdout;
+use crate::interrupts::install_interrupt_vectors;
+use crate::machine::{Cpu, Machine};
+use crate::machine_config::{
+    IniEntry, Register, Setting, apply_settings, flag, parse_address, parse_bool, parse_flag,
+    parse_ini, parse_machine_setting, parse_register, parse_segment_offset, register_value,
+};
+use crate::memory::{MEMORY_SIZE, physical_address};
+use crate::symbols::{Symbols, parse_offset};
+
+// Instructions a test may run before it counts as not stopping.
+const DEFAULT_MAX_INSTRUCTIONS: u64 = 1_000_000;
+
+// Unit tests for assembly routines. A spec file names the program and holds any number of tests:
+//
+//     program = sum.bin
+//     load = 1000:0000
+//     symbols = sum.lst
+//
+//     [small]
+//     entry = sum
+//     stop = done
+//
+//     [small.registers]
+//     cx = 3
+//     [small.memory]
+//     load = "numbers.bin@1000:0100"
+//
+//     [small.expect]
+//     ax = 6
+//     Z = 0
+//     1000:0200 = "06 00"
+//     max-clocks = 200
+//
+// Each test runs on a fresh machine with the program loaded and every segment register pointing
+// at its segment. It starts at entry, the load address by default, and ends when execution
+// reaches stop, a hlt or the program exits. [name.registers], [name.flags] and [name.memory]
+// take the settings of --machine files. [name.expect] checks registers, flags, byte ranges and
+// clocks.
+struct Spec {
+    program: PathBuf,
+    load: (u16, u16),
+    cpu: Cpu,
+    symbols: Option<PathBuf>,
+    tests: Vec<Test>,
+}
+
+enum Stop {
+    At(String),
+    Exit,
+}
+
+struct Test {
+    name: String,
+    entry: Option<String>,
+    stop: Stop,
+    max_instructions: u64,
+    settings: Vec<Setting>,
+    registers: Vec<(String, Register, u16)>,
+    flags: Vec<(char, bool)>,
+    memory: Vec<(usize, Vec<u8>)>,
+    max_clocks: Option<u64>,
+}
+
+impl Test {
+    fn new(name: &str) -> Test {
+        Test {
+            name: name.to_string(),
+            entry: None,
+            stop: Stop::Exit,
+            max_instructions: DEFAULT_MAX_INSTRUCTIONS,
+            settings: Vec::new(),
+            registers: Vec::new(),
+            flags: Vec::new(),
+            memory: Vec::new(),
+            max_clocks: None,
+        }
+    }
+}
+
+// Runs every spec file, and every .spec file in a directory. Returns whether all tests passed.
+pub fn run_guest_tests(paths: &[String]) -> Result<bool, String> {
+    let mut files = Vec::new();
+    for path in paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            let mut specs: Vec<PathBuf> = fs::read_dir(path)
+                .map_err(|error| format!("{}: {}", path.display(), error))?
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    path.extension()
+                        .is_some_and(|extension| extension == "spec")
+                })
+                .collect();
+            specs.sort();
+            files.extend(specs);
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+
+    let (mut passed, mut failed) = (0, 0);
+    for file in files {
+        let path = file.to_string_lossy();
+        println!("{}", path);
+        let text = fs::read_to_string(&file).map_err(|error| format!("{}: {}", path, error))?;
+        let spec = parse_spec(&path, &text)?;
+        let program = fs::read(&spec.program)
+            .map_err(|error| format!("{}: {}", spec.program.display(), error))?;
+        let base = physical_address(spec.load.0, spec.load.1);
+        let symbols = match &spec.symbols {
+            Some(symbols) => {
+                let text = fs::read_to_string(symbols)
+                    .map_err(|error| format!("{}: {}", symbols.display(), error))?;
+                Symbols::load(&text, base)?
+            }
+            None => Symbols::empty(base),
+        };
+
+        for test in &spec.tests {
+            let (result, failures) = run_test(&spec, &program, &symbols, test)?;
+            flush_stdout();
+            if failures.is_empty() {
+                passed += 1;
+                println!("  PASS {} ({})", test.name, result);
+            } else {
+                failed += 1;
+                println!("  FAIL {} ({})", test.name, result);
+                for failure in failures {
+                    println!("      {}", failure);
+                }
+            }
+        }
+    }
+    println!("{} passed, {} failed", passed, failed);
+    Ok(failed == 0)
+}
+
+// A description of how the run went, and the expectations it failed.
+fn run_test(
+    spec: &Spec,
+    program: &[u8],
+    symbols: &Symbols,
+    test: &Test,
+) -> Result<(String, Vec<String>), String> {
+    let resolve = |text: &str| {
+        symbols
+            .resolve(text)
+            .ok_or_else(|| format!("{}: Unknown location {}", test.name, text))
+    };
+    let (segment, offset) = spec.load;
+    let mut machine = Machine::new();
+    machine.cpu = spec.cpu;
+    install_interrupt_vectors(&mut machine);
+    machine.load(segment, offset, program);
+    for register in [CS, DS, ES, SS] {
+        machine.segment_registers[register] = segment;
+    }
+    machine.ip = match &test.entry {
+        Some(entry) => offset_in(segment, resolve(entry)?)
+            .ok_or_else(|| format!("{}: {} is outside the code segment", test.name, entry))?,
+        None => offset,
+    };
+    apply_settings(&mut machine, &test.settings)
+        .map_err(|error| format!("{}: {}", test.name, error))?;
+    let stop = match &test.stop {
+        Stop::At(text) => Some(resolve(text)?),
+        Stop::Exit => None,
+    };
+
+    let mut failures = Vec::new();
+    let mut instructions = 0;
+    loop {
+        if let Err(error) = machine.step() {
+            failures.push(error);
+            break;
+        }
+        instructions += 1;
+        let address = physical_address(machine.segment_registers[CS], machine.ip);
+        if machine.exit_code.is_some() || machine.halted || Some(address) == stop {
+            break;
+        }
+        if instructions == test.max_instructions {
+            failures.push(format!("Did not stop within {} instructions", instructions));
+            break;
+        }
+    }
+    let result = format!(
+        "{} instructions, {} clocks",
+        instructions, machine.current_clock
+    );
+
+    for (name, register, expected) in &test.registers {
+        let value = register_value(&machine, register);
+        if value != *expected {
+            failures.push(format!(
+                "{}: expected {:#06x}, got {:#06x}",
+                name, expected, value
+            ));
+        }
+    }
+    let mut flags = machine.flags.clone();
+    for (letter, expected) in &test.flags {
+        let value = *flag(&mut flags, *letter);
+        if value != *expected {
+            let state = |set: bool| if set { "set" } else { "clear" };
+            failures.push(format!(
+                "flag {}: expected {}, got {}",
+                letter,
+                state(*expected),
+                state(value)
+            ));
+        }
+    }
+    for (address, expected) in &test.memory {
+        let value: Vec<u8> = (0..expected.len())
+            .map(|i| machine.memory[(address + i) % MEMORY_SIZE])
+            .collect();
+        if value != *expected {
+            failures.push(format!(
+                "memory {:05x}: expected {}, got {}",
+                address,
+                hex_bytes(expected),
+                hex_bytes(&value)
+            ));
+        }
+    }
+    if let Some(max_clocks) = test.max_clocks
+        && machine.current_clock > max_clocks
+    {
+        failures.push(format!(
+            "clocks: {}, expected at most {}",
+            machine.current_clock, max_clocks
+        ));
+    }
+    Ok((result, failures))
+}
+
+fn offset_in(segment: u16, address: usize) -> Option<u16> {
+    let offset = address.checked_sub(physical_address(segment, 0))?;
+    u16::try_from(offset).ok()
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    bytes.join(" ")
+}
+
+fn parse_spec(path: &str, text: &str) -> Result<Spec, String> {
+    let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+    let mut spec = Spec {
+        program: PathBuf::new(),
+        load: (0, 0),
+        cpu: Cpu::Intel8086,
+        symbols: None,
+        tests: Vec::new(),
+    };
+    for entry in parse_ini(path, text)? {
+        let error = |message: &str| entry.error(path, message);
+        let (name, part) = match entry.section.rsplit_once('.') {
+            Some((name, part)) => (name, part),
+            None => (entry.section.as_str(), ""),
+        };
+        if name.is_empty() {
+            parse_spec_setting(&mut spec, &entry, directory).map_err(|message| error(&message))?;
+            continue;
+        }
+        if spec.tests.last().is_none_or(|test| test.name != name) {
+            if spec.tests.iter().any(|test| test.name == name) {
+                return Err(error(&format!(
+                    "The sections of test {} are split up",
+                    name
+                )));
+            }
+            spec.tests.push(Test::new(name));
+        }
+        let test = spec.tests.last_mut().unwrap();
+        let result = match part {
+            "" => parse_test_setting(test, &entry),
+            "expect" => parse_expectation(test, &entry),
+            _ => parse_machine_setting(part, &entry.key, &entry.value, directory)
+                .map(|setting| test.settings.push(setting)),
+        };
+        result.map_err(|message| error(&message))?;
+    }
+    if spec.program.as_os_str().is_empty() {
+        return Err(format!("{}: Missing program = <file>", path));
+    }
+    Ok(spec)
+}
+
+fn parse_spec_setting(spec: &mut Spec, entry: &IniEntry, directory: &Path) -> Result<(), String> {
+    let value = entry.value.as_str();
+    match entry.key.as_str() {
+        "program" => spec.program = directory.join(value),
+        "symbols" => spec.symbols = Some(directory.join(value)),
+        "cpu" => spec.cpu = Cpu::parse(value).ok_or_else(|| format!("Unknown cpu {}", value))?,
+        "load" => {
+            spec.load = parse_segment_offset(value)
+                .ok_or_else(|| format!("Expected segment:offset, got {}", value))?
+        }
+        key => return Err(format!("Unknown setting {}", key)),
+    }
+    Ok(())
+}
+
+fn parse_test_setting(test: &mut Test, entry: &IniEntry) -> Result<(), String> {
+    let value = entry.value.as_str();
+    match entry.key.as_str() {
+        "entry" => test.entry = Some(value.to_string()),
+        "stop" if value == "exit" => test.stop = Stop::Exit,
+        "stop" => test.stop = Stop::At(value.to_string()),
+        "max-instructions" => {
+            test.max_instructions = value
+                .parse()
+                .ok()
+                .filter(|count| *count > 0)
+                .ok_or_else(|| format!("Invalid instruction count {}", value))?
+        }
+        key => return Err(format!("Unknown setting {}", key)),
+    }
+    Ok(())
+}
+
+// Registers, flags, `address = "bytes"` and max-clocks.
+fn parse_expectation(test: &mut Test, entry: &IniEntry) -> Result<(), String> {
+    let (key, value) = (entry.key.as_str(), entry.value.as_str());
+    let invalid = || format!("Invalid value {} for {}", value, key);
+    if key == "max-clocks" {
+        test.max_clocks = Some(value.parse().map_err(|_| invalid())?);
+    } else if let Some(register) = parse_register(key) {
+        let expected = parse_offset(value).filter(|value| *value <= 0xffff);
+        test.registers.push((
+            key.to_ascii_lowercase(),
+            register,
+            expected.ok_or_else(invalid)? as u16,
+        ));
+    } else if let Some(letter) = parse_flag(key) {
+        test.flags
+            .push((letter, parse_bool(value).ok_or_else(invalid)?));
+    } else if let Some(address) = parse_address(key) {
+        let bytes: Option<Vec<u8>> = value
+            .split_whitespace()
+            .map(|byte| u8::from_str_radix(byte.trim_start_matches("0x"), 16).ok())
+            .collect();
+        test.memory.push((
+            address,
+            bytes
+                .filter(|bytes| !bytes.is_empty())
+                .ok_or_else(invalid)?,
+        ));
+    } else {
+        return Err(format!("Unknown expectation {}", key));
+    }
+    Ok(())
+}
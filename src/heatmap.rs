@@ -0,0 +1,171 @@
+use std::fmt::Write;
+
+use crate::machine::ExecutedInstruction;
+use crate::memory::{MEMORY_SIZE, physical_address};
+use crate::shadow::Location;
+
+// How many addresses, lines and regions the report lists.
+const HOT_SPOTS: usize = 20;
+const LINE_SIZE: usize = 16;
+// The image has one pixel for each byte of memory, 1024 bytes to a row.
+const IMAGE_WIDTH: usize = 1024;
+
+// Data reads and writes of each byte of memory, stack included. Instruction fetches aren't
+// counted, they would drown out everything else.
+pub struct Heatmap {
+    reads: Vec<u32>,
+    writes: Vec<u32>,
+}
+
+impl Heatmap {
+    pub fn new() -> Heatmap {
+        Heatmap {
+            reads: vec![0; MEMORY_SIZE],
+            writes: vec![0; MEMORY_SIZE],
+        }
+    }
+
+    pub fn record(&mut self, executed: &ExecutedInstruction) {
+        let accesses = &executed.memory_accesses;
+        for (locations, counts) in [
+            (&accesses.reads, &mut self.reads),
+            (&accesses.writes, &mut self.writes),
+        ] {
+            for location in locations {
+                if let Location::Memory {
+                    segment,
+                    offset,
+                    length,
+                } = location
+                {
+                    for i in 0..*length {
+                        let count = &mut counts[physical_address(*segment, offset.wrapping_add(i))];
+                        *count = count.saturating_add(1);
+                    }
+                }
+            }
+        }
+    }
+
+    // Reads and writes of each 16 byte line that was accessed at all.
+    fn lines(&self) -> Vec<(usize, u64, u64)> {
+        let sum = |counts: &[u32]| counts.iter().map(|count| *count as u64).sum::<u64>();
+        self.reads
+            .chunks(LINE_SIZE)
+            .zip(self.writes.chunks(LINE_SIZE))
+            .enumerate()
+            .map(|(line, (reads, writes))| (line * LINE_SIZE, sum(reads), sum(writes)))
+            .filter(|(_, reads, writes)| reads + writes > 0)
+            .collect()
+    }
+
+    // The busiest addresses and lines, then the ranges of memory written, where a write past
+    // the end of an array shows up as a region of its own.
+    pub fn report(&self) -> String {
+        let total = |counts: &[u32]| counts.iter().map(|count| *count as u64).sum::<u64>();
+        let touched = (0..MEMORY_SIZE)
+            .filter(|address| self.reads[*address] > 0 || self.writes[*address] > 0)
+            .count();
+        let mut text = format!(
+            "Memory accesses: {} byte reads, {} byte writes, {} bytes touched\n",
+            total(&self.reads),
+            total(&self.writes),
+            touched
+        );
+
+        text += "\nHot addresses:\n";
+        text += "  address      reads     writes\n";
+        let mut addresses: Vec<usize> = (0..MEMORY_SIZE)
+            .filter(|address| self.reads[*address] > 0 || self.writes[*address] > 0)
+            .collect();
+        addresses.sort_by_key(|address| {
+            let count = self.reads[*address] as u64 + self.writes[*address] as u64;
+            (std::cmp::Reverse(count), *address)
+        });
+        for address in addresses.into_iter().take(HOT_SPOTS) {
+            writeln!(
+                text,
+                "  {:05x}  {:>9}  {:>9}",
+                address, self.reads[address], self.writes[address]
+            )
+            .unwrap();
+        }
+
+        text += "\nHot lines:\n";
+        text += "  line             reads     writes\n";
+        let mut lines = self.lines();
+        lines.sort_by_key(|(line, reads, writes)| (std::cmp::Reverse(reads + writes), *line));
+        for (line, reads, writes) in lines.into_iter().take(HOT_SPOTS) {
+            writeln!(
+                text,
+                "  {:05x}-{:05x}  {:>9}  {:>9}",
+                line,
+                line + LINE_SIZE - 1,
+                reads,
+                writes
+            )
+            .unwrap();
+        }
+
+        text += "\nWritten regions:\n";
+        text += "  region          writes\n";
+        let regions = self.written_regions();
+        for (first, last, writes) in regions.iter().take(HOT_SPOTS) {
+            writeln!(text, "  {:05x}-{:05x}  {:>9}", first, last, writes).unwrap();
+        }
+        if regions.len() > HOT_SPOTS {
+            writeln!(text, "  and {} more", regions.len() - HOT_SPOTS).unwrap();
+        }
+        text
+    }
+
+    // Runs of written bytes with their first and last address and the writes to them.
+    fn written_regions(&self) -> Vec<(usize, usize, u64)> {
+        let mut regions: Vec<(usize, usize, u64)> = Vec::new();
+        for (address, writes) in self.writes.iter().enumerate() {
+            if *writes == 0 {
+                continue;
+            }
+            match regions.last_mut() {
+                Some((_, last, total)) if *last + 1 == address => {
+                    *last = address;
+                    *total += *writes as u64;
+                }
+                _ => regions.push((address, address, *writes as u64)),
+            }
+        }
+        regions
+    }
+
+    // One row for each line that was accessed, for a spreadsheet.
+    pub fn csv(&self) -> String {
+        let mut text = String::from("line,reads,writes\n");
+        for (line, reads, writes) in self.lines() {
+            writeln!(text, "0x{:05x},{},{}", line, reads, writes).unwrap();
+        }
+        text
+    }
+
+    // A binary PPM with one pixel for each byte of memory, 1024 bytes to a row. Reads are
+    // green and writes red, brighter the more often they happened on a log scale.
+    pub fn image(&self) -> Vec<u8> {
+        let brightness = |counts: &[u32]| {
+            let most = counts.iter().copied().max().unwrap_or(0).max(1) as f64;
+            move |count: u32| (255.0 * (count as f64).ln_1p() / most.ln_1p()).round() as u8
+        };
+        let red = brightness(&self.writes);
+        let green = brightness(&self.reads);
+        let mut image =
+            format!("P6\n{} {}\n255\n", IMAGE_WIDTH, MEMORY_SIZE / IMAGE_WIDTH).into_bytes();
+        for (reads, writes) in self.reads.iter().zip(&self.writes) {
+            image.extend([red(*writes), green(*reads), 0]);
+        }
+        image
+    }
+}
+
+impl Default for Heatmap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt::Display;
+
+use crate::formatter::nasm_formatter::NasmFormatter;
+use crate::formatter::{FormatOptions, Formatter};
+use crate::{constants::RETURN_INSTRUCTIONS, rm::Rm};
+
+#[derive(Clone)]
+pub struct Instruction {
+    pub name: &'static str,
+    pub w: usize,
+    pub destination: Option<Rm>,
+    pub source: Option<Rm>,
+    pub immediate_value: Option<i16>,
+    // The nesting level of enter, or the segment of a direct far call or jmp.
+    pub second_immediate: Option<i16>,
+    // Byte immediates sign-extended to a word are shown signed, all others unsigned.
+    pub signed_immediate: bool,
+    // Size in bytes of an 8087 memory operand, which w cannot express.
+    pub memory_size: Option<u16>,
+    // Encodings missing from Intel's manuals that the 8086 still executes.
+    pub undocumented: bool,
+    // mov between the accumulator and a direct address has a shorter encoding of its own.
+    pub accumulator_form: bool,
+    // The segment register of a segment override prefix.
+    pub segment_override: Option<usize>,
+    // rep, repe or repne.
+    pub repeat: Option<&'static str>,
+    // Calls, jumps and returns that load cs as well as ip.
+    pub far: bool,
+    pub length: u16,
+}
+
+impl Instruction {
+    pub fn is_jump(&self) -> bool {
+        RETURN_INSTRUCTIONS.iter().any(|i| i.1 == self.name)
+    }
+
+    // Jumps and calls whose immediate is a displacement from the next instruction.
+    pub fn is_relative(&self) -> bool {
+        self.is_jump()
+            || (matches!(self.name, "jmp" | "call") && self.destination.is_none() && !self.far)
+    }
+
+    // Every 8087 mnemonic starts with an f.
+    pub fn is_fpu(&self) -> bool {
+        self.name.starts_with('f')
+    }
+
+    // Read-modify-write instructions access their memory operand twice.
+    pub fn memory_transfers(&self) -> u16 {
+        match (&self.destination, &self.source) {
+            // The CPU reads the first word of an 8087 operand, the 8087 does the rest.
+            (Some(destination), _) if destination.is_memory() && self.is_fpu() => 1,
+            // A far pointer is two words.
+            (Some(destination), _) if destination.is_memory() && self.far => 2,
+            (Some(destination), _) if destination.is_memory() => match self.name {
+                "mov" | "cmp" | "test" | "mul" | "imul" | "div" | "idiv" | "push" | "pop"
+                | "call" | "jmp" => 1,
+                _ => 2,
+            },
+            // lea only computes the address.
+            (_, Some(source)) if source.is_memory() => match self.name {
+                "lea" => 0,
+                "lds" | "les" | "xchg" => 2,
+                _ => 1,
+            },
+            _ => 0,
+        }
+    }
+
+    // The segment register a memory operand is addressed with.
+    pub fn segment(&self, rm: &Rm) -> usize {
+        self.segment_override.unwrap_or(rm.default_segment())
+    }
+
+    pub fn memory_operand(&self) -> Option<&Rm> {
+        [&self.destination, &self.source]
+            .into_iter()
+            .flatten()
+            .find(|rm| rm.is_memory())
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            NasmFormatter.format(self, &FormatOptions::default())
+        )
+    }
+}
@@ -0,0 +1,80 @@
+use std::fs;
+
+use crate::alignment::AlignmentLint;
+use crate::constants::CS;
+use crate::coverage::Coverage;
+use crate::heatmap::Heatmap;
+use crate::machine::{ExecutedInstruction, Machine};
+use crate::profile::Profile;
+
+// Analyses that watch every executed instruction and report once the run is over.
+#[derive(Default)]
+pub struct Instruments {
+    pub profile: Option<Profile>,
+    // Where --folded writes the call stacks of the profile.
+    pub folded: Option<String>,
+    pub coverage: Option<Coverage>,
+    // Where --lcov writes the coverage, and the source file it names.
+    pub lcov: Option<(String, String)>,
+    pub heatmap: Option<Heatmap>,
+    // Where --heatmap-csv and --heatmap-image write the heatmap.
+    pub heatmap_csv: Option<String>,
+    pub heatmap_image: Option<String>,
+    pub alignment: Option<AlignmentLint>,
+}
+
+impl Instruments {
+    // before is cs:ip before the step, machine is the state after it.
+    pub fn record(
+        &mut self,
+        before: (u16, u16),
+        executed: &ExecutedInstruction,
+        machine: &Machine,
+    ) {
+        let after = (machine.segment_registers[CS], machine.ip);
+        if let Some(profile) = &mut self.profile {
+            profile.record(before, executed, after, &machine.call_stack);
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(executed, after);
+        }
+        if let Some(heatmap) = &mut self.heatmap {
+            heatmap.record(executed);
+        }
+        if let Some(alignment) = &mut self.alignment {
+            alignment.record(executed);
+        }
+    }
+
+    // Reports go to stderr, stdout belongs to the program or the trace.
+    pub fn report(&self) -> Result<(), String> {
+        let write = |path: &str, contents: &[u8]| {
+            fs::write(path, contents).map_err(|error| format!("{}: {}", path, error))
+        };
+        if let Some(profile) = &self.profile {
+            eprint!("{}", profile.report());
+            if let Some(path) = &self.folded {
+                write(path, profile.folded_stacks().as_bytes())?;
+            }
+        }
+        if let Some(coverage) = &self.coverage {
+            eprint!("{}", coverage.annotated());
+            if let Some((path, source_path)) = &self.lcov {
+                write(path, coverage.lcov(source_path).as_bytes())?;
+            }
+        }
+        if let Some(heatmap) = &self.heatmap {
+            eprint!("{}", heatmap.report());
+            if let Some(path) = &self.heatmap_csv {
+                write(path, heatmap.csv().as_bytes())?;
+            }
+            if let Some(path) = &self.heatmap_image {
+                write(path, &heatmap.image())?;
+            }
+        }
+        if let Some(alignment) = &self.alignment {
+            eprint!("{}", alignment.report());
+        }
+        Ok(())
+    }
+}
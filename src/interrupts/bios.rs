@@ -0,0 +1,195 @@
+use std::collections::VecDeque;
+
+use crate::constants::{AH, AL, AX, BH, CX, DH, DL, DX};
+use crate::machine::Machine;
+
+use super::dos::{read_stdin_byte, write_stdout};
+use super::{register, set_register};
+
+const COLUMNS: u16 = 80;
+const ROWS: u16 = 25;
+
+// The PIT runs at a quarter of the 4.77 MHz CPU clock and overflows every 65536 counts.
+const CLOCKS_PER_TICK: u64 = 4 * 65536;
+const TICKS_PER_DAY: u64 = 0x1800b0;
+
+// Scan codes for printable ASCII characters starting at space, US keyboard layout.
+const SCAN_CODES: [u8; 95] = [
+    0x39, 0x02, 0x28, 0x04, 0x05, 0x06, 0x08, 0x28, 0x0a, 0x0b, 0x09, 0x0d, 0x33, 0x0c, 0x34, 0x35,
+    0x0b, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x27, 0x27, 0x33, 0x0d, 0x34, 0x35,
+    0x03, 0x1e, 0x30, 0x2e, 0x20, 0x12, 0x21, 0x22, 0x23, 0x17, 0x24, 0x25, 0x26, 0x32, 0x31, 0x18,
+    0x19, 0x10, 0x13, 0x1f, 0x14, 0x16, 0x2f, 0x11, 0x2d, 0x15, 0x2c, 0x1a, 0x2b, 0x1b, 0x07, 0x0c,
+    0x29, 0x1e, 0x30, 0x2e, 0x20, 0x12, 0x21, 0x22, 0x23, 0x17, 0x24, 0x25, 0x26, 0x32, 0x31, 0x18,
+    0x19, 0x10, 0x13, 0x1f, 0x14, 0x16, 0x2f, 0x11, 0x2d, 0x15, 0x2c, 0x1a, 0x2b, 0x1b, 0x29,
+];
+
+pub struct Bios {
+    pub video_mode: u8,
+    pub cursor_row: u16,
+    pub cursor_column: u16,
+    // Scripted keystrokes, stdin is used when there are none.
+    pub keys: Option<VecDeque<u8>>,
+    pub pending_key: Option<u16>,
+    pub tick_offset: i64,
+}
+
+impl Bios {
+    pub fn new() -> Bios {
+        Bios {
+            video_mode: 3,
+            cursor_row: 0,
+            cursor_column: 0,
+            keys: None,
+            pending_key: None,
+            tick_offset: 0,
+        }
+    }
+
+    fn next_key(&mut self) -> Option<u16> {
+        if let Some(key) = self.pending_key.take() {
+            return Some(key);
+        }
+        let character = match &mut self.keys {
+            Some(keys) => keys.pop_front()?,
+            None => read_stdin_byte()?,
+        };
+        Some(key_code(character))
+    }
+
+    fn teletype(&mut self, character: u8) {
+        match character {
+            b'\r' => self.cursor_column = 0,
+            b'\n' => self.cursor_row += 1,
+            0x08 => self.cursor_column = self.cursor_column.saturating_sub(1),
+            0x07 => {}
+            _ => {
+                self.cursor_column += 1;
+                if self.cursor_column == COLUMNS {
+                    self.cursor_column = 0;
+                    self.cursor_row += 1;
+                }
+            }
+        }
+        self.cursor_row = self.cursor_row.min(ROWS - 1);
+        write_stdout(&[character]);
+    }
+}
+
+impl Default for Bios {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Scan code in the high byte, ASCII in the low byte, like the BIOS keyboard buffer.
+fn key_code(character: u8) -> u16 {
+    let (character, scan_code) = match character {
+        b'\n' | b'\r' => (b'\r', 0x1c),
+        0x08 => (0x08, 0x0e),
+        b'\t' => (b'\t', 0x0f),
+        0x1b => (0x1b, 0x01),
+        b' '..=b'~' => (character, SCAN_CODES[(character - b' ') as usize]),
+        _ => (character, 0),
+    };
+    ((scan_code as u16) << 8) | character as u16
+}
+
+pub fn video_interrupt(machine: &mut Machine) -> Result<(), String> {
+    let function = register(machine, 0, AH);
+    match function {
+        // Set video mode, clears the screen.
+        0x00 => {
+            machine.bios.video_mode = register(machine, 0, AL) as u8 & 0x7f;
+            machine.bios.cursor_row = 0;
+            machine.bios.cursor_column = 0;
+        }
+        // Set cursor position, only page 0 is tracked.
+        0x02 => {
+            machine.bios.cursor_row = register(machine, 0, DH).min(ROWS - 1);
+            machine.bios.cursor_column = register(machine, 0, DL).min(COLUMNS - 1);
+        }
+        // Get cursor position and shape.
+        0x03 => {
+            set_register(machine, 0, DH, machine.bios.cursor_row);
+            set_register(machine, 0, DL, machine.bios.cursor_column);
+            set_register(machine, 1, CX, 0x0607);
+        }
+        // Teletype output of al.
+        0x0e => machine.bios.teletype(register(machine, 0, AL) as u8),
+        // Get video mode.
+        0x0f => {
+            set_register(machine, 0, AL, machine.bios.video_mode as u16);
+            set_register(machine, 0, AH, COLUMNS);
+            set_register(machine, 0, BH, 0);
+        }
+        _ => {
+            return Err(format!(
+                "Video function {:#04x} is not supported by the simulator",
+                function
+            ));
+        }
+    }
+    Ok(())
+}
+
+pub fn keyboard_interrupt(machine: &mut Machine) -> Result<(), String> {
+    let function = register(machine, 0, AH);
+    match function {
+        // Wait for a key and remove it from the buffer.
+        0x00 => {
+            let key = machine
+                .bios
+                .next_key()
+                .ok_or_else(|| String::from("Keyboard input is exhausted"))?;
+            set_register(machine, 1, AX, key);
+        }
+        // Check for a key without removing it, zf is set when there is none.
+        0x01 => {
+            let key = machine.bios.next_key();
+            machine.bios.pending_key = key;
+            machine.flags.zf = key.is_none();
+            if let Some(key) = key {
+                set_register(machine, 1, AX, key);
+            }
+        }
+        // Shift flags, no modifier is ever pressed.
+        0x02 => set_register(machine, 0, AL, 0),
+        _ => {
+            return Err(format!(
+                "Keyboard function {:#04x} is not supported by the simulator",
+                function
+            ));
+        }
+    }
+    Ok(())
+}
+
+pub fn timer_interrupt(machine: &mut Machine) -> Result<(), String> {
+    let function = register(machine, 0, AH);
+    let clock_ticks = (machine.current_clock / CLOCKS_PER_TICK) as i64;
+    let ticks = (clock_ticks + machine.bios.tick_offset).max(0) as u64;
+    match function {
+        // Read tick count into cx:dx, al is set once a day has passed. Like the BIOS, reading
+        // clears the midnight flag, the count starts over from the new day.
+        0x00 => {
+            set_register(machine, 1, CX, ((ticks % TICKS_PER_DAY) >> 16) as u16);
+            set_register(machine, 1, DX, (ticks % TICKS_PER_DAY) as u16);
+            set_register(machine, 0, AL, (ticks >= TICKS_PER_DAY) as u16);
+            let days = ticks / TICKS_PER_DAY;
+            machine.bios.tick_offset -= (days * TICKS_PER_DAY) as i64;
+        }
+        // Set tick count from cx:dx.
+        0x01 => {
+            let new_ticks =
+                ((register(machine, 1, CX) as u64) << 16) | register(machine, 1, DX) as u64;
+            machine.bios.tick_offset = new_ticks as i64 - clock_ticks;
+        }
+        _ => {
+            return Err(format!(
+                "Timer function {:#04x} is not supported by the simulator",
+                function
+            ));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,692 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::constants::{AH, AL, AX, BX, CH, CL, CX, DH, DL, DS, DX, ES};
+use crate::loader::MEMORY_TOP_SEGMENT;
+use crate::machine::Machine;
+use crate::memory::physical_address;
+
+use super::{interrupt_vector, register, set_interrupt_vector, set_register};
+
+const ERROR_INVALID_FUNCTION: u16 = 1;
+const ERROR_FILE_NOT_FOUND: u16 = 2;
+const ERROR_PATH_NOT_FOUND: u16 = 3;
+const ERROR_TOO_MANY_OPEN_FILES: u16 = 4;
+const ERROR_ACCESS_DENIED: u16 = 5;
+const ERROR_INVALID_HANDLE: u16 = 6;
+const ERROR_NOT_ENOUGH_MEMORY: u16 = 8;
+const ERROR_INVALID_BLOCK: u16 = 9;
+
+// What int 21h/3000h reports, DOS 5.0.
+const DOS_VERSION: u16 = 0x0005;
+// Drive C:, the root directory.
+const CURRENT_DRIVE: u16 = 2;
+// ioctl device information of handles 0-4: the console for the first three, then aux and prn.
+const DEVICE_INFORMATION: [u16; FIRST_FILE_HANDLE] = [0x80d3, 0x80d3, 0x80d3, 0x80c0, 0xa8c0];
+
+// Handles 0-4 are stdin, stdout, stderr, aux and prn.
+const FIRST_FILE_HANDLE: usize = 5;
+const MAX_OPEN_FILES: usize = 20;
+
+pub struct Dos {
+    // Every path the guest opens is resolved inside this directory.
+    pub root: PathBuf,
+    pub files: Vec<Option<File>>,
+    pub psp_segment: u16,
+    // Memory is handed out from the bottom up with no control blocks. blocks are the segments
+    // of the allocated blocks in address order, each can grow up to the next and the last up to
+    // the top of memory. memory_end is the segment right after the last one.
+    pub blocks: Vec<u16>,
+    pub memory_end: u16,
+}
+
+impl Dos {
+    pub fn new(root: PathBuf) -> Dos {
+        Dos {
+            root,
+            files: Vec::new(),
+            psp_segment: 0,
+            blocks: Vec::new(),
+            memory_end: MEMORY_TOP_SEGMENT,
+        }
+    }
+
+    // Maps a DOS path such as C:\DATA\IN.TXT onto the root directory, matching each
+    // component case-insensitively. Paths that try to leave the root are rejected.
+    fn resolve_path(&self, dos_path: &str) -> Option<PathBuf> {
+        let mut dos_path = dos_path;
+        if dos_path.len() >= 2 && dos_path.as_bytes()[1] == b':' {
+            dos_path = &dos_path[2..];
+        }
+
+        let mut path = self.root.clone();
+        for component in Path::new(&dos_path.replace('\\', "/")).components() {
+            match component {
+                Component::Normal(name) => {
+                    let name = name.to_str()?;
+                    let existing = fs::read_dir(&path).ok().and_then(|entries| {
+                        entries
+                            .flatten()
+                            .map(|entry| entry.file_name())
+                            .find(|entry| {
+                                entry
+                                    .to_str()
+                                    .is_some_and(|entry| entry.eq_ignore_ascii_case(name))
+                            })
+                    });
+                    path.push(existing.unwrap_or_else(|| name.into()));
+                }
+                Component::RootDir | Component::CurDir => {}
+                _ => return None,
+            }
+        }
+        Some(path)
+    }
+
+    fn add_file(&mut self, file: File) -> Option<u16> {
+        let index = match self.files.iter().position(|f| f.is_none()) {
+            Some(index) => index,
+            None if self.files.len() < MAX_OPEN_FILES => {
+                self.files.push(None);
+                self.files.len() - 1
+            }
+            None => return None,
+        };
+        self.files[index] = Some(file);
+        Some((index + FIRST_FILE_HANDLE) as u16)
+    }
+
+    fn file(&mut self, handle: u16) -> Option<&mut File> {
+        self.files
+            .get_mut((handle as usize).checked_sub(FIRST_FILE_HANDLE)?)?
+            .as_mut()
+    }
+}
+
+pub fn dos_interrupt(machine: &mut Machine) -> Result<(), String> {
+    let function = register(machine, 0, AH);
+    match function {
+        // Read character from stdin with echo.
+        0x01 => {
+            let character = read_stdin_byte().unwrap_or(0x1a);
+            write_stdout(&[character]);
+            set_register(machine, 0, AL, character as u16);
+        }
+        // Write character in dl to stdout.
+        0x02 => {
+            write_stdout(&[register(machine, 0, DL) as u8]);
+            set_register(machine, 0, AL, register(machine, 0, DL));
+        }
+        // Write $-terminated string at ds:dx to stdout.
+        0x09 => {
+            let string = read_string(machine, register(machine, 1, DX), b'$');
+            write_stdout(&string);
+            set_register(machine, 0, AL, b'$' as u16);
+        }
+        // Buffered input into the buffer at ds:dx, first byte is the buffer size.
+        0x0a => buffered_input(machine),
+        // Get current drive.
+        0x19 => set_register(machine, 0, AL, CURRENT_DRIVE),
+        // Set interrupt vector al to ds:dx.
+        0x25 => {
+            let vector = register(machine, 0, AL) as u8;
+            let segment = machine.segment_registers[DS];
+            set_interrupt_vector(machine, vector, segment, register(machine, 1, DX));
+        }
+        // Get date, in UTC like the time.
+        0x2a => {
+            let (year, month, day, day_of_week) = current_date();
+            set_register(machine, 1, CX, year);
+            set_register(machine, 0, DH, month);
+            set_register(machine, 0, DL, day);
+            set_register(machine, 0, AL, day_of_week);
+        }
+        // Get time, in UTC. The host's time zone can't be read without OS-specific code.
+        0x2c => {
+            let (hour, minute, second, hundredths) = current_time();
+            set_register(machine, 0, CH, hour);
+            set_register(machine, 0, CL, minute);
+            set_register(machine, 0, DH, second);
+            set_register(machine, 0, DL, hundredths);
+        }
+        // Get DOS version, bx and cx hold the OEM and serial number.
+        0x30 => {
+            set_register(machine, 1, AX, DOS_VERSION);
+            set_register(machine, 1, BX, 0);
+            set_register(machine, 1, CX, 0);
+        }
+        // Get or set the ctrl-break check, which is always off.
+        0x33 => {
+            if register(machine, 0, AL) == 0 {
+                set_register(machine, 0, DL, 0);
+            }
+        }
+        // Get interrupt vector al into es:bx.
+        0x35 => {
+            let (segment, offset) = interrupt_vector(machine, register(machine, 0, AL) as u8);
+            machine.segment_registers[ES] = segment;
+            set_register(machine, 1, BX, offset);
+        }
+        0x3c => create_file(machine),
+        0x3d => open_file(machine),
+        0x3e => close_file(machine),
+        0x3f => read_file(machine),
+        0x40 => write_file(machine),
+        0x41 => delete_file(machine),
+        0x42 => seek_file(machine),
+        0x44 => device_control(machine),
+        0x48 => allocate_memory(machine),
+        0x49 => free_memory(machine),
+        0x4a => resize_memory(machine),
+        // Terminate with return code in al.
+        0x4c => machine.exit_code = Some(register(machine, 0, AL) as u8),
+        // Get PSP segment.
+        0x51 | 0x62 => set_register(machine, 1, BX, machine.dos.psp_segment),
+        _ => {
+            return Err(format!(
+                "DOS function {:#04x} is not supported by the simulator",
+                function
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn buffered_input(machine: &mut Machine) {
+    let buffer = register(machine, 1, DX);
+    let segment = machine.segment_registers[DS];
+    let size = machine.memory[physical_address(segment, buffer)] as usize;
+    if size == 0 {
+        return;
+    }
+
+    let mut line = Vec::new();
+    while let Some(character) = read_stdin_byte() {
+        if character == b'\n' {
+            break;
+        }
+        if character != b'\r' && line.len() < size - 1 {
+            line.push(character);
+        }
+    }
+
+    machine.memory[physical_address(segment, buffer.wrapping_add(1))] = line.len() as u8;
+    line.push(b'\r');
+    for (i, character) in line.iter().enumerate() {
+        let offset = buffer.wrapping_add(2 + i as u16);
+        machine.memory[physical_address(segment, offset)] = *character;
+    }
+    machine.define_memory(
+        physical_address(segment, buffer.wrapping_add(1)),
+        line.len() + 1,
+    );
+}
+
+fn create_file(machine: &mut Machine) {
+    let name = read_file_name(machine);
+    let Some(path) = machine.dos.resolve_path(&name) else {
+        return fail(machine, ERROR_PATH_NOT_FOUND);
+    };
+    match File::create(&path) {
+        Ok(file) => add_file(machine, file),
+        Err(error) => fail(machine, error_code(&error)),
+    }
+}
+
+fn open_file(machine: &mut Machine) {
+    let name = read_file_name(machine);
+    let Some(path) = machine.dos.resolve_path(&name) else {
+        return fail(machine, ERROR_PATH_NOT_FOUND);
+    };
+    let mut options = OpenOptions::new();
+    match register(machine, 0, AL) & 0b111 {
+        0 => options.read(true),
+        1 => options.write(true),
+        2 => options.read(true).write(true),
+        _ => return fail(machine, ERROR_INVALID_FUNCTION),
+    };
+    match options.open(&path) {
+        Ok(file) => add_file(machine, file),
+        Err(error) => fail(machine, error_code(&error)),
+    }
+}
+
+fn close_file(machine: &mut Machine) {
+    let handle = register(machine, 1, BX);
+    if handle < FIRST_FILE_HANDLE as u16 {
+        return succeed(machine, 0);
+    }
+    match machine.dos.file(handle) {
+        Some(_) => {
+            machine.dos.files[handle as usize - FIRST_FILE_HANDLE] = None;
+            succeed(machine, 0);
+        }
+        None => fail(machine, ERROR_INVALID_HANDLE),
+    }
+}
+
+fn read_file(machine: &mut Machine) {
+    let handle = register(machine, 1, BX);
+    let mut data = vec![0u8; register(machine, 1, CX) as usize];
+
+    let result = match handle {
+        0 => {
+            flush_stdout();
+            read_stdin_line(&mut data)
+        }
+        _ => match machine.dos.file(handle) {
+            Some(file) => read_up_to(file, &mut data),
+            None => return fail(machine, ERROR_INVALID_HANDLE),
+        },
+    };
+    match result {
+        Ok(count) => {
+            let buffer = register(machine, 1, DX);
+            let segment = machine.segment_registers[DS];
+            for (i, byte) in data[..count].iter().enumerate() {
+                let address = physical_address(segment, buffer.wrapping_add(i as u16));
+                machine.memory[address] = *byte;
+            }
+            machine.define_memory(physical_address(segment, buffer), count);
+            succeed(machine, count as u16);
+        }
+        Err(error) => fail(machine, error_code(&error)),
+    }
+}
+
+fn write_file(machine: &mut Machine) {
+    let handle = register(machine, 1, BX);
+    let buffer = register(machine, 1, DX);
+    let segment = machine.segment_registers[DS];
+    let data: Vec<u8> = (0..register(machine, 1, CX))
+        .map(|i| machine.memory[physical_address(segment, buffer.wrapping_add(i))])
+        .collect();
+
+    let result = match handle {
+        // DOS writes to stdin like stdout, both are the console.
+        0 | 1 | 3 | 4 => {
+            write_stdout(&data);
+            Ok(())
+        }
+        2 => io::stderr().write_all(&data),
+        _ => match machine.dos.file(handle) {
+            Some(file) => file.write_all(&data),
+            None => return fail(machine, ERROR_INVALID_HANDLE),
+        },
+    };
+    match result {
+        Ok(()) => succeed(machine, data.len() as u16),
+        Err(error) => fail(machine, error_code(&error)),
+    }
+}
+
+fn delete_file(machine: &mut Machine) {
+    let name = read_file_name(machine);
+    let Some(path) = machine.dos.resolve_path(&name) else {
+        return fail(machine, ERROR_PATH_NOT_FOUND);
+    };
+    match fs::remove_file(&path) {
+        Ok(()) => succeed(machine, 0),
+        Err(error) => fail(machine, error_code(&error)),
+    }
+}
+
+fn seek_file(machine: &mut Machine) {
+    let handle = register(machine, 1, BX);
+    let offset = ((register(machine, 1, CX) as u32) << 16) | register(machine, 1, DX) as u32;
+    let position = match register(machine, 0, AL) {
+        0 => SeekFrom::Start(offset as u64),
+        1 => SeekFrom::Current(offset as i32 as i64),
+        2 => SeekFrom::End(offset as i32 as i64),
+        _ => return fail(machine, ERROR_INVALID_FUNCTION),
+    };
+    let Some(file) = machine.dos.file(handle) else {
+        return fail(machine, ERROR_INVALID_HANDLE);
+    };
+    match file.seek(position) {
+        Ok(position) => {
+            set_register(machine, 1, DX, (position >> 16) as u16);
+            succeed(machine, position as u16);
+        }
+        Err(error) => fail(machine, error_code(&error)),
+    }
+}
+
+// Only getting and setting the device information of a handle are supported, setting changes
+// nothing.
+fn device_control(machine: &mut Machine) {
+    let handle = register(machine, 1, BX);
+    let information = match DEVICE_INFORMATION.get(handle as usize) {
+        Some(information) => *information,
+        None if machine.dos.file(handle).is_some() => CURRENT_DRIVE,
+        None => return fail(machine, ERROR_INVALID_HANDLE),
+    };
+    match register(machine, 0, AL) {
+        0 => {
+            set_register(machine, 1, DX, information);
+            succeed(machine, information);
+        }
+        1 => succeed(machine, information),
+        _ => fail(machine, ERROR_INVALID_FUNCTION),
+    }
+}
+
+// Allocates bx paragraphs after the most recent block, bx says how many are left on failure.
+fn allocate_memory(machine: &mut Machine) {
+    let paragraphs = register(machine, 1, BX);
+    let available = MEMORY_TOP_SEGMENT - machine.dos.memory_end;
+    if paragraphs > available {
+        set_register(machine, 1, BX, available);
+        return fail(machine, ERROR_NOT_ENOUGH_MEMORY);
+    }
+    let segment = machine.dos.memory_end;
+    machine.dos.blocks.push(segment);
+    machine.dos.memory_end = segment + paragraphs;
+    succeed(machine, segment);
+}
+
+// Frees the block at es. Only the memory of the last block is handed out again, from where it
+// started, and the block before it becomes the last.
+fn free_memory(machine: &mut Machine) {
+    let block = machine.segment_registers[ES];
+    let Some(index) = machine.dos.blocks.iter().position(|start| *start == block) else {
+        return fail(machine, ERROR_INVALID_BLOCK);
+    };
+    machine.dos.blocks.remove(index);
+    if index == machine.dos.blocks.len() {
+        machine.dos.memory_end = block;
+    }
+    machine.flags.cf = false;
+}
+
+// Resizes the block at es to bx paragraphs, up to the next block or the top of memory for the
+// last one. bx says how far it could grow on failure.
+fn resize_memory(machine: &mut Machine) {
+    let block = machine.segment_registers[ES];
+    let paragraphs = register(machine, 1, BX);
+    let blocks = &machine.dos.blocks;
+    let Some(index) = blocks.iter().position(|start| *start == block) else {
+        return fail(machine, ERROR_INVALID_BLOCK);
+    };
+    let limit = blocks.get(index + 1).copied().unwrap_or(MEMORY_TOP_SEGMENT);
+    let last = index + 1 == blocks.len();
+    let end = block as u32 + paragraphs as u32;
+    if end > limit as u32 {
+        set_register(machine, 1, BX, limit - block);
+        return fail(machine, ERROR_NOT_ENOUGH_MEMORY);
+    }
+    if last {
+        machine.dos.memory_end = end as u16;
+    }
+    machine.flags.cf = false;
+}
+
+fn add_file(machine: &mut Machine, file: File) {
+    match machine.dos.add_file(file) {
+        Some(handle) => succeed(machine, handle),
+        None => fail(machine, ERROR_TOO_MANY_OPEN_FILES),
+    }
+}
+
+fn read_file_name(machine: &Machine) -> String {
+    let name = read_string(machine, register(machine, 1, DX), 0);
+    String::from_utf8_lossy(&name).into_owned()
+}
+
+fn read_string(machine: &Machine, offset: u16, terminator: u8) -> Vec<u8> {
+    let segment = machine.segment_registers[DS];
+    (0..=u16::MAX)
+        .map(|i| machine.memory[physical_address(segment, offset.wrapping_add(i))])
+        .take_while(|byte| *byte != terminator)
+        .collect()
+}
+
+fn succeed(machine: &mut Machine, ax: u16) {
+    machine.flags.cf = false;
+    set_register(machine, 1, AX, ax);
+}
+
+fn fail(machine: &mut Machine, error: u16) {
+    machine.flags.cf = true;
+    set_register(machine, 1, AX, error);
+}
+
+fn error_code(error: &io::Error) -> u16 {
+    match error.kind() {
+        io::ErrorKind::NotFound => ERROR_FILE_NOT_FOUND,
+        _ => ERROR_ACCESS_DENIED,
+    }
+}
+
+fn read_up_to(file: &mut File, data: &mut [u8]) -> io::Result<usize> {
+    let mut count = 0;
+    while count < data.len() {
+        match file.read(&mut data[count..])? {
+            0 => break,
+            read => count += read,
+        }
+    }
+    Ok(count)
+}
+
+// Reading from stdin returns at most one line, like the DOS console device.
+fn read_stdin_line(data: &mut [u8]) -> io::Result<usize> {
+    let mut count = 0;
+    while count < data.len() {
+        let Some(character) = read_stdin_byte() else {
+            break;
+        };
+        if character == b'\n' {
+            data[count] = b'\r';
+            count += 1;
+            if count < data.len() {
+                data[count] = b'\n';
+                count += 1;
+            }
+            break;
+        }
+        data[count] = character;
+        count += 1;
+    }
+    Ok(count)
+}
+
+pub fn read_stdin_byte() -> Option<u8> {
+    flush_stdout();
+    let mut character = [0u8];
+    match io::stdin().read(&mut character) {
+        Ok(1) => Some(character[0]),
+        _ => None,
+    }
+}
+
+pub fn write_stdout(data: &[u8]) {
+    let _ = io::stdout().write_all(data);
+}
+
+pub fn flush_stdout() {
+    let _ = io::stdout().flush();
+}
+
+fn current_date() -> (u16, u16, u16, u16) {
+    let days = (unix_time().as_secs() / 86400) as i64;
+    // 1970-01-01 was a Thursday.
+    let day_of_week = ((days + 4) % 7) as u16;
+
+    // Days since 1970-01-01 to a civil date, see howardhinnant.github.io/date_algorithms.html.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year as u16, month as u16, day as u16, day_of_week)
+}
+
+fn current_time() -> (u16, u16, u16, u16) {
+    let time = unix_time();
+    let seconds = time.as_secs() % 86400;
+    (
+        (seconds / 3600) as u16,
+        (seconds / 60 % 60) as u16,
+        (seconds % 60) as u16,
+        (time.subsec_millis() / 10) as u16,
+    )
+}
+
+fn unix_time() -> std::time::Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs service ah with bx and es, returning cf and ax.
+    fn service(machine: &mut Machine, ah: u16, bx: u16, es: u16) -> (bool, u16) {
+        set_register(machine, 0, AH, ah);
+        set_register(machine, 1, BX, bx);
+        machine.segment_registers[ES] = es;
+        dos_interrupt(machine).unwrap();
+        (machine.flags.cf, register(machine, 1, AX))
+    }
+
+    fn machine_with_program(psp_segment: u16, paragraphs: u16) -> Machine {
+        let mut machine = Machine::new();
+        machine.dos.psp_segment = psp_segment;
+        machine.dos.blocks = vec![psp_segment];
+        machine.dos.memory_end = psp_segment + paragraphs;
+        machine
+    }
+
+    #[test]
+    fn freeing_the_last_block_hands_its_memory_out_again() {
+        let mut machine = machine_with_program(0x1000, 0x100);
+        assert_eq!(service(&mut machine, 0x48, 0x10, 0), (false, 0x1100));
+        assert_eq!(service(&mut machine, 0x48, 0x20, 0), (false, 0x1110));
+        assert!(!service(&mut machine, 0x49, 0, 0x1110).0);
+
+        // The freed block is gone and the one before it is the last again.
+        assert_eq!(
+            service(&mut machine, 0x4a, 0x10, 0x1110),
+            (true, ERROR_INVALID_BLOCK)
+        );
+        assert!(!service(&mut machine, 0x4a, 0x40, 0x1100).0);
+        assert_eq!(machine.dos.memory_end, 0x1140);
+        assert_eq!(service(&mut machine, 0x48, 0x10, 0), (false, 0x1140));
+    }
+
+    #[test]
+    fn blocks_grow_up_to_the_next_block() {
+        let mut machine = machine_with_program(0x1000, 0x100);
+        service(&mut machine, 0x48, 0x10, 0);
+        assert_eq!(
+            service(&mut machine, 0x4a, 0x200, 0x1000),
+            (true, ERROR_NOT_ENOUGH_MEMORY)
+        );
+        assert_eq!(register(&machine, 1, BX), 0x100);
+        assert!(!service(&mut machine, 0x4a, 0x80, 0x1000).0);
+        assert_eq!(
+            service(&mut machine, 0x49, 0, 0x2000),
+            (true, ERROR_INVALID_BLOCK)
+        );
+    }
+
+    // A directory of its own under the system temporary directory, removed afterwards.
+    fn with_root(name: &str, test: impl FnOnce(PathBuf)) {
+        let root = std::env::temp_dir().join(format!("perf-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        test(root.clone());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn paths_stay_inside_the_root() {
+        let dos = Dos::new(PathBuf::from("root"));
+        assert_eq!(
+            dos.resolve_path("C:\\DATA\\IN.TXT"),
+            Some(PathBuf::from("root/DATA/IN.TXT"))
+        );
+        assert_eq!(
+            dos.resolve_path(".\\IN.TXT"),
+            Some(PathBuf::from("root/IN.TXT"))
+        );
+        assert_eq!(dos.resolve_path("..\\IN.TXT"), None);
+        assert_eq!(dos.resolve_path("C:\\DATA\\..\\..\\IN.TXT"), None);
+        assert_eq!(dos.resolve_path("DATA/../IN.TXT"), None);
+    }
+
+    #[test]
+    fn paths_match_existing_names_in_any_case() {
+        with_root("case", |root| {
+            fs::create_dir(root.join("Data")).unwrap();
+            let dos = Dos::new(root.clone());
+            assert_eq!(
+                dos.resolve_path("C:\\DATA\\in.txt"),
+                Some(root.join("Data").join("in.txt"))
+            );
+        });
+    }
+
+    // Runs service ax with bx, cx and ds:dx, returning cf and ax.
+    fn file_service(machine: &mut Machine, ax: u16, bx: u16, cx: u16, dx: u16) -> (bool, u16) {
+        for (reg, value) in [(AX, ax), (BX, bx), (CX, cx), (DX, dx)] {
+            set_register(machine, 1, reg, value);
+        }
+        dos_interrupt(machine).unwrap();
+        (machine.flags.cf, register(machine, 1, AX))
+    }
+
+    #[test]
+    fn files_are_created_written_and_read_back() {
+        with_root("files", |root| {
+            let mut machine = Machine::new();
+            machine.dos = Dos::new(root.clone());
+            machine.load(0, 0x100, b"OUT.TXT\0");
+            machine.load(0, 0x200, b"hello");
+
+            let (failed, handle) = file_service(&mut machine, 0x3c00, 0, 0, 0x100);
+            assert!(!failed);
+            assert_eq!(handle, FIRST_FILE_HANDLE as u16);
+            assert_eq!(
+                file_service(&mut machine, 0x4000, handle, 5, 0x200),
+                (false, 5)
+            );
+            assert!(!file_service(&mut machine, 0x3e00, handle, 0, 0).0);
+            assert_eq!(fs::read(root.join("OUT.TXT")).unwrap(), b"hello");
+
+            let (failed, handle) = file_service(&mut machine, 0x3d00, 0, 0, 0x100);
+            assert!(!failed);
+            // Asking for more than the file holds reads what there is.
+            assert_eq!(
+                file_service(&mut machine, 0x3f00, handle, 16, 0x300),
+                (false, 5)
+            );
+            assert_eq!(&machine.memory[0x300..0x305], b"hello");
+            assert!(!file_service(&mut machine, 0x3e00, handle, 0, 0).0);
+            assert_eq!(
+                file_service(&mut machine, 0x3e00, handle, 0, 0),
+                (true, ERROR_INVALID_HANDLE)
+            );
+
+            machine.load(0, 0x100, b"NONE.TXT\0");
+            assert_eq!(
+                file_service(&mut machine, 0x3d00, 0, 0, 0x100),
+                (true, ERROR_FILE_NOT_FOUND)
+            );
+        });
+    }
+}
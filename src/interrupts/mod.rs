@@ -0,0 +1,67 @@
+use crate::machine::Machine;
+use crate::memory::{physical_address, read_word, write_word};
+use crate::simulator::{read_register, write_register};
+
+use bios::{keyboard_interrupt, timer_interrupt, video_interrupt};
+use dos::dos_interrupt;
+
+pub mod bios;
+pub mod dos;
+
+// Every vector starts out pointing at an iret in the BIOS segment, vector n at BIOS_SEGMENT:n.
+// Reaching one of those addresses runs the service emulated by the host before the iret.
+pub const BIOS_SEGMENT: u16 = 0xf000;
+const IRET: u8 = 0xcf;
+
+pub const DIVIDE_ERROR: u8 = 0;
+pub const SINGLE_STEP: u8 = 1;
+pub const NON_MASKABLE_INTERRUPT: u8 = 2;
+pub const BREAKPOINT: u8 = 3;
+pub const OVERFLOW: u8 = 4;
+pub const BOUND_RANGE_EXCEEDED: u8 = 5;
+
+pub fn install_interrupt_vectors(machine: &mut Machine) {
+    for vector in 0..=255u8 {
+        set_interrupt_vector(machine, vector, BIOS_SEGMENT, vector as u16);
+        machine.memory[physical_address(BIOS_SEGMENT, vector as u16)] = IRET;
+    }
+    machine.define_memory(physical_address(BIOS_SEGMENT, 0), 256);
+}
+
+pub fn interrupt_vector(machine: &Machine, vector: u8) -> (u16, u16) {
+    let offset = read_word(&machine.memory, 0, vector as u16 * 4);
+    let segment = read_word(&machine.memory, 0, vector as u16 * 4 + 2);
+    (segment, offset)
+}
+
+pub fn set_interrupt_vector(machine: &mut Machine, vector: u8, segment: u16, offset: u16) {
+    write_word(&mut machine.memory, 0, vector as u16 * 4, offset);
+    write_word(&mut machine.memory, 0, vector as u16 * 4 + 2, segment);
+    machine.define_memory(vector as usize * 4, 4);
+}
+
+pub fn host_interrupt(machine: &mut Machine, vector: u8) -> Result<(), String> {
+    match vector {
+        DIVIDE_ERROR => return Err(String::from("Divide error")),
+        // The PC has print screen on this vector, returning would run the bound again forever.
+        BOUND_RANGE_EXCEEDED => return Err(String::from("Bound range exceeded")),
+        0x10 => video_interrupt(machine)?,
+        0x16 => keyboard_interrupt(machine)?,
+        0x1a => timer_interrupt(machine)?,
+        // Program terminate.
+        0x20 => machine.exit_code = Some(0),
+        0x21 => dos_interrupt(machine)?,
+        // Everything else is a plain iret.
+        _ => {}
+    }
+    Ok(())
+}
+
+fn register(machine: &Machine, w: usize, reg: usize) -> u16 {
+    read_register(&machine.simulation_registers, w, reg) as u16
+}
+
+fn set_register(machine: &mut Machine, w: usize, reg: usize, value: u16) {
+    write_register(&mut machine.simulation_registers, w, reg, value as i16);
+    machine.define_register(w, reg);
+}
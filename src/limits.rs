@@ -0,0 +1,145 @@
+use crate::constants::CS;
+use crate::machine::{ExecutedInstruction, Machine};
+use crate::memory::physical_address;
+
+// Exit statuses of the stops that aren't the program ending. Programs pick their own exit
+// codes, these are high enough to be unlikely among them.
+const HALTED_STATUS: i32 = 120;
+const STOP_ADDRESS_STATUS: i32 = 121;
+const MAX_INSTRUCTIONS_STATUS: i32 = 122;
+const MAX_CLOCKS_STATUS: i32 = 123;
+const INFINITE_LOOP_STATUS: i32 = 124;
+
+// Whether a run exited with the status of a stop, which still ends a complete trace.
+pub fn is_stop_status(status: i32) -> bool {
+    (HALTED_STATUS..=INFINITE_LOOP_STATUS).contains(&status)
+}
+
+pub enum StopReason {
+    // The program terminated through DOS, with its exit code.
+    Exited(u8),
+    // A flat program ran past its last byte.
+    EndOfProgram,
+    // hlt with no interrupt that could ever resume it.
+    Halted,
+    StopAddress,
+    MaxInstructions,
+    MaxClocks,
+    // A jump to itself that changes nothing, which only an interrupt could leave.
+    InfiniteLoop,
+}
+
+impl StopReason {
+    pub fn exit_status(&self) -> i32 {
+        match self {
+            StopReason::Exited(code) => *code as i32,
+            StopReason::EndOfProgram => 0,
+            StopReason::Halted => HALTED_STATUS,
+            StopReason::StopAddress => STOP_ADDRESS_STATUS,
+            StopReason::MaxInstructions => MAX_INSTRUCTIONS_STATUS,
+            StopReason::MaxClocks => MAX_CLOCKS_STATUS,
+            StopReason::InfiniteLoop => INFINITE_LOOP_STATUS,
+        }
+    }
+
+    // `Stopped at 1000:0105 by hlt after 12 instructions and 96 clocks`, None when the program
+    // simply ended.
+    pub fn summary(&self, machine: &Machine, limits: &Limits) -> Option<String> {
+        let reason = match self {
+            StopReason::Exited(_) | StopReason::EndOfProgram => return None,
+            StopReason::Halted => String::from("by hlt"),
+            StopReason::StopAddress => String::from("at the stop address"),
+            StopReason::MaxInstructions => String::from("by the instruction limit"),
+            StopReason::MaxClocks => String::from("by the clock limit"),
+            StopReason::InfiniteLoop => String::from("in an infinite loop"),
+        };
+        Some(format!(
+            "Stopped at {:04x}:{:04x} {} after {} instructions and {} clocks",
+            machine.segment_registers[CS],
+            machine.ip,
+            reason,
+            limits.instructions,
+            machine.current_clock
+        ))
+    }
+}
+
+#[derive(Default)]
+pub struct Limits {
+    pub max_instructions: Option<u64>,
+    pub max_clocks: Option<u64>,
+    // Physical addresses execution stops at before running the instruction there.
+    pub stop_at: Vec<usize>,
+    instructions: u64,
+}
+
+impl Limits {
+    // Called before the first step, a stop address at the entry stops before anything runs.
+    pub fn check_start(&self, machine: &Machine) -> Option<StopReason> {
+        let cs_ip = (machine.segment_registers[CS], machine.ip);
+        self.stop_at
+            .contains(&physical_address(cs_ip.0, cs_ip.1))
+            .then_some(StopReason::StopAddress)
+    }
+
+    // Called after every step. interrupts_ahead says whether an interrupt is still scheduled,
+    // which could end a hlt or a loop waiting for it.
+    pub fn check(
+        &mut self,
+        machine: &Machine,
+        executed: &ExecutedInstruction,
+        interrupts_ahead: bool,
+    ) -> Option<StopReason> {
+        self.instructions += 1;
+        let waiting = interrupts_ahead || machine.interrupt_ready();
+        let instruction = &executed.instruction;
+        let cs_ip = (machine.segment_registers[CS], machine.ip);
+        if machine.halted && !waiting {
+            Some(StopReason::Halted)
+        } else if self.stop_at.contains(&physical_address(cs_ip.0, cs_ip.1)) {
+            Some(StopReason::StopAddress)
+        } else if self
+            .max_instructions
+            .is_some_and(|max| self.instructions >= max)
+        {
+            Some(StopReason::MaxInstructions)
+        } else if self
+            .max_clocks
+            .is_some_and(|max| machine.current_clock >= max)
+        {
+            Some(StopReason::MaxClocks)
+        } else if (instruction.is_jump() || instruction.name == "jmp")
+            && !instruction.name.starts_with("loop")
+            && cs_ip == (executed.segment, executed.offset)
+            && !machine.flags.tf
+            && !waiting
+        {
+            Some(StopReason::InfiniteLoop)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_at_the_entry_before_the_first_step() {
+        let mut machine = Machine::new();
+        machine.segment_registers[CS] = 0x1000;
+        machine.ip = 0x100;
+        let mut limits = Limits {
+            stop_at: vec![physical_address(0x1010, 0)],
+            ..Default::default()
+        };
+        assert!(matches!(
+            limits.check_start(&machine),
+            Some(StopReason::StopAddress)
+        ));
+
+        limits.stop_at = vec![physical_address(0x1000, 0x101)];
+        assert!(limits.check_start(&machine).is_none());
+    }
+}
@@ -0,0 +1,46 @@
+use crate::decoder::{decode, explain};
+use crate::formatter::{FormatOptions, Formatter};
+use crate::machine::Cpu;
+use crate::symbols::Symbols;
+
+// Wide enough for the hex bytes of the longest instruction.
+const BYTES_WIDTH: usize = 18;
+
+pub fn print_listing(
+    program: &[u8],
+    cpu: Cpu,
+    formatter: &dyn Formatter,
+    options: &FormatOptions,
+    explain_fields: bool,
+    symbols: &Symbols,
+) {
+    let mut offset = 0;
+    while offset < program.len() {
+        let bytes = &program[offset..];
+        let Some(instruction) = decode(bytes, cpu) else {
+            let hex = format!("{:02x}", bytes[0]);
+            println!("{:04x}  {:<BYTES_WIDTH$}db {:#04x}", offset, hex, bytes[0]);
+            offset += 1;
+            continue;
+        };
+
+        let length = instruction.length as usize;
+        let hex: Vec<String> = bytes[..length]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        print!("{}", symbols.header(offset, formatter.comment()));
+        let text = formatter.format_marked(&instruction, options)
+            + &symbols.jump_note(offset, &instruction, formatter.comment());
+        println!("{:04x}  {:<BYTES_WIDTH$}{}", offset, hex.join(" "), text);
+        if explain_fields {
+            println!(
+                "{:6}{:BYTES_WIDTH$}{}",
+                "",
+                "",
+                explain(bytes, &instruction)
+            );
+        }
+        offset += length;
+    }
+}
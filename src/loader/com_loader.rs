@@ -0,0 +1,77 @@
+use crate::constants::{CS, DS, ES, SP, SS};
+use crate::machine::Machine;
+
+use super::{MEMORY_TOP_SEGMENT, PSP_SIZE, build_psp};
+
+// The program, the PSP and the initial stack word all have to fit in one segment.
+const MAX_COM_SIZE: usize = 0x10000 - PSP_SIZE as usize - 2;
+
+pub fn load_com(
+    machine: &mut Machine,
+    segment: u16,
+    program: &[u8],
+    program_name: &str,
+    arguments: &[String],
+) -> Result<(), String> {
+    if program.len() > MAX_COM_SIZE {
+        return Err(format!(
+            "Program is {} bytes long, a .COM file can be at most {} bytes",
+            program.len(),
+            MAX_COM_SIZE
+        ));
+    }
+
+    build_psp(
+        machine,
+        segment,
+        MEMORY_TOP_SEGMENT,
+        program_name,
+        arguments,
+    )?;
+    machine.load(segment, PSP_SIZE, program);
+
+    for segment_register in [ES, CS, SS, DS] {
+        machine.segment_registers[segment_register] = segment;
+    }
+    machine.ip = PSP_SIZE;
+    // DOS starts programs with interrupts enabled.
+    machine.flags.if_ = true;
+    machine.simulation_registers[SP] = 0;
+    // DOS pushes a zero word so that a near ret jumps to the int 20h at PSP:0000.
+    machine.push_word(0);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{physical_address, read_word};
+
+    #[test]
+    fn loads_after_the_psp_with_everything_in_one_segment() {
+        let mut machine = Machine::new();
+        let arguments = [String::from("A"), String::from("B")];
+        load_com(&mut machine, 0x1000, &[0x90, 0xc3], "test.com", &arguments).unwrap();
+
+        assert_eq!(machine.segment_registers, [0x1000; 4]);
+        assert_eq!(machine.ip, 0x100);
+        assert_eq!(machine.memory[physical_address(0x1000, 0x100)], 0x90);
+        // The zero word DOS pushed returns to the int 20h at offset 0.
+        assert_eq!(machine.simulation_registers[SP] as u16, 0xfffe);
+        assert_eq!(read_word(&machine.memory, 0x1000, 0xfffe), 0);
+        assert_eq!(read_word(&machine.memory, 0x1000, 0), 0x20cd);
+
+        let tail = physical_address(0x1000, 0x80);
+        assert_eq!(&machine.memory[tail..tail + 6], b"\x04 A B\r");
+        assert_eq!(machine.dos.blocks, [0x1000]);
+        assert_eq!(machine.dos.memory_end, MEMORY_TOP_SEGMENT);
+    }
+
+    #[test]
+    fn rejects_programs_that_do_not_fit_in_a_segment() {
+        let mut machine = Machine::new();
+        let program = vec![0; MAX_COM_SIZE + 1];
+        assert!(load_com(&mut machine, 0x1000, &program, "big.com", &[]).is_err());
+    }
+}
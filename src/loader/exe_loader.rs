@@ -0,0 +1,258 @@
+use crate::constants::{CS, DS, ES, SP, SS};
+use crate::machine::Machine;
+use crate::memory::{read_word, write_word};
+
+use super::{MEMORY_TOP_SEGMENT, PARAGRAPH_SIZE, PSP_SIZE, build_psp};
+
+const HEADER_FIELDS_SIZE: usize = 0x1c;
+const PAGE_SIZE: usize = 512;
+
+pub struct ExeHeader {
+    pub bytes_on_last_page: u16,
+    pub pages: u16,
+    pub relocation_count: u16,
+    pub header_paragraphs: u16,
+    pub min_alloc: u16,
+    pub max_alloc: u16,
+    pub initial_ss: u16,
+    pub initial_sp: u16,
+    pub initial_ip: u16,
+    pub initial_cs: u16,
+    pub relocation_table_offset: u16,
+}
+
+impl ExeHeader {
+    pub fn parse(file: &[u8]) -> Result<ExeHeader, String> {
+        if file.len() < HEADER_FIELDS_SIZE {
+            return Err(format!(
+                "File is {} bytes long, an MZ header needs at least {}",
+                file.len(),
+                HEADER_FIELDS_SIZE
+            ));
+        }
+        if &file[0..2] != b"MZ" && &file[0..2] != b"ZM" {
+            return Err(format!(
+                "Missing MZ signature, found {:#04x} {:#04x}",
+                file[0], file[1]
+            ));
+        }
+
+        let field = |offset: usize| ((file[offset + 1] as u16) << 8) | file[offset] as u16;
+        let header = ExeHeader {
+            bytes_on_last_page: field(0x02),
+            pages: field(0x04),
+            relocation_count: field(0x06),
+            header_paragraphs: field(0x08),
+            min_alloc: field(0x0a),
+            max_alloc: field(0x0c),
+            initial_ss: field(0x0e),
+            initial_sp: field(0x10),
+            initial_ip: field(0x14),
+            initial_cs: field(0x16),
+            relocation_table_offset: field(0x18),
+        };
+
+        if header.bytes_on_last_page as usize >= PAGE_SIZE {
+            return Err(format!(
+                "Bytes on last page is {}, it must be less than {}",
+                header.bytes_on_last_page, PAGE_SIZE
+            ));
+        }
+        if header.pages == 0 {
+            return Err(String::from("Page count is 0"));
+        }
+        if header.header_size() < HEADER_FIELDS_SIZE {
+            return Err(format!(
+                "Header is {} bytes long, it must be at least {}",
+                header.header_size(),
+                HEADER_FIELDS_SIZE
+            ));
+        }
+        if header.image_end() < header.header_size() {
+            return Err(format!(
+                "Header is {} bytes long but the pages only cover {} bytes",
+                header.header_size(),
+                header.image_end()
+            ));
+        }
+        if header.image_end() > file.len() {
+            return Err(format!(
+                "Header describes {} bytes but the file is only {} bytes long",
+                header.image_end(),
+                file.len()
+            ));
+        }
+        let relocation_table_end =
+            header.relocation_table_offset as usize + header.relocation_count as usize * 4;
+        if header.relocation_count != 0 && relocation_table_end > header.header_size() {
+            return Err(format!(
+                "Relocation table ends at {:#x}, past the end of the header at {:#x}",
+                relocation_table_end,
+                header.header_size()
+            ));
+        }
+
+        Ok(header)
+    }
+
+    pub fn header_size(&self) -> usize {
+        self.header_paragraphs as usize * PARAGRAPH_SIZE
+    }
+
+    // Offset in the file of the first byte after the load module.
+    pub fn image_end(&self) -> usize {
+        let end = self.pages as usize * PAGE_SIZE;
+        if self.bytes_on_last_page == 0 {
+            end
+        } else {
+            end - PAGE_SIZE + self.bytes_on_last_page as usize
+        }
+    }
+
+    pub fn image_size(&self) -> usize {
+        self.image_end() - self.header_size()
+    }
+}
+
+// The load module is placed at load_segment with the PSP in the 256 bytes right before it.
+pub fn load_exe(
+    machine: &mut Machine,
+    load_segment: u16,
+    file: &[u8],
+    program_name: &str,
+    arguments: &[String],
+) -> Result<(), String> {
+    let header = ExeHeader::parse(file)?;
+    let psp_segment = load_segment
+        .checked_sub(PSP_SIZE / PARAGRAPH_SIZE as u16)
+        .ok_or_else(|| {
+            format!(
+                "Load segment {:#06x} leaves no room for the PSP",
+                load_segment
+            )
+        })?;
+
+    let image_paragraphs = header.image_size().div_ceil(PARAGRAPH_SIZE);
+    let available =
+        (MEMORY_TOP_SEGMENT as usize).saturating_sub(load_segment as usize + image_paragraphs);
+    if available < header.min_alloc as usize {
+        return Err(format!(
+            "Program needs {} extra paragraphs but only {} are available",
+            header.min_alloc, available
+        ));
+    }
+    let memory_top =
+        load_segment as usize + image_paragraphs + available.min(header.max_alloc as usize);
+
+    build_psp(
+        machine,
+        psp_segment,
+        memory_top as u16,
+        program_name,
+        arguments,
+    )?;
+    machine.load(
+        load_segment,
+        0,
+        &file[header.header_size()..header.image_end()],
+    );
+
+    for i in 0..header.relocation_count as usize {
+        let entry = header.relocation_table_offset as usize + i * 4;
+        let offset = ((file[entry + 1] as u16) << 8) | file[entry] as u16;
+        let segment = ((file[entry + 3] as u16) << 8) | file[entry + 2] as u16;
+        let segment = load_segment.wrapping_add(segment);
+
+        let value = read_word(&machine.memory, segment, offset);
+        write_word(
+            &mut machine.memory,
+            segment,
+            offset,
+            value.wrapping_add(load_segment),
+        );
+    }
+
+    machine.segment_registers[ES] = psp_segment;
+    machine.segment_registers[DS] = psp_segment;
+    machine.segment_registers[SS] = load_segment.wrapping_add(header.initial_ss);
+    machine.segment_registers[CS] = load_segment.wrapping_add(header.initial_cs);
+    machine.simulation_registers[SP] = header.initial_sp as i16;
+    machine.ip = header.initial_ip;
+    machine.flags.if_ = true;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::physical_address;
+
+    // A two paragraph header with one relocation of the word at 0000:0001, followed by
+    // mov ax, 0x0005 and a 4 byte stack segment 1 paragraph in.
+    fn exe(min_alloc: u16, max_alloc: u16) -> Vec<u8> {
+        let mut file = vec![0; 0x20];
+        let mut field = |offset: usize, value: u16| {
+            file[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+        };
+        field(0x00, u16::from_le_bytes(*b"MZ"));
+        field(0x02, 0x20 + 0x14);
+        field(0x04, 1);
+        field(0x06, 1);
+        field(0x08, 2);
+        field(0x0a, min_alloc);
+        field(0x0c, max_alloc);
+        field(0x0e, 1);
+        field(0x10, 4);
+        field(0x14, 0);
+        field(0x16, 0);
+        field(0x18, 0x1c);
+        field(0x1c, 1);
+        field(0x1e, 0);
+        file.extend([0xb8, 0x05, 0x00]);
+        file.resize(0x20 + 0x14, 0);
+        file
+    }
+
+    #[test]
+    fn relocates_segments_and_sets_up_the_registers() {
+        let mut machine = Machine::new();
+        load_exe(&mut machine, 0x1010, &exe(0, 0xffff), "test.exe", &[]).unwrap();
+
+        assert_eq!(read_word(&machine.memory, 0x1010, 1), 0x1015);
+        assert_eq!(machine.memory[physical_address(0x1010, 0)], 0xb8);
+        assert_eq!(machine.segment_registers[CS], 0x1010);
+        assert_eq!(machine.segment_registers[SS], 0x1011);
+        assert_eq!(machine.segment_registers[DS], 0x1000);
+        assert_eq!(machine.segment_registers[ES], 0x1000);
+        assert_eq!((machine.ip, machine.simulation_registers[SP]), (0, 4));
+        assert_eq!(machine.dos.psp_segment, 0x1000);
+    }
+
+    #[test]
+    fn allocates_between_min_alloc_and_max_alloc() {
+        let mut machine = Machine::new();
+        load_exe(&mut machine, 0x1010, &exe(0, 0x20), "test.exe", &[]).unwrap();
+        // Two image paragraphs and 0x20 extra ones.
+        assert_eq!(machine.dos.memory_end, 0x1010 + 2 + 0x20);
+        assert_eq!(read_word(&machine.memory, 0x1000, 2), 0x1010 + 2 + 0x20);
+
+        let mut machine = Machine::new();
+        load_exe(&mut machine, 0x1010, &exe(0, 0xffff), "test.exe", &[]).unwrap();
+        assert_eq!(machine.dos.memory_end, MEMORY_TOP_SEGMENT);
+
+        let mut machine = Machine::new();
+        assert!(load_exe(&mut machine, 0x1010, &exe(0x9000, 0xffff), "test.exe", &[]).is_err());
+    }
+
+    #[test]
+    fn rejects_broken_headers() {
+        let mut file = exe(0, 0xffff);
+        file[0] = b'X';
+        assert!(ExeHeader::parse(&file).is_err());
+
+        let mut file = exe(0, 0xffff);
+        file.truncate(0x30);
+        assert!(ExeHeader::parse(&file).is_err());
+    }
+}
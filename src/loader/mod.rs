@@ -0,0 +1,77 @@
+use crate::machine::Machine;
+
+pub mod com_loader;
+pub mod exe_loader;
+
+pub const PSP_SIZE: u16 = 0x100;
+
+// Programs are loaded above the interrupt vector table and BIOS data area.
+pub const DEFAULT_LOAD_SEGMENT: u16 = 0x1000;
+
+// Segment of the first byte after conventional memory (640 KiB).
+pub const MEMORY_TOP_SEGMENT: u16 = 0xa000;
+
+pub const PARAGRAPH_SIZE: usize = 16;
+
+const MAX_COMMAND_TAIL_LENGTH: usize = 126;
+
+// The environment block goes right below the PSP. It has no variables but PATH, then the count
+// of strings after it and the program's full name, where C startup code finds argv[0].
+pub fn build_psp(
+    machine: &mut Machine,
+    psp_segment: u16,
+    memory_top: u16,
+    program_name: &str,
+    arguments: &[String],
+) -> Result<(), String> {
+    let mut command_tail = String::new();
+    for argument in arguments {
+        command_tail += " ";
+        command_tail += argument;
+    }
+    if command_tail.len() > MAX_COMMAND_TAIL_LENGTH {
+        return Err(format!(
+            "Command tail is {} bytes long, DOS allows at most {}",
+            command_tail.len(),
+            MAX_COMMAND_TAIL_LENGTH
+        ));
+    }
+
+    let mut environment = b"PATH=C:\\\0\0\x01\0C:\\".to_vec();
+    environment.extend(program_name.to_ascii_uppercase().bytes());
+    environment.push(0);
+    let environment_segment = psp_segment
+        .checked_sub(environment.len().div_ceil(PARAGRAPH_SIZE) as u16)
+        .ok_or_else(|| {
+            format!(
+                "PSP segment {:#06x} leaves no room for the environment",
+                psp_segment
+            )
+        })?;
+
+    let mut psp = [0u8; PSP_SIZE as usize];
+    // int 20h, so returning to offset 0 terminates the program.
+    psp[0x00] = 0xcd;
+    psp[0x01] = 0x20;
+    psp[0x02] = memory_top as u8;
+    psp[0x03] = (memory_top >> 8) as u8;
+    psp[0x2c] = environment_segment as u8;
+    psp[0x2d] = (environment_segment >> 8) as u8;
+    // int 21h; retf, the far call entry point for DOS services.
+    psp[0x50] = 0xcd;
+    psp[0x51] = 0x21;
+    psp[0x52] = 0xcb;
+    // Unopened FCBs with blank file names.
+    psp[0x5d..0x68].fill(b' ');
+    psp[0x6d..0x78].fill(b' ');
+    psp[0x80] = command_tail.len() as u8;
+    psp[0x81..0x81 + command_tail.len()].copy_from_slice(command_tail.as_bytes());
+    psp[0x81 + command_tail.len()] = b'\r';
+
+    machine.load(environment_segment, 0, &environment);
+    machine.load(psp_segment, 0, &psp);
+    machine.dos.psp_segment = psp_segment;
+    machine.dos.blocks = vec![psp_segment];
+    machine.dos.memory_end = memory_top;
+    Ok(())
+}
@@ -0,0 +1,1276 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+use crate::call_stack::{CallStack, FrameKind};
+use crate::constants::{AH, AX, BP, BX, CS, CX, DI, DS, DX, ES, SI, SP, SS};
+use crate::decoder::{MAX_INSTRUCTION_LENGTH, decode};
+use crate::flag::Flags;
+use crate::fpu::{Fpu, execute_fpu};
+use crate::instruction::Instruction;
+use crate::interrupts::bios::Bios;
+use crate::interrupts::dos::Dos;
+use crate::interrupts::{
+    BIOS_SEGMENT, BOUND_RANGE_EXCEEDED, BREAKPOINT, DIVIDE_ERROR, NON_MASKABLE_INTERRUPT, OVERFLOW,
+    SINGLE_STEP, host_interrupt, interrupt_vector,
+};
+use crate::memory::{MEMORY_SIZE, physical_address, read_word, write_word};
+use crate::rm::Rm;
+use crate::shadow::{Location, Shadow, accesses};
+use crate::simulator::immediate_to_rm_simulator::{
+    AdcImmediateToRMSimulator, AddImmediateToRMSimulator, AndImmediateToRMSimulator,
+    CmpImmediateToRMSimulator, ImmediateToRMSimulator, ImulImmediateToRMSimulator,
+    MovImmediateToRMSimulator, OrImmediateToRMSimulator, SbbImmediateToRMSimulator,
+    SubImmediateToRMSimulator, TestImmediateToRMSimulator, XorImmediateToRMSimulator,
+};
+use crate::simulator::rm_simulator::{
+    DecRmSimulator, DivRmSimulator, IdivRmSimulator, ImulRmSimulator, IncRmSimulator,
+    MulRmSimulator, NegRmSimulator, NotRmSimulator, RmSimulator,
+};
+use crate::simulator::rm_to_rm_simulator::{
+    AdcRmToRmSimulator, AddRmToRmSimulator, AndRmToRmSimulator, CmpRmToRmSimulator,
+    MovRmToRmSimulator, OrRmToRmSimulator, RMToRmSimulator, SbbRmToRmSimulator, SubRmToRmSimulator,
+    TestRmToRmSimulator, XchgRmToRmSimulator, XorRmToRmSimulator,
+};
+use crate::simulator::shift_simulator::{
+    RclShiftSimulator, RcrShiftSimulator, RolShiftSimulator, RorShiftSimulator, SarShiftSimulator,
+    SetMinusOneShiftSimulator, ShiftSimulator, ShlShiftSimulator, ShrShiftSimulator,
+    simulate_shift,
+};
+use crate::simulator::{SimulatorInput, SimulatorOutput, read_register, write_register};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Cpu {
+    Intel8086,
+    // Same timings, but every word transfer takes two bus cycles on its 8-bit bus.
+    Intel8088,
+    // The 80186 and 80188 add a few instructions. Clocks still follow the 8086 manual.
+    Intel80186,
+    Intel80188,
+}
+
+impl Cpu {
+    pub fn parse(name: &str) -> Option<Cpu> {
+        match name {
+            "8086" => Some(Cpu::Intel8086),
+            "8088" => Some(Cpu::Intel8088),
+            "80186" => Some(Cpu::Intel80186),
+            "80188" => Some(Cpu::Intel80188),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Cpu::Intel8086 => "8086",
+            Cpu::Intel8088 => "8088",
+            Cpu::Intel80186 => "80186",
+            Cpu::Intel80188 => "80188",
+        }
+    }
+
+    pub fn has_byte_bus(&self) -> bool {
+        matches!(self, Cpu::Intel8088 | Cpu::Intel80188)
+    }
+
+    pub fn has_80186_instructions(&self) -> bool {
+        matches!(self, Cpu::Intel80186 | Cpu::Intel80188)
+    }
+}
+
+pub struct Machine {
+    pub cpu: Cpu,
+    pub simulation_registers: [i16; 8],
+    pub segment_registers: [u16; 4],
+    pub ip: u16,
+    pub flags: Flags,
+    pub memory: Vec<u8>,
+    pub current_clock: u64,
+    pub exit_code: Option<u8>,
+    pub dos: Dos,
+    pub bios: Bios,
+    // The 8087, when one is installed.
+    pub fpu: Option<Fpu>,
+    pub pending_interrupts: VecDeque<u8>,
+    pub pending_nmi: bool,
+    // Set by hlt until an interrupt arrives.
+    pub halted: bool,
+    // Vectors whose default handler runs the host service instead of a bare iret.
+    pub host_interrupts: [bool; 256],
+    // Interrupts are held off for one instruction after ss is loaded.
+    pub interrupt_inhibit: bool,
+    pub call_stack: CallStack,
+    // What has been written, with --memcheck.
+    pub shadow: Option<Shadow>,
+    // Filled in by each step while memory accesses are watched.
+    pub memory_accesses: Option<MemoryAccesses>,
+    // Clocks the current step lost to stack and string words at odd addresses.
+    odd_word_penalty: i16,
+}
+
+// The data memory a step read and wrote, including the stack.
+#[derive(Default)]
+pub struct MemoryAccesses {
+    pub reads: Vec<Location>,
+    pub writes: Vec<Location>,
+}
+
+pub struct ExecutedInstruction {
+    pub instruction: Instruction,
+    // Where the instruction was fetched, after any interrupt delivered before it.
+    pub segment: u16,
+    pub offset: u16,
+    pub output: SimulatorOutput,
+    // Parts of the clocks spent on the memory operand, already included in the output.
+    pub effective_address_cycles: i16,
+    pub transfer_penalty: i16,
+    // The part of the transfer penalty lost only because words were at odd addresses, stack and
+    // string elements included. Always 0 on the 8088, which moves every word a byte at a time.
+    pub alignment_penalty: i16,
+    // Empty unless the machine watches memory accesses.
+    pub memory_accesses: MemoryAccesses,
+}
+
+impl Machine {
+    pub fn new() -> Machine {
+        Machine {
+            cpu: Cpu::Intel8086,
+            simulation_registers: [0; 8],
+            segment_registers: [0; 4],
+            ip: 0,
+            flags: Flags::default(),
+            memory: vec![0; MEMORY_SIZE],
+            current_clock: 0,
+            exit_code: None,
+            dos: Dos::new(PathBuf::from(".")),
+            bios: Bios::new(),
+            fpu: None,
+            pending_interrupts: VecDeque::new(),
+            pending_nmi: false,
+            halted: false,
+            host_interrupts: [true; 256],
+            interrupt_inhibit: false,
+            call_stack: CallStack::default(),
+            shadow: None,
+            memory_accesses: None,
+            odd_word_penalty: 0,
+        }
+    }
+
+    pub fn load(&mut self, segment: u16, offset: u16, bytes: &[u8]) {
+        let start = physical_address(segment, offset);
+        for (i, byte) in bytes.iter().enumerate() {
+            self.memory[(start + i) % MEMORY_SIZE] = *byte;
+        }
+        self.define_memory(start, bytes.len());
+    }
+
+    // Memory the host wrote, which --memcheck counts as initialized.
+    pub fn define_memory(&mut self, address: usize, length: usize) {
+        if let Some(shadow) = &mut self.shadow {
+            shadow.define_memory(address, length);
+        }
+    }
+
+    pub fn define_register(&mut self, w: usize, reg: usize) {
+        if let Some(shadow) = &mut self.shadow {
+            shadow.define(&Location::Register { w, reg });
+        }
+    }
+
+    // Stack and --memcheck problems found since the last call, for the run loop to show.
+    pub fn take_problems(&mut self) -> Vec<String> {
+        let mut problems = self.call_stack.take_problems();
+        if let Some(shadow) = &mut self.shadow {
+            problems.extend(shadow.take_problems());
+        }
+        problems
+    }
+
+    pub fn problem_count(&self) -> u64 {
+        let shadow_problems = self
+            .shadow
+            .as_ref()
+            .map_or(0, |shadow| shadow.problem_count);
+        self.call_stack.problem_count + shadow_problems
+    }
+
+    pub fn decode_at(&self, segment: u16, offset: u16) -> Option<Instruction> {
+        let bytes: Vec<u8> = (0..MAX_INSTRUCTION_LENGTH as u16)
+            .map(|i| self.memory[physical_address(segment, offset.wrapping_add(i))])
+            .collect();
+        decode(&bytes, self.cpu)
+    }
+
+    pub fn step(&mut self) -> Result<ExecutedInstruction, String> {
+        if let Some(memory_accesses) = &mut self.memory_accesses {
+            *memory_accesses = MemoryAccesses::default();
+        }
+        self.odd_word_penalty = 0;
+        let mut delivery_cycles = 0;
+        if self.pending_nmi {
+            self.pending_nmi = false;
+            self.interrupt(NON_MASKABLE_INTERRUPT);
+            delivery_cycles += 50;
+        } else if self.flags.if_ && !self.interrupt_inhibit && !self.pending_interrupts.is_empty() {
+            let vector = self.pending_interrupts.pop_front().unwrap();
+            self.interrupt(vector);
+            delivery_cycles += 61;
+        }
+        if delivery_cycles != 0 {
+            self.halted = false;
+            delivery_cycles += self.run_host_interrupt()?;
+        }
+
+        let old_ip = self.ip;
+        let cs = self.segment_registers[CS];
+        let instruction = self.decode_at(cs, old_ip).ok_or_else(|| {
+            format!(
+                "Unknown instruction {:#04x} at {:04x}:{:04x}",
+                self.memory[physical_address(cs, old_ip)],
+                cs,
+                old_ip
+            )
+        })?;
+        let old_flags = self.flags.clone();
+        let (effective_address_cycles, mut transfer_penalty) = self.memory_timing(&instruction);
+        let mut alignment_penalty = if self.cpu.has_byte_bus() {
+            0
+        } else {
+            transfer_penalty
+        };
+
+        let watched = self.shadow.is_some() || self.memory_accesses.is_some();
+        let accesses = watched.then(|| accesses(self, &instruction));
+        if let (Some(shadow), Some((reads, _))) = (&mut self.shadow, &accesses) {
+            shadow.begin((cs, old_ip), &instruction);
+            for location in reads {
+                shadow.read(location);
+            }
+        }
+        if let (Some(memory_accesses), Some((reads, writes))) =
+            (&mut self.memory_accesses, &accesses)
+        {
+            let memory = |location: &&Location| matches!(location, Location::Memory { .. });
+            memory_accesses
+                .reads
+                .extend(reads.iter().filter(memory).cloned());
+            memory_accesses
+                .writes
+                .extend(writes.iter().filter(memory).cloned());
+        }
+
+        self.ip = old_ip.wrapping_add(instruction.length);
+        let mut output = self.execute(&instruction)?;
+        if let (Some(shadow), Some((_, writes))) = (&mut self.shadow, &accesses) {
+            for location in writes {
+                shadow.define(location);
+            }
+        }
+        output.number_of_cycles += transfer_penalty;
+        // Each prefix takes 2 clocks, repeats are timed with the string instructions.
+        if instruction.segment_override.is_some() {
+            output.number_of_cycles += 2;
+        }
+        self.interrupt_inhibit = instruction.name != "push"
+            && matches!(instruction.destination, Some(Rm::SegmentReg(SS)));
+
+        if let Some(vector) = output.interrupt {
+            self.interrupt(vector);
+        } else if old_flags.tf {
+            // The trap is taken after every instruction that started with TF set.
+            self.interrupt(SINGLE_STEP);
+            output.number_of_cycles += 50;
+        }
+        output.number_of_cycles += delivery_cycles + self.run_host_interrupt()?;
+        output.number_of_cycles += self.odd_word_penalty;
+        transfer_penalty += self.odd_word_penalty;
+        alignment_penalty += self.odd_word_penalty;
+        self.current_clock += output.number_of_cycles as u64;
+        let (ss, sp) = (self.segment_registers[SS], self.simulation_registers[SP]);
+        self.call_stack.check_limit((cs, old_ip), ss, sp as u16);
+
+        Ok(ExecutedInstruction {
+            instruction,
+            segment: cs,
+            offset: old_ip,
+            output,
+            effective_address_cycles,
+            transfer_penalty,
+            alignment_penalty,
+            memory_accesses: self
+                .memory_accesses
+                .as_mut()
+                .map(std::mem::take)
+                .unwrap_or_default(),
+        })
+    }
+
+    // Word transfers take 4 extra clocks on an odd address, and always on an 8-bit bus.
+    fn memory_timing(&self, instruction: &Instruction) -> (i16, i16) {
+        let Some(rm) = instruction.memory_operand() else {
+            return (0, 0);
+        };
+        let address = rm.calculate_memory_index(&self.simulation_registers);
+        let penalty =
+            if instruction.w == 1 && (self.cpu.has_byte_bus() || !address.is_multiple_of(2)) {
+                4 * instruction.memory_transfers() as i16
+            } else {
+                0
+            };
+        let effective_address_cycles = if instruction.accumulator_form {
+            0
+        } else {
+            rm.estimate_cycles()
+        };
+        (effective_address_cycles, penalty)
+    }
+
+    // Whether step would start with an interrupt, the only way out of hlt.
+    pub fn interrupt_ready(&self) -> bool {
+        self.pending_nmi
+            || (self.flags.if_ && !self.interrupt_inhibit && !self.pending_interrupts.is_empty())
+    }
+
+    pub fn raise_interrupt(&mut self, vector: u8) {
+        self.pending_interrupts.push_back(vector);
+    }
+
+    pub fn raise_nmi(&mut self) {
+        self.pending_nmi = true;
+    }
+
+    pub fn push_word(&mut self, value: u16) {
+        let sp = (self.simulation_registers[SP] as u16).wrapping_sub(2);
+        self.simulation_registers[SP] = sp as i16;
+        self.odd_word(sp);
+        let segment = self.segment_registers[SS];
+        write_word(&mut self.memory, segment, sp, value);
+        let slot = Location::Memory {
+            segment,
+            offset: sp,
+            length: 2,
+        };
+        if let Some(shadow) = &mut self.shadow {
+            shadow.define(&slot);
+        }
+        if let Some(memory_accesses) = &mut self.memory_accesses {
+            memory_accesses.writes.push(slot);
+        }
+    }
+
+    pub fn pop_word(&mut self) -> u16 {
+        let sp = self.simulation_registers[SP] as u16;
+        self.simulation_registers[SP] = sp.wrapping_add(2) as i16;
+        self.odd_word(sp);
+        let segment = self.segment_registers[SS];
+        let slot = Location::Memory {
+            segment,
+            offset: sp,
+            length: 2,
+        };
+        if let Some(shadow) = &mut self.shadow {
+            shadow.read(&slot);
+        }
+        if let Some(memory_accesses) = &mut self.memory_accesses {
+            memory_accesses.reads.push(slot);
+        }
+        read_word(&self.memory, segment, sp)
+    }
+
+    // A word the instruction timings don't cover, on the stack or a string element, takes a
+    // second bus cycle at an odd address on the 8086. The 8088 moves every word a byte at a time.
+    fn odd_word(&mut self, offset: u16) {
+        if !offset.is_multiple_of(2) && !self.cpu.has_byte_bus() {
+            self.odd_word_penalty += 4;
+        }
+    }
+
+    // Pushes flags, cs and ip and continues at the handler from the interrupt vector table.
+    pub fn interrupt(&mut self, vector: u8) {
+        let return_address = (self.segment_registers[CS], self.ip);
+        self.push_word(self.flags.to_word());
+        self.flags.if_ = false;
+        self.flags.tf = false;
+        self.push_word(self.segment_registers[CS]);
+        self.push_word(self.ip);
+
+        let (segment, offset) = interrupt_vector(self, vector);
+        self.segment_registers[CS] = segment;
+        self.ip = offset;
+        let slot = self.stack_top();
+        self.call_stack.call(
+            FrameKind::Interrupt(vector),
+            (segment, offset),
+            return_address,
+            slot,
+        );
+    }
+
+    // at is the address of the iret, or of the host handler returning.
+    fn interrupt_return(&mut self, at: (u16, u16)) {
+        let slot = self.stack_top();
+        self.ip = self.pop_word();
+        self.segment_registers[CS] = self.pop_word();
+        self.flags = Flags::from_word(self.pop_word());
+        let returned_to = (self.segment_registers[CS], self.ip);
+        self.call_stack.ret("iret", at, slot, returned_to);
+    }
+
+    fn stack_top(&self) -> (u16, u16) {
+        (
+            self.segment_registers[SS],
+            self.simulation_registers[SP] as u16,
+        )
+    }
+
+    // Runs the host service when cs:ip reached one of the default handlers, then returns from
+    // it. Services report through CF and ZF, so those are copied into the saved flags.
+    fn run_host_interrupt(&mut self) -> Result<i16, String> {
+        if self.segment_registers[CS] != BIOS_SEGMENT
+            || self.ip > 0xff
+            || !self.host_interrupts[self.ip as usize]
+        {
+            return Ok(0);
+        }
+        host_interrupt(self, self.ip as u8)?;
+
+        let sp = self.simulation_registers[SP] as u16;
+        let ss = self.segment_registers[SS];
+        let mut saved_flags = Flags::from_word(read_word(&self.memory, ss, sp.wrapping_add(4)));
+        saved_flags.cf = self.flags.cf;
+        saved_flags.zf = self.flags.zf;
+        write_word(
+            &mut self.memory,
+            ss,
+            sp.wrapping_add(4),
+            saved_flags.to_word(),
+        );
+        self.interrupt_return((BIOS_SEGMENT, self.ip));
+        Ok(24)
+    }
+
+    fn execute(&mut self, instruction: &Instruction) -> Result<SimulatorOutput, String> {
+        if instruction.is_jump() {
+            let displacement = instruction.immediate_value.unwrap();
+            let taken = self.jump_taken(instruction.name)?;
+            if taken {
+                self.ip = self.ip.wrapping_add(displacement as u16);
+            }
+            let (taken_cycles, not_taken_cycles) = match instruction.name {
+                "loop" => (17, 5),
+                "loopz" => (18, 6),
+                "loopnz" => (19, 5),
+                "jcxz" => (18, 6),
+                _ => (16, 4),
+            };
+            return Ok(SimulatorOutput {
+                number_of_cycles: if taken {
+                    taken_cycles
+                } else {
+                    not_taken_cycles
+                },
+                ..Default::default()
+            });
+        }
+
+        match instruction.name {
+            "int" => {
+                return Ok(SimulatorOutput {
+                    number_of_cycles: 51,
+                    interrupt: Some(instruction.immediate_value.unwrap() as u8),
+                    ..Default::default()
+                });
+            }
+            "int3" => {
+                return Ok(SimulatorOutput {
+                    number_of_cycles: 52,
+                    interrupt: Some(BREAKPOINT),
+                    ..Default::default()
+                });
+            }
+            "into" => {
+                return Ok(SimulatorOutput {
+                    number_of_cycles: if self.flags.of { 53 } else { 4 },
+                    interrupt: self.flags.of.then_some(OVERFLOW),
+                    ..Default::default()
+                });
+            }
+            "cmc" | "clc" | "stc" | "cli" | "sti" | "cld" | "std" => {
+                match instruction.name {
+                    "cmc" => self.flags.cf = !self.flags.cf,
+                    "clc" => self.flags.cf = false,
+                    "stc" => self.flags.cf = true,
+                    "cli" => self.flags.if_ = false,
+                    "sti" => self.flags.if_ = true,
+                    "cld" => self.flags.df = false,
+                    _ => self.flags.df = true,
+                }
+                return Ok(SimulatorOutput {
+                    number_of_cycles: 2,
+                    ..Default::default()
+                });
+            }
+            "iret" => {
+                let at = self.ip.wrapping_sub(instruction.length);
+                self.interrupt_return((self.segment_registers[CS], at));
+                return Ok(SimulatorOutput {
+                    number_of_cycles: 24,
+                    ..Default::default()
+                });
+            }
+            // The run loop waits for an interrupt, or stops when none can come.
+            "hlt" => {
+                self.halted = true;
+                return Ok(SimulatorOutput {
+                    number_of_cycles: 2,
+                    ..Default::default()
+                });
+            }
+            // The 8087 finishes each instruction before the next one starts, so there is
+            // never anything to wait for.
+            "wait" => {
+                return Ok(SimulatorOutput {
+                    number_of_cycles: 3,
+                    ..Default::default()
+                });
+            }
+            _ if instruction.is_fpu() => return execute_fpu(self, instruction),
+            "push" => return Ok(self.push(instruction)),
+            "pop" => return Ok(self.pop(instruction)),
+            "pushf" | "popf" => {
+                if instruction.name == "pushf" {
+                    self.push_word(self.flags.to_word());
+                } else {
+                    self.flags = Flags::from_word(self.pop_word());
+                }
+                return Ok(SimulatorOutput {
+                    number_of_cycles: if instruction.name == "pushf" { 10 } else { 8 },
+                    ..Default::default()
+                });
+            }
+            "call" | "jmp" => return Ok(self.transfer(instruction)),
+            "ret" | "retf" => return Ok(self.return_from(instruction)),
+            "pusha" => {
+                let sp = self.simulation_registers[SP];
+                for reg in [AX, CX, DX, BX] {
+                    self.push_word(self.simulation_registers[reg] as u16);
+                }
+                self.push_word(sp as u16);
+                for reg in [BP, SI, DI] {
+                    self.push_word(self.simulation_registers[reg] as u16);
+                }
+                return Ok(SimulatorOutput {
+                    number_of_cycles: 36,
+                    ..Default::default()
+                });
+            }
+            "popa" => {
+                // The saved sp is skipped.
+                for reg in [DI, SI, BP, SP, BX, DX, CX, AX] {
+                    let value = self.pop_word() as i16;
+                    if reg != SP {
+                        self.simulation_registers[reg] = value;
+                    }
+                }
+                return Ok(SimulatorOutput {
+                    number_of_cycles: 51,
+                    ..Default::default()
+                });
+            }
+            "enter" => return Ok(self.enter(instruction)),
+            "leave" => {
+                self.simulation_registers[SP] = self.simulation_registers[BP];
+                self.simulation_registers[BP] = self.pop_word() as i16;
+                return Ok(SimulatorOutput {
+                    number_of_cycles: 8,
+                    ..Default::default()
+                });
+            }
+            "bound" => return Ok(self.bound(instruction)),
+            // al becomes 0xff when CF is set and 0 otherwise, the flags stay.
+            "salc" => {
+                let ax = self.simulation_registers[AX] as u16 & 0xff00;
+                let al = if self.flags.cf { 0xff } else { 0 };
+                self.simulation_registers[AX] = (ax | al) as i16;
+                return Ok(SimulatorOutput {
+                    number_of_cycles: 3,
+                    ..Default::default()
+                });
+            }
+            "movsb" | "movsw" | "cmpsb" | "cmpsw" | "scasb" | "scasw" | "lodsb" | "lodsw"
+            | "stosb" | "stosw" | "insb" | "insw" | "outsb" | "outsw" => {
+                return Ok(self.string(instruction));
+            }
+            "nop" => {
+                return Ok(SimulatorOutput {
+                    number_of_cycles: 3,
+                    ..Default::default()
+                });
+            }
+            // cbw extends the sign of al into ah, cwd that of ax into dx.
+            "cbw" | "cwd" => {
+                let cycles = if instruction.name == "cbw" {
+                    let al = read_register(&self.simulation_registers, 0, AX);
+                    self.simulation_registers[AX] = al as i8 as i16;
+                    2
+                } else {
+                    self.simulation_registers[DX] = self.simulation_registers[AX] >> 15;
+                    5
+                };
+                return Ok(SimulatorOutput {
+                    number_of_cycles: cycles,
+                    ..Default::default()
+                });
+            }
+            // lahf and sahf move sf, zf, af, pf and cf, the low byte of the flags, to and from ah.
+            "lahf" | "sahf" => {
+                let flags = self.flags.to_word();
+                if instruction.name == "lahf" {
+                    write_register(&mut self.simulation_registers, 0, AH, flags as u8 as i16);
+                } else {
+                    let ah = read_register(&self.simulation_registers, 0, AH) as u8;
+                    self.flags = Flags::from_word(flags & 0xff00 | ah as u16);
+                }
+                return Ok(SimulatorOutput {
+                    number_of_cycles: 4,
+                    ..Default::default()
+                });
+            }
+            // al becomes the byte at [bx+al].
+            "xlatb" => {
+                let offset = (self.simulation_registers[BX] as u16).wrapping_add(read_register(
+                    &self.simulation_registers,
+                    0,
+                    AX,
+                )
+                    as u8
+                    as u16);
+                let segment = self.segment_registers[instruction.segment_override.unwrap_or(DS)];
+                let al = self.read_data((segment, offset), 0);
+                write_register(&mut self.simulation_registers, 0, AX, al);
+                return Ok(SimulatorOutput {
+                    number_of_cycles: 11,
+                    ..Default::default()
+                });
+            }
+            "daa" | "das" | "aaa" | "aas" => return Ok(self.decimal_adjust(instruction)),
+            // aam splits al into digits of the base in ah and al, aad joins them back.
+            "aam" | "aad" => {
+                let base = instruction.immediate_value.unwrap() as u8;
+                let ax = self.simulation_registers[AX] as u16;
+                let (al, ah) = (ax as u8, (ax >> 8) as u8);
+                let (ax, cycles) = if instruction.name == "aam" {
+                    if base == 0 {
+                        return Ok(SimulatorOutput {
+                            number_of_cycles: 83,
+                            interrupt: Some(DIVIDE_ERROR),
+                            ..Default::default()
+                        });
+                    }
+                    (((al / base) as u16) << 8 | (al % base) as u16, 83)
+                } else {
+                    (al.wrapping_add(ah.wrapping_mul(base)) as u16, 60)
+                };
+                self.simulation_registers[AX] = ax as i16;
+                self.flags.update_from_value(ax as i16, 0);
+                return Ok(SimulatorOutput {
+                    number_of_cycles: cycles,
+                    ..Default::default()
+                });
+            }
+            "lea" | "lds" | "les" => return Ok(self.load_address(instruction)),
+            // Nothing is attached to the ports, so reads see the bus floating high and writes go
+            // nowhere.
+            "in" | "out" => {
+                let old_value = read_register(&self.simulation_registers, instruction.w, AX);
+                if instruction.name == "in" {
+                    write_register(&mut self.simulation_registers, instruction.w, AX, -1);
+                }
+                return Ok(SimulatorOutput {
+                    old_value,
+                    new_value: read_register(&self.simulation_registers, instruction.w, AX),
+                    number_of_cycles: if instruction.immediate_value.is_some() {
+                        10
+                    } else {
+                        8
+                    },
+                    ..Default::default()
+                });
+            }
+            _ => {}
+        }
+
+        let Some(destination) = &instruction.destination else {
+            return Err(format!(
+                "{} is not supported by the simulator",
+                instruction.name
+            ));
+        };
+        let input = SimulatorInput {
+            simulation_registers: &mut self.simulation_registers,
+            segment_registers: &mut self.segment_registers,
+            memory: &mut self.memory,
+            flags: &mut self.flags,
+            source: instruction.source.as_ref(),
+            destination,
+            immediate_value: instruction.immediate_value,
+            w: instruction.w,
+            segment_override: instruction.segment_override,
+        };
+
+        let shift_simulator: Option<&dyn ShiftSimulator> = match instruction.name {
+            "rol" => Some(&RolShiftSimulator),
+            "ror" => Some(&RorShiftSimulator),
+            "rcl" => Some(&RclShiftSimulator),
+            "rcr" => Some(&RcrShiftSimulator),
+            "shl" => Some(&ShlShiftSimulator),
+            "shr" => Some(&ShrShiftSimulator),
+            "sar" => Some(&SarShiftSimulator),
+            "setmo" | "setmoc" => Some(&SetMinusOneShiftSimulator),
+            _ => None,
+        };
+        if let Some(simulator) = shift_simulator {
+            let (count, by_one) = match instruction.immediate_value {
+                Some(count) => (count as u8, count == 1),
+                None => (input.simulation_registers[CX] as u8, false),
+            };
+            // The 80186 only uses the low five bits of the count, the 8086 shifts up to 255 times.
+            let count = if self.cpu.has_80186_instructions() {
+                count & 0x1f
+            } else {
+                count
+            };
+            return Ok(simulate_shift(simulator, input, count, by_one));
+        }
+
+        if instruction.immediate_value.is_some() {
+            let simulator: &dyn ImmediateToRMSimulator = match instruction.name {
+                "mov" => &MovImmediateToRMSimulator,
+                "add" => &AddImmediateToRMSimulator,
+                "adc" => &AdcImmediateToRMSimulator,
+                "sub" => &SubImmediateToRMSimulator,
+                "sbb" => &SbbImmediateToRMSimulator,
+                "and" => &AndImmediateToRMSimulator,
+                "or" => &OrImmediateToRMSimulator,
+                "xor" => &XorImmediateToRMSimulator,
+                "cmp" => &CmpImmediateToRMSimulator,
+                "test" => &TestImmediateToRMSimulator,
+                "imul" => &ImulImmediateToRMSimulator,
+                name => return Err(format!("{} is not supported by the simulator", name)),
+            };
+            Ok(simulator.simulate(input))
+        } else if instruction.source.is_none() {
+            let simulator: &dyn RmSimulator = match instruction.name {
+                "inc" => &IncRmSimulator,
+                "dec" => &DecRmSimulator,
+                "not" => &NotRmSimulator,
+                "neg" => &NegRmSimulator,
+                "mul" => &MulRmSimulator,
+                "imul" => &ImulRmSimulator,
+                "div" => &DivRmSimulator,
+                "idiv" => &IdivRmSimulator,
+                name => return Err(format!("{} is not supported by the simulator", name)),
+            };
+            Ok(simulator.simulate(input))
+        } else {
+            let simulator: &dyn RMToRmSimulator = match instruction.name {
+                "mov" => &MovRmToRmSimulator,
+                "add" => &AddRmToRmSimulator,
+                "adc" => &AdcRmToRmSimulator,
+                "sub" => &SubRmToRmSimulator,
+                "sbb" => &SbbRmToRmSimulator,
+                "and" => &AndRmToRmSimulator,
+                "or" => &OrRmToRmSimulator,
+                "xor" => &XorRmToRmSimulator,
+                "cmp" => &CmpRmToRmSimulator,
+                "test" => &TestRmToRmSimulator,
+                "xchg" => &XchgRmToRmSimulator,
+                name => return Err(format!("{} is not supported by the simulator", name)),
+            };
+            let mut output = simulator.simulate(input);
+            // The accumulator forms of mov take 10 clocks, address included.
+            if instruction.accumulator_form {
+                output.number_of_cycles = 10;
+            }
+            Ok(output)
+        }
+    }
+
+    fn memory_address(&self, instruction: &Instruction, rm: &Rm) -> (u16, u16) {
+        (
+            self.segment_registers[instruction.segment(rm)],
+            rm.calculate_memory_index(&self.simulation_registers),
+        )
+    }
+
+    // daa and das correct al after adding or subtracting two packed BCD bytes, aaa and aas after
+    // unpacked ones with the carry going to ah.
+    fn decimal_adjust(&mut self, instruction: &Instruction) -> SimulatorOutput {
+        let subtract = instruction.name.ends_with('s');
+        let adjust = |value: u8, by: u8| {
+            if subtract {
+                value.wrapping_sub(by)
+            } else {
+                value.wrapping_add(by)
+            }
+        };
+        let ax = self.simulation_registers[AX] as u16;
+        let (al, ah) = (ax as u8, (ax >> 8) as u8);
+        let low_adjust = al & 0x0f > 9 || self.flags.af;
+        let cycles = if instruction.name.starts_with('d') {
+            let high_adjust = al > 0x99 || self.flags.cf;
+            let mut al = if low_adjust { adjust(al, 6) } else { al };
+            if high_adjust {
+                al = adjust(al, 0x60);
+            }
+            self.flags.cf = high_adjust || (subtract && low_adjust && (ax as u8) < 6);
+            self.flags.af = low_adjust;
+            self.flags.update_from_value(al as i16, 0);
+            write_register(&mut self.simulation_registers, 0, AX, al as i16);
+            4
+        } else {
+            let (al, ah) = if low_adjust {
+                (adjust(al, 6), adjust(ah, 1))
+            } else {
+                (al, ah)
+            };
+            self.flags.cf = low_adjust;
+            self.flags.af = low_adjust;
+            self.simulation_registers[AX] = ((ah as u16) << 8 | (al & 0x0f) as u16) as i16;
+            8
+        };
+        SimulatorOutput {
+            number_of_cycles: cycles,
+            ..Default::default()
+        }
+    }
+
+    // lea loads the offset of its memory operand, lds and les a far pointer from it.
+    fn load_address(&mut self, instruction: &Instruction) -> SimulatorOutput {
+        let Some(Rm::Reg { reg, .. }) = instruction.destination else {
+            unreachable!("lea, lds and les always have a register destination")
+        };
+        let rm = instruction.source.as_ref().unwrap();
+        let (segment, offset) = self.memory_address(instruction, rm);
+        let old_value = self.simulation_registers[reg];
+        if instruction.name == "lea" {
+            self.simulation_registers[reg] = offset as i16;
+        } else {
+            self.simulation_registers[reg] = read_word(&self.memory, segment, offset) as i16;
+            let pointer_segment = read_word(&self.memory, segment, offset.wrapping_add(2));
+            let segment_register = if instruction.name == "lds" { DS } else { ES };
+            self.segment_registers[segment_register] = pointer_segment;
+        }
+        SimulatorOutput {
+            old_value,
+            new_value: self.simulation_registers[reg],
+            number_of_cycles: if instruction.name == "lea" { 2 } else { 16 } + rm.estimate_cycles(),
+            ..Default::default()
+        }
+    }
+
+    // Runs one element of a string instruction. Under a repeat prefix ip goes back to the prefix
+    // until cx runs out, or for cmps and scas until ZF stops matching it.
+    fn string(&mut self, instruction: &Instruction) -> SimulatorOutput {
+        let operation = &instruction.name[..instruction.name.len() - 1];
+        let (cycles, repeated_cycles, start_cycles) = match operation {
+            "movs" => (18, 17, 9),
+            "cmps" => (22, 22, 9),
+            "scas" => (15, 15, 9),
+            "lods" => (12, 13, 9),
+            "stos" => (11, 10, 9),
+            _ => (14, 8, 8),
+        };
+        let Some(repeat) = instruction.repeat else {
+            self.string_element(instruction, operation);
+            return SimulatorOutput {
+                number_of_cycles: cycles,
+                ..Default::default()
+            };
+        };
+
+        let cx = self.simulation_registers[CX] as u16;
+        let mut again = false;
+        if cx != 0 {
+            self.string_element(instruction, operation);
+            self.simulation_registers[CX] = cx.wrapping_sub(1) as i16;
+            let compares = matches!(operation, "cmps" | "scas");
+            again = cx > 1 && (!compares || self.flags.zf == (repeat != "repne"));
+        }
+        if again {
+            self.ip = self.ip.wrapping_sub(instruction.length);
+        }
+        // Starting the repeat is counted with the last element.
+        SimulatorOutput {
+            number_of_cycles: (cx != 0) as i16 * repeated_cycles + (!again) as i16 * start_cycles,
+            ..Default::default()
+        }
+    }
+
+    // movs, cmps and lods read ds:si, where ds can be overridden. movs, stos and ins write es:di,
+    // which cmps and scas compare. Nothing is attached to the ports, so ins sees the bus floating
+    // high and outs writes nowhere.
+    fn string_element(&mut self, instruction: &Instruction, operation: &str) {
+        let w = instruction.w;
+        let source = (
+            self.segment_registers[instruction.segment_override.unwrap_or(DS)],
+            self.simulation_registers[SI] as u16,
+        );
+        let destination = (
+            self.segment_registers[ES],
+            self.simulation_registers[DI] as u16,
+        );
+        let accumulator = read_register(&self.simulation_registers, w, AX);
+        match operation {
+            "movs" => {
+                let value = self.read_data(source, w);
+                self.write_data(destination, w, value);
+            }
+            "cmps" => {
+                let (left, right) = (self.read_data(source, w), self.read_data(destination, w));
+                self.flags.update_from_subtraction(left, right, w);
+            }
+            "scas" => {
+                let right = self.read_data(destination, w);
+                self.flags.update_from_subtraction(accumulator, right, w);
+            }
+            "lods" => {
+                let value = self.read_data(source, w);
+                write_register(&mut self.simulation_registers, w, AX, value);
+            }
+            "stos" => self.write_data(destination, w, accumulator),
+            "ins" => self.write_data(destination, w, -1),
+            _ => {
+                self.read_data(source, w);
+            }
+        }
+
+        let size = w as u16 + 1;
+        let step = if self.flags.df {
+            size.wrapping_neg()
+        } else {
+            size
+        };
+        let (uses_si, uses_di) = match operation {
+            "movs" | "cmps" => (true, true),
+            "lods" | "outs" => (true, false),
+            _ => (false, true),
+        };
+        for (used, reg) in [(uses_si, SI), (uses_di, DI)] {
+            if used {
+                self.simulation_registers[reg] =
+                    (self.simulation_registers[reg] as u16).wrapping_add(step) as i16;
+            }
+        }
+    }
+
+    fn read_data(&mut self, (segment, offset): (u16, u16), w: usize) -> i16 {
+        if w == 1 {
+            self.odd_word(offset);
+            read_word(&self.memory, segment, offset) as i16
+        } else {
+            self.memory[physical_address(segment, offset)] as i16
+        }
+    }
+
+    fn write_data(&mut self, (segment, offset): (u16, u16), w: usize, value: i16) {
+        if w == 1 {
+            self.odd_word(offset);
+            write_word(&mut self.memory, segment, offset, value as u16);
+        } else {
+            self.memory[physical_address(segment, offset)] = value as u8;
+        }
+    }
+
+    // The 8086 and 80186 push sp as it is after the decrement.
+    fn push(&mut self, instruction: &Instruction) -> SimulatorOutput {
+        let (value, cycles) = match &instruction.destination {
+            None => (instruction.immediate_value.unwrap() as u16, 10),
+            Some(Rm::Reg { reg: SP, .. }) => {
+                ((self.simulation_registers[SP] as u16).wrapping_sub(2), 11)
+            }
+            Some(Rm::Reg { reg, .. }) => (self.simulation_registers[*reg] as u16, 11),
+            Some(Rm::SegmentReg(reg)) => (self.segment_registers[*reg], 10),
+            Some(rm) => {
+                let (segment, offset) = self.memory_address(instruction, rm);
+                (
+                    read_word(&self.memory, segment, offset),
+                    16 + rm.estimate_cycles(),
+                )
+            }
+        };
+        self.push_word(value);
+        SimulatorOutput {
+            number_of_cycles: cycles,
+            ..Default::default()
+        }
+    }
+
+    // pop cs, which only the 8086 decodes, carries on at the popped segment with the same ip.
+    fn pop(&mut self, instruction: &Instruction) -> SimulatorOutput {
+        let value = self.pop_word();
+        let cycles = match instruction.destination.as_ref().unwrap() {
+            Rm::Reg { reg, .. } => {
+                self.simulation_registers[*reg] = value as i16;
+                8
+            }
+            Rm::SegmentReg(reg) => {
+                self.segment_registers[*reg] = value;
+                8
+            }
+            rm => {
+                let (segment, offset) = self.memory_address(instruction, rm);
+                write_word(&mut self.memory, segment, offset, value);
+                17 + rm.estimate_cycles()
+            }
+        };
+        SimulatorOutput {
+            number_of_cycles: cycles,
+            ..Default::default()
+        }
+    }
+
+    // call and jmp go relative to ip, to a register or a word in memory, or far to an immediate
+    // segment:offset or a far pointer in memory. Calls push cs for far calls, then ip.
+    fn transfer(&mut self, instruction: &Instruction) -> SimulatorOutput {
+        let call = instruction.name == "call";
+        let cs = self.segment_registers[CS];
+        let clocks =
+            |call_clocks: i16, jump_clocks: i16| if call { call_clocks } else { jump_clocks };
+        let (target, cycles) = match (&instruction.destination, instruction.second_immediate) {
+            (None, Some(segment)) => (
+                (segment as u16, instruction.immediate_value.unwrap() as u16),
+                clocks(28, 15),
+            ),
+            (None, None) => {
+                let displacement = instruction.immediate_value.unwrap() as u16;
+                ((cs, self.ip.wrapping_add(displacement)), clocks(19, 15))
+            }
+            (Some(Rm::Reg { reg, .. }), _) => {
+                ((cs, self.simulation_registers[*reg] as u16), clocks(16, 11))
+            }
+            (Some(rm), _) => {
+                let (segment, offset) = self.memory_address(instruction, rm);
+                let pointer = read_word(&self.memory, segment, offset);
+                if instruction.far {
+                    let pointer_segment = read_word(&self.memory, segment, offset.wrapping_add(2));
+                    (
+                        (pointer_segment, pointer),
+                        clocks(37, 24) + rm.estimate_cycles(),
+                    )
+                } else {
+                    ((cs, pointer), clocks(21, 18) + rm.estimate_cycles())
+                }
+            }
+        };
+
+        if call {
+            let return_address = (cs, self.ip);
+            if instruction.far {
+                self.push_word(cs);
+            }
+            self.push_word(self.ip);
+            let kind = if instruction.far {
+                FrameKind::Far
+            } else {
+                FrameKind::Near
+            };
+            let slot = self.stack_top();
+            self.call_stack.call(kind, target, return_address, slot);
+        }
+        (self.segment_registers[CS], self.ip) = target;
+        SimulatorOutput {
+            number_of_cycles: cycles,
+            ..Default::default()
+        }
+    }
+
+    // ret and retf, which drop the given number of bytes of arguments after the return address.
+    fn return_from(&mut self, instruction: &Instruction) -> SimulatorOutput {
+        let at = (
+            self.segment_registers[CS],
+            self.ip.wrapping_sub(instruction.length),
+        );
+        let slot = self.stack_top();
+        self.ip = self.pop_word();
+        if instruction.far {
+            self.segment_registers[CS] = self.pop_word();
+        }
+        if let Some(bytes) = instruction.immediate_value {
+            let sp = self.simulation_registers[SP] as u16;
+            self.simulation_registers[SP] = sp.wrapping_add(bytes as u16) as i16;
+        }
+        let returned_to = (self.segment_registers[CS], self.ip);
+        self.call_stack.ret(instruction.name, at, slot, returned_to);
+
+        SimulatorOutput {
+            number_of_cycles: match (instruction.far, instruction.immediate_value) {
+                (false, None) => 8,
+                (false, Some(_)) => 12,
+                (true, None) => 18,
+                (true, Some(_)) => 17,
+            },
+            ..Default::default()
+        }
+    }
+
+    // enter size, level: pushes bp, copies level - 1 frame pointers of the enclosing procedures
+    // and reserves size bytes of locals.
+    fn enter(&mut self, instruction: &Instruction) -> SimulatorOutput {
+        let size = instruction.immediate_value.unwrap() as u16;
+        let level = instruction.second_immediate.unwrap() & 0x1f;
+
+        self.push_word(self.simulation_registers[BP] as u16);
+        let frame = self.simulation_registers[SP];
+        if level > 0 {
+            for _ in 1..level {
+                let bp = (self.simulation_registers[BP] as u16).wrapping_sub(2);
+                self.simulation_registers[BP] = bp as i16;
+                self.push_word(read_word(&self.memory, self.segment_registers[SS], bp));
+            }
+            self.push_word(frame as u16);
+        }
+        self.simulation_registers[BP] = frame;
+        let sp = self.simulation_registers[SP] as u16;
+        self.simulation_registers[SP] = sp.wrapping_sub(size) as i16;
+
+        SimulatorOutput {
+            number_of_cycles: match level {
+                0 => 15,
+                1 => 25,
+                level => 22 + 16 * (level - 1),
+            },
+            ..Default::default()
+        }
+    }
+
+    // Raises interrupt 5 when the register is outside the signed word bounds in memory. The
+    // saved ip points at the bound itself so the handler can fix things up and retry.
+    fn bound(&mut self, instruction: &Instruction) -> SimulatorOutput {
+        let Some(Rm::Reg { reg, .. }) = instruction.destination else {
+            unreachable!("bound always has a register destination")
+        };
+        let rm = instruction.source.as_ref().unwrap();
+        let (segment, offset) = self.memory_address(instruction, rm);
+        let lower = read_word(&self.memory, segment, offset) as i16;
+        let upper = read_word(&self.memory, segment, offset.wrapping_add(2)) as i16;
+        let value = self.simulation_registers[reg];
+
+        let out_of_bounds = value < lower || value > upper;
+        if out_of_bounds {
+            self.ip = self.ip.wrapping_sub(instruction.length);
+        }
+        SimulatorOutput {
+            number_of_cycles: 35 + rm.estimate_cycles(),
+            interrupt: out_of_bounds.then_some(BOUND_RANGE_EXCEEDED),
+            ..Default::default()
+        }
+    }
+
+    fn jump_taken(&mut self, name: &str) -> Result<bool, String> {
+        let cx = &mut self.simulation_registers[CX];
+        let flags = &self.flags;
+        Ok(match name {
+            "je" => flags.zf,
+            "jne" => !flags.zf,
+            "jl" => flags.sf != flags.of,
+            "jnl" => flags.sf == flags.of,
+            "jle" => flags.zf || flags.sf != flags.of,
+            "jg" => !flags.zf && flags.sf == flags.of,
+            "jb" => flags.cf,
+            "jnb" => !flags.cf,
+            "jbe" => flags.cf || flags.zf,
+            "ja" => !flags.cf && !flags.zf,
+            "jp" => flags.pf,
+            "jnp" => !flags.pf,
+            "jo" => flags.of,
+            "jno" => !flags.of,
+            "js" => flags.sf,
+            "jns" => !flags.sf,
+            "jcxz" => *cx == 0,
+            "loop" | "loopz" | "loopnz" => {
+                *cx = cx.wrapping_sub(1);
+                *cx != 0
+                    && match name {
+                        "loopz" => flags.zf,
+                        "loopnz" => !flags.zf,
+                        _ => true,
+                    }
+            }
+            name => return Err(format!("{} is not supported by the simulator", name)),
+        })
+    }
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs one string instruction at 0:0 with si and di at 0x100.
+    fn run_string(opcode: u8) -> Machine {
+        let mut machine = Machine::new();
+        machine.cpu = Cpu::Intel80186;
+        machine.load(0, 0, &[opcode]);
+        machine.simulation_registers[SI] = 0x100;
+        machine.simulation_registers[DI] = 0x100;
+        machine.step().unwrap();
+        machine
+    }
+
+    #[test]
+    fn byte_ins_and_outs_move_one_byte() {
+        let machine = run_string(0x6c);
+        assert_eq!(machine.simulation_registers[DI], 0x101);
+        assert_eq!(machine.memory[0x100..0x102], [0xff, 0x00]);
+
+        let machine = run_string(0x6e);
+        assert_eq!(machine.simulation_registers[SI], 0x101);
+    }
+
+    #[test]
+    fn word_ins_and_outs_move_a_word() {
+        let machine = run_string(0x6d);
+        assert_eq!(machine.simulation_registers[DI], 0x102);
+        assert_eq!(machine.memory[0x100..0x103], [0xff, 0xff, 0x00]);
+
+        let machine = run_string(0x6f);
+        assert_eq!(machine.simulation_registers[SI], 0x102);
+    }
+
+    // The clocks and alignment penalty of the instruction at 0:0 with sp, si and di at start.
+    fn odd_word_cycles(bytes: &[u8], start: i16) -> (i16, i16) {
+        let mut machine = Machine::new();
+        machine.load(0, 0, bytes);
+        for reg in [SP, SI, DI] {
+            machine.simulation_registers[reg] = start;
+        }
+        let executed = machine.step().unwrap();
+        (executed.output.number_of_cycles, executed.alignment_penalty)
+    }
+
+    #[test]
+    fn stack_words_at_odd_addresses_cost_a_bus_cycle() {
+        // push ax, pop ax
+        for opcode in [0x50, 0x58] {
+            let (even, no_penalty) = odd_word_cycles(&[opcode], 0x100);
+            assert_eq!(no_penalty, 0);
+            assert_eq!(odd_word_cycles(&[opcode], 0x101), (even + 4, 4));
+        }
+    }
+
+    #[test]
+    fn string_words_at_odd_addresses_cost_a_bus_cycle() {
+        // movsw reads and writes a word, stosw only writes one, movsb is never penalized.
+        let (even, _) = odd_word_cycles(&[0xa5], 0x100);
+        assert_eq!(odd_word_cycles(&[0xa5], 0x101), (even + 8, 8));
+        let (even, _) = odd_word_cycles(&[0xab], 0x100);
+        assert_eq!(odd_word_cycles(&[0xab], 0x101), (even + 4, 4));
+        assert_eq!(odd_word_cycles(&[0xa4], 0x101).1, 0);
+    }
+}
@@ -0,0 +1,277 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::constants::{REGISTER_NAMES, SEGMENT_REGISTER_NAMES};
+use crate::flag::Flags;
+use crate::machine::Machine;
+use crate::memory::{MEMORY_SIZE, physical_address};
+use crate::simulator::{read_register, write_register};
+use crate::symbols::parse_offset;
+
+pub enum Register {
+    General { w: usize, reg: usize },
+    Segment(usize),
+    Ip,
+}
+
+// One change to the initial state of the machine, applied after the program is loaded.
+pub enum Setting {
+    Register(Register, u16),
+    Flag(char, bool),
+    Load(PathBuf, usize),
+    // First and last physical address, inclusive.
+    Fill(usize, usize, u8),
+    // Memory --memcheck counts as initialized, first and last physical address.
+    Defined(usize, usize),
+}
+
+// The value of --reg, --flag, --load, --fill or --defined.
+pub fn parse_setting(option: &str, value: &str) -> Result<Setting, String> {
+    let invalid = || format!("Invalid value {} for {}", value, option);
+    match option {
+        // bx=1000, al=0x7f, ds=0x2000
+        "--reg" => {
+            let (name, number) = value.split_once('=').ok_or_else(invalid)?;
+            let register = parse_register(name.trim()).ok_or_else(invalid)?;
+            let number = parse_offset(number).filter(|number| *number <= 0xffff);
+            Ok(Setting::Register(
+                register,
+                number.ok_or_else(invalid)? as u16,
+            ))
+        }
+        // Z sets the zero flag, Z=0 clears it.
+        "--flag" => {
+            let (name, set) = match value.split_once('=') {
+                Some((name, set)) => (name, parse_bool(set).ok_or_else(invalid)?),
+                None => (value, true),
+            };
+            Ok(Setting::Flag(parse_flag(name).ok_or_else(invalid)?, set))
+        }
+        // data.bin@0x2000
+        "--load" => {
+            let (path, address) = value.rsplit_once('@').ok_or_else(invalid)?;
+            let address = parse_address(address).ok_or_else(invalid)?;
+            Ok(Setting::Load(PathBuf::from(path), address))
+        }
+        // 0x0..0xffff=0xcc
+        "--fill" => {
+            let (range, byte) = value.split_once('=').ok_or_else(invalid)?;
+            let (first, last) = parse_range(range).ok_or_else(invalid)?;
+            let byte = parse_offset(byte).filter(|byte| *byte <= 0xff);
+            Ok(Setting::Fill(first, last, byte.ok_or_else(invalid)? as u8))
+        }
+        // 0x2000..0x20ff
+        "--defined" => {
+            let (first, last) = parse_range(value).ok_or_else(invalid)?;
+            Ok(Setting::Defined(first, last))
+        }
+        _ => Err(format!("Unknown option {}", option)),
+    }
+}
+
+// first..last, physical addresses.
+fn parse_range(text: &str) -> Option<(usize, usize)> {
+    let (first, last) = text.split_once("..")?;
+    let first = parse_address(first)?;
+    let last = parse_address(last).filter(|last| *last >= first)?;
+    Some((first, last))
+}
+
+// An INI style machine description, also valid TOML when values are quoted:
+//
+//     [registers]
+//     bx = 0x1000
+//     [flags]
+//     Z = true
+//     [memory]
+//     load = "data.bin@0x2000"
+//     fill = "0x0..0xffff=0xcc"
+//     defined = "0x2000..0x20ff"
+//
+// Files to load are found relative to the description.
+pub fn parse_machine_file(path: &str) -> Result<Vec<Setting>, String> {
+    let text = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+    let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+    parse_ini(path, &text)?
+        .iter()
+        .map(|entry| {
+            parse_machine_setting(&entry.section, &entry.key, &entry.value, directory)
+                .map_err(|error| entry.error(path, &error))
+        })
+        .collect()
+}
+
+pub struct IniEntry {
+    pub line: usize,
+    // Lowercase, empty before the first [section].
+    pub section: String,
+    pub key: String,
+    // Without the quotes TOML wants around strings.
+    pub value: String,
+}
+
+impl IniEntry {
+    pub fn error(&self, path: &str, message: &str) -> String {
+        format!("{}:{}: {}", path, self.line, message)
+    }
+}
+
+// `key = value` lines under [section] headers, skipping blank lines and # or ; comments.
+pub fn parse_ini(path: &str, text: &str) -> Result<Vec<IniEntry>, String> {
+    let mut section = String::new();
+    let mut entries = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(['#', ';']) {
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            section = name.trim().to_ascii_lowercase();
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("{}:{}: Expected key = value", path, number + 1))?;
+        entries.push(IniEntry {
+            line: number + 1,
+            section: section.clone(),
+            key: key.trim().to_string(),
+            value: value.trim().trim_matches('"').to_string(),
+        });
+    }
+    Ok(entries)
+}
+
+// One `key = value` line of a [registers], [flags] or [memory] section.
+pub fn parse_machine_setting(
+    section: &str,
+    key: &str,
+    value: &str,
+    directory: &Path,
+) -> Result<Setting, String> {
+    match (section, key) {
+        ("registers", register) => parse_setting("--reg", &format!("{}={}", register, value)),
+        ("flags", flag) => parse_setting("--flag", &format!("{}={}", flag, value)),
+        ("memory", "load") => match parse_setting("--load", value)? {
+            Setting::Load(file, address) => Ok(Setting::Load(directory.join(file), address)),
+            setting => Ok(setting),
+        },
+        ("memory", "fill") => parse_setting("--fill", value),
+        ("memory", "defined") => parse_setting("--defined", value),
+        _ => Err(format!("Unknown setting {} in [{}]", key, section)),
+    }
+}
+
+pub fn apply_settings(machine: &mut Machine, settings: &[Setting]) -> Result<(), String> {
+    for setting in settings {
+        match setting {
+            Setting::Register(Register::General { w, reg }, value) => {
+                write_register(&mut machine.simulation_registers, *w, *reg, *value as i16);
+                machine.define_register(*w, *reg);
+            }
+            Setting::Register(Register::Segment(index), value) => {
+                machine.segment_registers[*index] = *value
+            }
+            Setting::Register(Register::Ip, value) => machine.ip = *value,
+            Setting::Flag(letter, set) => *flag(&mut machine.flags, *letter) = *set,
+            Setting::Load(path, address) => {
+                let bytes =
+                    fs::read(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+                for (i, byte) in bytes.iter().enumerate() {
+                    machine.memory[(address + i) % MEMORY_SIZE] = *byte;
+                }
+                machine.define_memory(*address, bytes.len());
+            }
+            Setting::Fill(first, last, byte) => {
+                machine.memory[*first..=*last].fill(*byte);
+                machine.define_memory(*first, last - first + 1);
+            }
+            Setting::Defined(first, last) => machine.define_memory(*first, last - first + 1),
+        }
+    }
+    Ok(())
+}
+
+// The letters the traces show flags with.
+const FLAG_LETTERS: &str = "CPAZSTIDO";
+
+pub fn flag(flags: &mut Flags, letter: char) -> &mut bool {
+    match letter {
+        'C' => &mut flags.cf,
+        'P' => &mut flags.pf,
+        'A' => &mut flags.af,
+        'Z' => &mut flags.zf,
+        'S' => &mut flags.sf,
+        'T' => &mut flags.tf,
+        'I' => &mut flags.if_,
+        'D' => &mut flags.df,
+        _ => &mut flags.of,
+    }
+}
+
+pub fn register_value(machine: &Machine, register: &Register) -> u16 {
+    match register {
+        Register::General { w, reg } => {
+            read_register(&machine.simulation_registers, *w, *reg) as u16
+        }
+        Register::Segment(index) => machine.segment_registers[*index],
+        Register::Ip => machine.ip,
+    }
+}
+
+pub fn parse_register(name: &str) -> Option<Register> {
+    let name = name.to_ascii_lowercase();
+    if name == "ip" {
+        return Some(Register::Ip);
+    }
+    if let Some(index) = SEGMENT_REGISTER_NAMES.iter().position(|n| *n == name) {
+        return Some(Register::Segment(index));
+    }
+    (0..2).find_map(|w| {
+        let reg = REGISTER_NAMES[w].iter().position(|n| *n == name)?;
+        Some(Register::General { w, reg })
+    })
+}
+
+// Z or ZF, in either case.
+pub fn parse_flag(name: &str) -> Option<char> {
+    let name = name.trim().to_ascii_uppercase();
+    let name = name
+        .strip_suffix('F')
+        .filter(|name| !name.is_empty())
+        .unwrap_or(&name);
+    let mut letters = name.chars();
+    let letter = letters.next()?;
+    (letters.next().is_none() && FLAG_LETTERS.contains(letter)).then_some(letter)
+}
+
+pub fn parse_bool(text: &str) -> Option<bool> {
+    match text.trim() {
+        "1" | "true" | "on" => Some(true),
+        "0" | "false" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+// A physical address, or segment:offset in hex like the traces show it.
+pub fn parse_address(text: &str) -> Option<usize> {
+    if text.contains(':') {
+        let (segment, offset) = parse_segment_offset(text)?;
+        return Some(physical_address(segment, offset));
+    }
+    parse_offset(text)
+        .map(|address| address as usize)
+        .filter(|address| *address < MEMORY_SIZE)
+}
+
+pub fn parse_segment_offset(text: &str) -> Option<(u16, u16)> {
+    let hex = |text: &str| {
+        let text = text.trim();
+        u16::from_str_radix(text.strip_prefix("0x").unwrap_or(text), 16).ok()
+    };
+    let (segment, offset) = text.split_once(':')?;
+    Some((hex(segment)?, hex(offset)?))
+}
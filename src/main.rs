@@ -1,320 +1,817 @@
+mod alignment;
+mod call_stack;
 mod constants;
+mod coverage;
+mod debugger;
+mod decoder;
+mod diff;
 mod flag;
+mod formatter;
+mod fpu;
+mod guest_tests;
+mod heatmap;
+mod instruction;
+mod instruments;
+mod interrupts;
+mod json;
+mod limits;
+mod listing;
+mod loader;
+mod machine;
+mod machine_config;
+mod memory;
+mod nasm_listing;
+mod ndisasm;
+mod profile;
 mod rm;
+mod shadow;
 mod simulator;
+mod single_step_tests;
+mod snapshot;
+mod snapshots;
+mod symbols;
+mod trace;
 
-use std::fs::File;
-use std::io::prelude::*;
-use std::{env, io::SeekFrom};
-
-use constants::{
-    ACCUMULATOR_NAMES, IMMEDIATE_TO_ACCUMULATOR_INSTRUCTIONS,
-    IMMEDIATE_TO_REGISTER_MEMORY_INSTRUCTION, IMMEDIATE_TO_REGISTER_MEMORY_INSTRUCTION_MOV,
-    MOVE_IMMEDIATE_TO_REGISTER_INSTRUCTION, REGISTER_NAMES, RETURN_INSTRUCTIONS,
-};
-use flag::Flags;
-use rm::Rm;
-use simulator::immediate_to_rm_simulator::MovImmediateToRMSimulator;
-use simulator::{SimulatorInput, SimulatorOutput};
-use simulator::{
-    immediate_to_rm_simulator::{
-        AddImmediateToRMSimulator, CmpImmediateToRMSimulator, ImmediateToRMSimulator,
-        SubImmediateToRMSimulator,
-    },
-    rm_to_rm_simulator::{
-        AddRmToRmSimulator, CmpRmToRmSimulator, MovRmToRmSimulator, RMToRmSimulator,
-        SubRmToRmSimulator,
-    },
-};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use alignment::AlignmentLint;
+use call_stack::{StackLimit, backtrace};
+use constants::{CS, SP, SS};
+use coverage::Coverage;
+use debugger::Debugger;
+use formatter::att_formatter::AttFormatter;
+use formatter::masm_formatter::MasmFormatter;
+use formatter::nasm_formatter::NasmFormatter;
+use formatter::{FormatOptions, Formatter, Signedness};
+use fpu::Fpu;
+use guest_tests::run_guest_tests;
+use heatmap::Heatmap;
+use instruments::Instruments;
+use interrupts::dos::flush_stdout;
+use interrupts::install_interrupt_vectors;
+use limits::{Limits, StopReason};
+use loader::DEFAULT_LOAD_SEGMENT;
+use loader::com_loader::load_com;
+use loader::exe_loader::load_exe;
+use machine::{Cpu, Machine, MemoryAccesses};
+use machine_config::{apply_settings, parse_machine_file, parse_setting};
+use memory::physical_address;
+use nasm_listing::parse_nasm_listing;
+use ndisasm::{NdisasmOptions, parse_address, print_ndisasm};
+use profile::Profile;
+use shadow::Shadow;
+use single_step_tests::{SingleStepOptions, run_single_step_tests};
+use snapshot::{load_state, save_state};
+use snapshots::{SnapshotOptions, run_snapshots};
+use symbols::Symbols;
+use trace::{RegisterState, TraceOptions};
 
 fn main() -> std::io::Result<()> {
     let args: Vec<String> = env::args().collect();
     assert!(args.len() >= 2);
 
-    let simulation_mode = args[1] == "--exec";
-    let mut file = File::open(args.last().unwrap())?;
-    let mut current_byte = [0u8];
-
-    let rm_to_rm_instructions: [(u8, &str, Box<dyn RMToRmSimulator>); 4] = [
-        (0b10001000, "mov", Box::new(MovRmToRmSimulator)),
-        (0b00000000, "add", Box::new(AddRmToRmSimulator)),
-        (0b00101000, "sub", Box::new(SubRmToRmSimulator)),
-        (0b00111000, "cmp", Box::new(CmpRmToRmSimulator)),
-    ];
-
-    let immediate_to_register_instructions: [(&str, Box<dyn ImmediateToRMSimulator>); 8] = [
-        ("add", Box::new(AddImmediateToRMSimulator)),
-        ("1", Box::new(AddImmediateToRMSimulator)),
-        ("2", Box::new(AddImmediateToRMSimulator)),
-        ("3", Box::new(AddImmediateToRMSimulator)),
-        ("4", Box::new(AddImmediateToRMSimulator)),
-        ("sub", Box::new(SubImmediateToRMSimulator)),
-        ("6", Box::new(AddImmediateToRMSimulator)),
-        ("cmp", Box::new(CmpImmediateToRMSimulator)),
-    ];
-
-    println!("; {}\n", args.last().unwrap());
-    println!("bits 16\n");
-
-    if args[1] == "--print-binary" {
-        while let Ok(_) = file.read_exact(&mut current_byte) {
-            print!("{:#010b} ", current_byte[0]);
+    if args[1] == "run-com" || args[1] == "run-exe" {
+        let mut machine = Machine::new();
+        let mut load_segment = None;
+        let mut scheduled_interrupts = Vec::new();
+        let mut instruments = Instruments::default();
+        let mut coverage_listing = None;
+        let mut symbols_file = None;
+        let mut breakpoints = Vec::new();
+        let mut debug = false;
+        let mut load_state_file = None;
+        let mut save_state_file = None;
+        let mut settings = Vec::new();
+        let mut limits = Limits::default();
+        let mut stop_at = Vec::new();
+        let mut stack_limit = None;
+        let mut file_index = 2;
+        while file_index < args.len() && args[file_index].starts_with("--") {
+            // Options without a value.
+            let flag = match args[file_index].as_str() {
+                "--fpu" => {
+                    machine.fpu = Some(Fpu::new());
+                    true
+                }
+                "--profile" => {
+                    instruments.profile.get_or_insert_with(Profile::default);
+                    true
+                }
+                "--debug" => {
+                    debug = true;
+                    true
+                }
+                "--memcheck" => {
+                    machine.shadow = Some(Shadow::new());
+                    true
+                }
+                "--heatmap" => {
+                    instruments.heatmap.get_or_insert_with(Heatmap::new);
+                    true
+                }
+                "--lint-alignment" => {
+                    instruments.alignment = Some(AlignmentLint::default());
+                    true
+                }
+                _ => false,
+            };
+            if flag {
+                file_index += 1;
+                continue;
+            }
+            let value = args.get(file_index + 1).ok_or_else(|| {
+                io::Error::other(format!("Missing value for {}", args[file_index]))
+            })?;
+            match args[file_index].as_str() {
+                "--load-segment" => {
+                    load_segment = Some(parse_number(value).ok_or_else(|| {
+                        io::Error::other(format!("Invalid load segment {}", value))
+                    })?)
+                }
+                "--cpu" => {
+                    machine.cpu = Cpu::parse(value)
+                        .ok_or_else(|| io::Error::other(format!("Unknown cpu {}", value)))?
+                }
+                "--folded" => {
+                    instruments.folded = Some(value.clone());
+                    instruments.profile.get_or_insert_with(Profile::default);
+                }
+                "--coverage" => coverage_listing = Some(value),
+                "--lcov" => instruments.lcov = Some((value.clone(), String::new())),
+                "--heatmap-csv" => {
+                    instruments.heatmap_csv = Some(value.clone());
+                    instruments.heatmap.get_or_insert_with(Heatmap::new);
+                }
+                "--heatmap-image" => {
+                    instruments.heatmap_image = Some(value.clone());
+                    instruments.heatmap.get_or_insert_with(Heatmap::new);
+                }
+                "--symbols" => symbols_file = Some(value),
+                "--break" => breakpoints.push(value),
+                "--load-state" => load_state_file = Some(value),
+                "--save-state" => save_state_file = Some(value),
+                "--reg" | "--flag" | "--load" | "--fill" | "--defined" => settings
+                    .push(parse_setting(&args[file_index], value).map_err(io::Error::other)?),
+                "--machine" => {
+                    settings.extend(parse_machine_file(value).map_err(io::Error::other)?)
+                }
+                "--max-instructions" | "--max-clocks" => {
+                    let limit = value.parse().map_err(|_| {
+                        io::Error::other(format!(
+                            "Invalid value {} for {}",
+                            value, args[file_index]
+                        ))
+                    })?;
+                    if args[file_index] == "--max-clocks" {
+                        limits.max_clocks = Some(limit);
+                    } else {
+                        limits.max_instructions = Some(limit);
+                    }
+                }
+                "--stop-at" => stop_at.push(value),
+                "--stack-limit" => {
+                    stack_limit = Some(parse_stack_limit(value).ok_or_else(|| {
+                        io::Error::other(format!("Invalid stack limit {}", value))
+                    })?)
+                }
+                "--dos-root" => machine.dos.root = PathBuf::from(value),
+                "--keys" => machine.bios.keys = Some(fs::read(value)?.into()),
+                "--guest-interrupt" => {
+                    let vector = parse_number(value)
+                        .filter(|vector| *vector <= 0xff)
+                        .ok_or_else(|| io::Error::other(format!("Invalid vector {}", value)))?;
+                    machine.host_interrupts[vector as usize] = false;
+                }
+                "--interrupt-at" => {
+                    scheduled_interrupts.push(parse_scheduled_interrupt(value).ok_or_else(
+                        || io::Error::other(format!("Invalid scheduled interrupt {}", value)),
+                    )?)
+                }
+                option => return Err(io::Error::other(format!("Unknown option {}", option))),
+            }
+            file_index += 2;
+        }
+
+        let path = args
+            .get(file_index)
+            .ok_or_else(|| io::Error::other("Missing program file"))?;
+        let file = fs::read(path)?;
+        let arguments = &args[file_index + 1..];
+        let program_name = Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        // After the options, so that --memcheck sees the vectors written.
+        install_interrupt_vectors(&mut machine);
+        // Where offset 0 of the program's nasm listing ends up.
+        let listing_base = if args[1] == "run-com" {
+            let segment = load_segment.unwrap_or(DEFAULT_LOAD_SEGMENT);
+            load_com(&mut machine, segment, &file, &program_name, arguments)
+                .map_err(io::Error::other)?;
+            physical_address(segment, 0x100)
+        } else {
+            let segment = load_segment.unwrap_or(DEFAULT_LOAD_SEGMENT + 0x10);
+            load_exe(&mut machine, segment, &file, &program_name, arguments)
+                .map_err(io::Error::other)?;
+            physical_address(segment, 0)
+        };
+        // The loader set up the stack.
+        machine.define_register(1, SP);
+        // The program is still loaded, it places the listing and symbols.
+        if let Some(path) = load_state_file {
+            load_state(&mut machine, &fs::read(path)?).map_err(io::Error::other)?;
+        }
+        apply_settings(&mut machine, &settings).map_err(io::Error::other)?;
+        // A .COM program may end by popping the zero word DOS pushed for it.
+        let pushed_by_dos = if args[1] == "run-com" { 2 } else { 0 };
+        set_stack_limit(&mut machine, stack_limit, pushed_by_dos);
+        add_coverage(&mut instruments, coverage_listing, listing_base)?;
+        watch_memory(&mut machine, &instruments);
+        let symbols = load_symbols(symbols_file, listing_base)?;
+        let mut debugger = start_debugger(debug, &breakpoints, &symbols)?;
+        limits.stop_at = resolve_stop_addresses(&stop_at, &symbols)?;
+
+        scheduled_interrupts.sort_by_key(|(clock, _)| *clock);
+        let result = run(
+            &mut machine,
+            &scheduled_interrupts,
+            &mut instruments,
+            &symbols,
+            &mut debugger,
+            &mut limits,
+        );
+        flush_stdout();
+        if let Some(path) = save_state_file {
+            fs::write(path, save_state(&machine).map_err(io::Error::other)?)?;
         }
+        instruments.report().map_err(io::Error::other)?;
+        let reason = result.map_err(io::Error::other)?;
+        if let Some(summary) = reason.summary(&machine, &limits) {
+            eprintln!("{}", summary);
+        }
+        process::exit(reason.exit_status());
     }
 
-    let mut simulation_registers = [0; 8];
-    let mut memory = [0u8; 65536];
-    let mut flags = Flags {
-        zf: false,
-        sf: false,
-    };
-    let mut current_clock = 0i16;
-
-    let mut old_ip = 0;
-    while let Ok(_) = file.read_exact(&mut current_byte) {
-        let current_byte = current_byte[0];
-        let old_flags = flags.clone();
-        let mut old_value = 0;
-        let mut new_value = 0;
-        let mut prefix = "";
-        let mut instruction_name = "";
-        let mut destination: Option<Rm> = None;
-        let mut source_rm: Option<Rm> = None;
-        let mut immediate_value: Option<i16> = None;
-        let mut default_print = true;
-        let mut number_of_cycles = 0i16;
-
-        if MOVE_IMMEDIATE_TO_REGISTER_INSTRUCTION == current_byte & 0b11110000 {
-            let w = ((0b1000 & current_byte) >> 3) as usize;
-            let reg = 0b111 & current_byte as usize;
-            let data = read_date(&mut file, w == 0);
-            number_of_cycles = 4;
-
-            instruction_name = "mov";
-            destination = Some(Rm::Reg { w, reg });
-
-            if w == 1 && simulation_mode {
-                old_value = simulation_registers[reg];
-                simulation_registers[reg] = data;
-                new_value = simulation_registers[reg];
-                immediate_value = Some(data);
+    if args[1] == "ndisasm" {
+        let mut options = NdisasmOptions {
+            cpu: Cpu::Intel8086,
+            origin: 0,
+            skip_header: 0,
+            skip_ranges: Vec::new(),
+        };
+        let mut index = 2;
+        while index < args.len() && args[index].starts_with('-') {
+            let value = args
+                .get(index + 1)
+                .ok_or_else(|| io::Error::other(format!("Missing value for {}", args[index])))?;
+            let invalid = || io::Error::other(format!("Invalid value {}", value));
+            match args[index].as_str() {
+                "-o" => options.origin = parse_address(value).ok_or_else(invalid)?,
+                "-e" => options.skip_header = parse_address(value).ok_or_else(invalid)? as usize,
+                "-k" => {
+                    let (start, length) = value.split_once(',').ok_or_else(invalid)?;
+                    options.skip_ranges.push((
+                        parse_address(start).ok_or_else(invalid)?,
+                        parse_address(length).ok_or_else(invalid)?,
+                    ));
+                }
+                "-b" if value == "16" => {}
+                "--cpu" => options.cpu = Cpu::parse(value).ok_or_else(invalid)?,
+                option => return Err(io::Error::other(format!("Unknown option {}", option))),
             }
-        } else if IMMEDIATE_TO_REGISTER_MEMORY_INSTRUCTION == current_byte & 0b11111100 {
-            let mut next_byte = [0u8];
-            file.read_exact(&mut next_byte).unwrap();
-            let next_byte = next_byte[0];
-
-            let mod_value = (0b11000000 & next_byte) >> 6;
-            let one_byte = (current_byte & 0b11) != 0b01;
-            let rm = Rm::new(
-                &mut file,
-                mod_value,
-                (0b1 & current_byte) as usize,
-                (0b111 & next_byte) as usize,
-            );
-            let data = read_date(&mut file, one_byte);
-            prefix = if mod_value == 0b11 {
-                ""
-            } else {
-                if one_byte { "byte " } else { "word " }
-            };
+            index += 2;
+        }
+
+        let path = args
+            .get(index)
+            .ok_or_else(|| io::Error::other("Missing program file"))?;
+        print_ndisasm(&fs::read(path)?, &options);
+        return Ok(());
+    }
 
-            let operation_index = ((next_byte & 0b111000) >> 3) as usize;
-
-            instruction_name = immediate_to_register_instructions[operation_index].0;
-            destination = Some(rm.clone());
-            immediate_value = Some(data);
-
-            if simulation_mode {
-                SimulatorOutput {
-                    old_value,
-                    new_value,
-                    number_of_cycles,
-                } = immediate_to_register_instructions[operation_index]
-                    .1
-                    .simulate(SimulatorInput {
-                        simulation_registers: &mut simulation_registers,
-                        memory: &mut memory,
-                        flags: &mut flags,
-                        destination: &rm,
-                        immediate_value: Some(data),
-                        source: None,
-                    });
+    if args[1] == "single-step-tests" {
+        let mut options = SingleStepOptions {
+            cpu: Cpu::Intel8086,
+            metadata: None,
+            only: Vec::new(),
+            details: false,
+        };
+        let mut index = 2;
+        while index < args.len() && args[index].starts_with("--") {
+            if args[index] == "--details" {
+                options.details = true;
+                index += 1;
+                continue;
             }
-        } else if IMMEDIATE_TO_REGISTER_MEMORY_INSTRUCTION_MOV == current_byte & 0b11111110 {
-            let mut next_byte = [0u8];
-            file.read_exact(&mut next_byte).unwrap();
-            let next_byte = next_byte[0];
-
-            let mod_value = (0b11000000 & next_byte) >> 6;
-            let w = (current_byte & 0b1) as usize;
-            let rm = Rm::new(&mut file, mod_value, w, (0b111 & next_byte) as usize);
-            let data = read_date(&mut file, w != 1);
-            prefix = if mod_value == 0b11 {
-                ""
-            } else {
-                if w != 1 { "byte " } else { "word " }
-            };
-            instruction_name = "mov";
-            destination = Some(rm.clone());
-            immediate_value = Some(data);
-
-            if simulation_mode {
-                SimulatorOutput {
-                    old_value,
-                    new_value,
-                    number_of_cycles,
-                } = MovImmediateToRMSimulator.simulate(SimulatorInput {
-                    simulation_registers: &mut simulation_registers,
-                    memory: &mut memory,
-                    flags: &mut flags,
-                    destination: &rm,
-                    immediate_value: Some(data),
-                    source: None,
-                });
-            } else {
-                print!("{} {}{}, {}", "mov", prefix, rm, data);
+            let value = args
+                .get(index + 1)
+                .ok_or_else(|| io::Error::other(format!("Missing value for {}", args[index])))?;
+            match args[index].as_str() {
+                "--cpu" => {
+                    options.cpu = Cpu::parse(value)
+                        .ok_or_else(|| io::Error::other(format!("Unknown cpu {}", value)))?
+                }
+                "--metadata" => options.metadata = Some(PathBuf::from(value)),
+                "--only" => options.only = value.split(',').map(String::from).collect(),
+                option => return Err(io::Error::other(format!("Unknown option {}", option))),
             }
-        } else if let Some(instruction) = rm_to_rm_instructions
-            .iter()
-            .find(|i| i.0 == current_byte & 0b11111100)
-        {
-            let mut next_byte = [0u8];
-            file.read_exact(&mut next_byte).unwrap();
-            let next_byte = next_byte[0];
+            index += 2;
+        }
 
-            let w = 0b00000001 & current_byte as usize;
-            let d = (0b00000010 & current_byte) >> 1;
+        let directory = args
+            .get(index)
+            .ok_or_else(|| io::Error::other("Missing test directory"))?;
+        let passed =
+            run_single_step_tests(Path::new(directory), &options).map_err(io::Error::other)?;
+        process::exit(if passed { 0 } else { 1 });
+    }
 
-            let mod_value = (0b11000000 & next_byte) >> 6;
-            let reg = Rm::Reg {
-                w,
-                reg: ((0b111000 & next_byte) >> 3) as usize,
-            };
-            let rm = Rm::new(&mut file, mod_value, w, 0b111 & next_byte as usize);
-
-            let source;
-            let dst;
-            if d == 0 {
-                source = reg;
-                dst = rm;
-            } else {
-                source = rm;
-                dst = reg;
+    if args[1] == "snapshot" {
+        let mut options = SnapshotOptions {
+            bless: false,
+            directory: None,
+            trace_options: Vec::new(),
+        };
+        let mut index = 2;
+        while index < args.len() && args[index].starts_with("--") {
+            match args[index].as_str() {
+                "--bless" => options.bless = true,
+                "--snapshots" | "--cpu" => {
+                    let value = args.get(index + 1).ok_or_else(|| {
+                        io::Error::other(format!("Missing value for {}", args[index]))
+                    })?;
+                    if args[index] == "--cpu" {
+                        options
+                            .trace_options
+                            .extend([args[index].clone(), value.clone()]);
+                    } else {
+                        options.directory = Some(PathBuf::from(value));
+                    }
+                    index += 1;
+                }
+                "--show-clocks" | "--no-ip" | "--fpu" | "--8088" => {
+                    options.trace_options.push(args[index].clone())
+                }
+                option => return Err(io::Error::other(format!("Unknown option {}", option))),
             }
+            index += 1;
+        }
+        let passed = run_snapshots(&args[index..], &options).map_err(io::Error::other)?;
+        process::exit(if passed { 0 } else { 1 });
+    }
 
-            instruction_name = instruction.1;
-            destination = Some(dst.clone());
-            source_rm = Some(source.clone());
-
-            if simulation_mode {
-                SimulatorOutput {
-                    old_value,
-                    new_value,
-                    number_of_cycles,
-                } = instruction.2.simulate(SimulatorInput {
-                    simulation_registers: &mut simulation_registers,
-                    memory: &mut memory,
-                    flags: &mut flags,
-                    source: Some(&source),
-                    destination: &dst,
-                    immediate_value: None,
-                });
-            }
-        } else if let Some(instruction) = IMMEDIATE_TO_ACCUMULATOR_INSTRUCTIONS
-            .iter()
-            .find(|i| i.0 == current_byte & 0b11111110)
-        {
-            let w = 0b1 & current_byte;
-            let data = read_date(&mut file, w == 0);
-            print!(
-                "{} {}, {}",
-                instruction.1, ACCUMULATOR_NAMES[w as usize], data
-            );
-        } else if let Some(instruction) = RETURN_INSTRUCTIONS
-            .iter()
-            .find(|i| i.0 == current_byte & 0b11111111)
-        {
-            let data = read_date(&mut file, true);
+    if args[1] == "test" {
+        let passed = run_guest_tests(&args[2..]).map_err(io::Error::other)?;
+        process::exit(if passed { 0 } else { 1 });
+    }
 
-            if simulation_mode {
-                if instruction.1 == "jne" && !flags.zf {
-                    file.seek(SeekFrom::Current(data as i64))
-                        .expect("Seek error");
+    let mut options = Vec::new();
+    let mut format_options = FormatOptions::default();
+    let mut syntax = "nasm";
+    let mut cpu = Cpu::Intel8086;
+    let mut instruments = Instruments::default();
+    let mut coverage_listing = None;
+    let mut symbols_file = None;
+    let mut breakpoints = Vec::new();
+    let mut settings = Vec::new();
+    let mut limits = Limits::default();
+    let mut stop_at = Vec::new();
+    let mut stack_limit = None;
+    let mut index = 1;
+    while index < args.len() - 1 {
+        match args[index].as_str() {
+            "--syntax" => {
+                index += 1;
+                syntax = &args[index];
+            }
+            "--cpu" => {
+                index += 1;
+                cpu = Cpu::parse(&args[index])
+                    .ok_or_else(|| io::Error::other(format!("Unknown cpu {}", args[index])))?;
+            }
+            "--8088" => cpu = Cpu::Intel8088,
+            "--folded" => {
+                index += 1;
+                instruments.folded = Some(args[index].clone());
+                instruments.profile.get_or_insert_with(Profile::default);
+            }
+            "--coverage" => {
+                index += 1;
+                coverage_listing = Some(&args[index]);
+            }
+            "--lcov" => {
+                index += 1;
+                instruments.lcov = Some((args[index].clone(), String::new()));
+            }
+            option @ ("--heatmap-csv" | "--heatmap-image") => {
+                index += 1;
+                if option == "--heatmap-csv" {
+                    instruments.heatmap_csv = Some(args[index].clone());
+                } else {
+                    instruments.heatmap_image = Some(args[index].clone());
                 }
+                instruments.heatmap.get_or_insert_with(Heatmap::new);
+            }
+            "--symbols" => {
+                index += 1;
+                symbols_file = Some(&args[index]);
             }
-            print!("{} ; {}", instruction.1, data);
-            default_print = false;
+            "--break" => {
+                index += 1;
+                breakpoints.push(&args[index]);
+            }
+            option @ ("--reg" | "--flag" | "--load" | "--fill" | "--defined") => {
+                index += 1;
+                settings.push(parse_setting(option, &args[index]).map_err(io::Error::other)?);
+            }
+            "--machine" => {
+                index += 1;
+                settings.extend(parse_machine_file(&args[index]).map_err(io::Error::other)?);
+            }
+            option @ ("--max-instructions" | "--max-clocks") => {
+                index += 1;
+                let limit = args[index].parse().map_err(|_| {
+                    io::Error::other(format!("Invalid value {} for {}", args[index], option))
+                })?;
+                if option == "--max-clocks" {
+                    limits.max_clocks = Some(limit);
+                } else {
+                    limits.max_instructions = Some(limit);
+                }
+            }
+            "--stop-at" => {
+                index += 1;
+                stop_at.push(&args[index]);
+            }
+            "--stack-limit" => {
+                index += 1;
+                stack_limit = Some(parse_stack_limit(&args[index]).ok_or_else(|| {
+                    io::Error::other(format!("Invalid stack limit {}", args[index]))
+                })?);
+            }
+            "--hex" => format_options.hex = true,
+            "--signed" => format_options.signedness = Signedness::Signed,
+            "--unsigned" => format_options.signedness = Signedness::Unsigned,
+            option
+                if [
+                    "--exec",
+                    "--print-binary",
+                    "--show-clocks",
+                    "--no-ip",
+                    "--listing",
+                    "--explain",
+                    "--fpu",
+                    "--profile",
+                    "--debug",
+                    "--memcheck",
+                    "--heatmap",
+                    "--lint-alignment",
+                ]
+                .contains(&option) =>
+            {
+                options.push(option)
+            }
+            option => return Err(io::Error::other(format!("Unknown option {}", option))),
         }
+        index += 1;
+    }
+    let formatter: &dyn Formatter = match syntax {
+        "nasm" => &NasmFormatter,
+        "masm" => &MasmFormatter,
+        "att" => &AttFormatter,
+        syntax => return Err(io::Error::other(format!("Unknown syntax {}", syntax))),
+    };
+    let path = args.last().unwrap();
+    let program = fs::read(path)?;
 
-        let new_ip = file.stream_position().unwrap();
+    let mut machine = Machine::new();
+    machine.cpu = cpu;
+    if options.contains(&"--memcheck") {
+        machine.shadow = Some(Shadow::new());
+    }
+    machine.load(0, 0, &program);
+    apply_settings(&mut machine, &settings).map_err(io::Error::other)?;
+    set_stack_limit(&mut machine, stack_limit, 0);
+    let symbols = load_symbols(symbols_file, 0)?;
 
-        if default_print {
-            let source = if let Some(immediate_value) = immediate_value {
-                immediate_value.to_string()
-            } else {
-                source_rm.unwrap().to_string()
-            };
-            if simulation_mode {
-                current_clock += number_of_cycles;
-                print!(
-                    "{} {}{}, {} ; {}:{:#06x}->{:#06x} ; flags:{}->{}; Clocks: +{} = {}",
-                    instruction_name,
-                    prefix,
-                    destination.clone().expect("No destination filled"),
-                    source,
-                    destination.unwrap(),
-                    old_value,
-                    new_value,
-                    old_flags,
-                    flags,
-                    number_of_cycles,
-                    current_clock
-                );
-            } else {
-                print!(
-                    "{} {}{}, {}",
-                    instruction_name,
-                    prefix,
-                    destination.clone().expect("No destination filled"),
-                    source
-                );
+    if options.contains(&"--exec") {
+        if options.contains(&"--fpu") {
+            machine.fpu = Some(Fpu::new());
+        }
+        let trace_options = TraceOptions {
+            show_clocks: options.contains(&"--show-clocks"),
+            show_ip: !options.contains(&"--no-ip"),
+        };
+        if options.contains(&"--profile") {
+            instruments.profile.get_or_insert_with(Profile::default);
+        }
+        if options.contains(&"--heatmap") {
+            instruments.heatmap.get_or_insert_with(Heatmap::new);
+        }
+        if options.contains(&"--lint-alignment") {
+            instruments.alignment = Some(AlignmentLint::default());
+        }
+        add_coverage(&mut instruments, coverage_listing, 0)?;
+        watch_memory(&mut machine, &instruments);
+        let debug = options.contains(&"--debug");
+        let mut debugger = start_debugger(debug, &breakpoints, &symbols)?;
+        limits.stop_at = resolve_stop_addresses(&stop_at, &symbols)?;
+        println!("{}", trace::header(path, machine.cpu, &trace_options));
+        let reason = execute(
+            &mut machine,
+            program.len(),
+            &trace_options,
+            &mut instruments,
+            &symbols,
+            &mut debugger,
+            &mut limits,
+        );
+        instruments.report().map_err(io::Error::other)?;
+        if let Some(reason) = reason.map_err(io::Error::other)? {
+            if let Some(summary) = reason.summary(&machine, &limits) {
+                eprintln!("{}", summary);
             }
+            process::exit(reason.exit_status());
         }
-        println!("; ip:{:#04x}->{:#04x}", old_ip, new_ip);
-        old_ip = new_ip;
+        return Ok(());
     }
 
-    if simulation_mode {
-        println!("\nFinal registers:");
-        for i in 0..(simulation_registers.len()) {
-            if simulation_registers[i] == 0 {
-                continue;
-            }
+    if options.contains(&"--listing") {
+        let explain = options.contains(&"--explain");
+        listing::print_listing(&program, cpu, formatter, &format_options, explain, &symbols);
+        return Ok(());
+    }
+
+    println!("{} {}\n", formatter.comment(), path);
+    println!("{}\n", formatter.mode_directive());
+
+    if options.contains(&"--print-binary") {
+        for byte in &program {
+            print!("{:#010b} ", byte);
+        }
+        println!("\n");
+    }
+
+    while (machine.ip as usize) < program.len() {
+        let old_ip = machine.ip;
+        let Some(instruction) = machine.decode_at(0, machine.ip) else {
             println!(
-                "\t{}: {:#06x} ({})",
-                REGISTER_NAMES[1][i], simulation_registers[i], simulation_registers[i],
+                "{} Unknown instruction {:#04x}",
+                formatter.comment(),
+                program[old_ip as usize]
             );
+            machine.ip += 1;
+            continue;
+        };
+        let address = old_ip as usize;
+        print!("{}", symbols.header(address, formatter.comment()));
+        print!("{}", formatter.format_marked(&instruction, &format_options));
+        print!(
+            "{}",
+            symbols.jump_note(address, &instruction, formatter.comment())
+        );
+        machine.ip += instruction.length;
+        println!(
+            "{} ip:{:#04x}->{:#04x}",
+            formatter.comment(),
+            old_ip,
+            machine.ip
+        );
+    }
+
+    Ok(())
+}
+
+// Runs a flat program loaded at 0000:0000 until ip leaves it or a limit stops it, printing a
+// sim86 style trace. None when it ended in the debugger, an error when a step failed.
+fn execute(
+    machine: &mut Machine,
+    program_length: usize,
+    options: &TraceOptions,
+    instruments: &mut Instruments,
+    symbols: &Symbols,
+    debugger: &mut Option<Debugger>,
+    limits: &mut Limits,
+) -> Result<Option<StopReason>, String> {
+    let mut reason = limits.check_start(machine);
+    let mut failure = None;
+    while reason.is_none() {
+        if let Some(exit_code) = machine.exit_code {
+            reason = Some(StopReason::Exited(exit_code));
+            break;
+        }
+        if machine.ip as usize >= program_length {
+            reason = Some(StopReason::EndOfProgram);
+            break;
         }
+        if let Some(debugger) = debugger
+            && !debugger.before_step(machine, symbols)
+        {
+            break;
+        }
+        let before = RegisterState::capture(machine);
+        let address = (machine.segment_registers[CS], machine.ip);
+        let executed = match machine.step() {
+            Ok(executed) => executed,
+            Err(error) => {
+                println!("; {}", error);
+                failure = Some(error);
+                break;
+            }
+        };
+        let after = RegisterState::capture(machine);
+        instruments.record(address, &executed, machine);
+        let address = physical_address(executed.segment, executed.offset);
+        print!("{}", symbols.header(address, ";"));
         println!(
-            "\tip: {:#06x} ({})",
-            file.stream_position().unwrap(),
-            file.stream_position().unwrap()
+            "{}",
+            trace::format_step(&executed, &before, &after, machine.current_clock, options)
         );
-        println!("\tflags: {}", flags);
+        for problem in machine.take_problems() {
+            println!("; {}", problem);
+            for line in backtrace(machine, symbols).lines() {
+                println!(";   {}", line);
+            }
+        }
+        reason = limits.check(machine, &executed, false);
+    }
+
+    println!(
+        "\n{}",
+        trace::final_registers(&RegisterState::capture(machine), options)
+    );
+    if let Some(fpu) = &machine.fpu {
+        println!("Final 8087 registers:\n{}", fpu::format_state(fpu));
+    }
+    match failure {
+        Some(error) => Err(error),
+        None => Ok(reason),
+    }
+}
+
+fn run(
+    machine: &mut Machine,
+    scheduled_interrupts: &[(u64, Option<u8>)],
+    instruments: &mut Instruments,
+    symbols: &Symbols,
+    debugger: &mut Option<Debugger>,
+    limits: &mut Limits,
+) -> Result<StopReason, String> {
+    let mut scheduled_interrupts = scheduled_interrupts.iter().peekable();
+    if let Some(reason) = limits.check_start(machine) {
+        return Ok(reason);
+    }
+    loop {
+        if let Some(exit_code) = machine.exit_code {
+            return Ok(StopReason::Exited(exit_code));
+        }
+        while let Some((_, vector)) =
+            scheduled_interrupts.next_if(|(clock, _)| *clock <= machine.current_clock)
+        {
+            match vector {
+                Some(vector) => machine.raise_interrupt(*vector),
+                None => machine.raise_nmi(),
+            }
+        }
+        // A halted cpu idles until the next interrupt.
+        if machine.halted
+            && !machine.interrupt_ready()
+            && let Some((clock, _)) = scheduled_interrupts.peek()
+        {
+            machine.current_clock = *clock;
+            continue;
+        }
+        if let Some(debugger) = debugger
+            && !debugger.before_step(machine, symbols)
+        {
+            return Err(String::from("Quit in the debugger"));
+        }
+        let before = (machine.segment_registers[CS], machine.ip);
+        let executed = machine.step()?;
+        instruments.record(before, &executed, machine);
+        for problem in machine.take_problems() {
+            flush_stdout();
+            eprintln!("{}", problem);
+            eprint!("{}", backtrace(machine, symbols));
+        }
+        // Only the NMI gets through while interrupts are disabled.
+        let interrupts_ahead = scheduled_interrupts
+            .clone()
+            .any(|(_, vector)| vector.is_none() || machine.flags.if_);
+        if let Some(reason) = limits.check(machine, &executed, interrupts_ahead) {
+            return Ok(reason);
+        }
+    }
+}
+
+// The heatmap counts the memory accesses each step reports.
+fn watch_memory(machine: &mut Machine, instruments: &Instruments) {
+    if instruments.heatmap.is_some() {
+        machine.memory_accesses = Some(MemoryAccesses::default());
     }
+}
 
+// The lcov file names the source next to the listing, with an .asm extension.
+fn add_coverage(
+    instruments: &mut Instruments,
+    listing: Option<&String>,
+    base: usize,
+) -> io::Result<()> {
+    let Some(listing) = listing else {
+        return match instruments.lcov {
+            Some(_) => Err(io::Error::other("--lcov needs a --coverage listing")),
+            None => Ok(()),
+        };
+    };
+    let lines = parse_nasm_listing(&fs::read_to_string(listing)?);
+    instruments.coverage = Some(Coverage::new(lines, base));
+    if let Some((_, source_path)) = &mut instruments.lcov {
+        *source_path = Path::new(listing)
+            .with_extension("asm")
+            .to_string_lossy()
+            .into_owned();
+    }
     Ok(())
 }
 
-fn read_date(file: &mut File, one_byte: bool) -> i16 {
-    if one_byte {
-        let mut data = [0u8];
-        file.read_exact(&mut data).unwrap();
-        return (data[0] as i8) as i16;
+// Labels and source lines from a nasm listing or label map, else only program offsets.
+fn load_symbols(path: Option<&String>, base: usize) -> io::Result<Symbols> {
+    match path {
+        Some(path) => Symbols::load(&fs::read_to_string(path)?, base).map_err(io::Error::other),
+        None => Ok(Symbols::empty(base)),
+    }
+}
+
+// A debugger when asked for one or when there are breakpoints to stop at.
+fn start_debugger(
+    debug: bool,
+    breakpoints: &[&String],
+    symbols: &Symbols,
+) -> io::Result<Option<Debugger>> {
+    if !debug && breakpoints.is_empty() {
+        return Ok(None);
+    }
+    let mut debugger = Debugger::new(debug);
+    for breakpoint in breakpoints {
+        debugger
+            .add_breakpoint(breakpoint, symbols)
+            .map_err(io::Error::other)?;
+    }
+    Ok(Some(debugger))
+}
+
+fn resolve_stop_addresses(stop_at: &[&String], symbols: &Symbols) -> io::Result<Vec<usize>> {
+    stop_at
+        .iter()
+        .map(|text| {
+            symbols
+                .resolve(text)
+                .ok_or_else(|| io::Error::other(format!("Unknown location {}", text)))
+        })
+        .collect()
+}
+
+// <lowest> or <lowest>..<top>, offsets in the stack segment.
+fn parse_stack_limit(text: &str) -> Option<(u16, Option<u16>)> {
+    match text.split_once("..") {
+        Some((lowest, top)) => Some((parse_number(lowest)?, Some(parse_number(top)?))),
+        None => Some((parse_number(text)?, None)),
+    }
+}
+
+// The stack is ss as the program starts, empty at the initial sp unless the top was given.
+// pushed is how many bytes the loader already pushed for the program.
+fn set_stack_limit(machine: &mut Machine, limit: Option<(u16, Option<u16>)>, pushed: u16) {
+    let Some((lowest, top)) = limit else {
+        return;
+    };
+    let sp = machine.simulation_registers[SP] as u16;
+    machine.call_stack.limit = Some(StackLimit {
+        segment: machine.segment_registers[SS],
+        lowest,
+        top: top.unwrap_or(sp.wrapping_add(pushed)),
+    });
+}
+
+// <clock>:<vector> or <clock>:nmi
+fn parse_scheduled_interrupt(text: &str) -> Option<(u64, Option<u8>)> {
+    let (clock, vector) = text.split_once(':')?;
+    let clock = clock.parse().ok()?;
+    if vector == "nmi" {
+        return Some((clock, None));
+    }
+    let vector = parse_number(vector).filter(|vector| *vector <= 0xff)?;
+    Some((clock, Some(vector as u8)))
+}
+
+fn parse_number(text: &str) -> Option<u16> {
+    if let Some(hex) = text.strip_prefix("0x") {
+        u16::from_str_radix(hex, 16).ok()
     } else {
-        let mut data = [0u8, 0u8];
-        file.read_exact(&mut data).unwrap();
-        return ((data[1] as i16) << 8) | data[0] as i16;
+        text.parse().ok()
     }
 }
@@ -0,0 +1,16 @@
+pub const MEMORY_SIZE: usize = 0x100000;
+
+pub fn physical_address(segment: u16, offset: u16) -> usize {
+    (((segment as usize) << 4) + offset as usize) % MEMORY_SIZE
+}
+
+pub fn read_word(memory: &[u8], segment: u16, offset: u16) -> u16 {
+    let low = memory[physical_address(segment, offset)] as u16;
+    let high = memory[physical_address(segment, offset.wrapping_add(1))] as u16;
+    (high << 8) | low
+}
+
+pub fn write_word(memory: &mut [u8], segment: u16, offset: u16, value: u16) {
+    memory[physical_address(segment, offset)] = value as u8;
+    memory[physical_address(segment, offset.wrapping_add(1))] = (value >> 8) as u8;
+}
@@ -0,0 +1,92 @@
+// Reads the listings written by `nasm -l`:
+//
+//     12 00000003 B80100                  mov ax, 1
+//
+// the source line number, the offset in the section, the assembled bytes and the source text
+// from column 40 on. Lines that assemble to nothing leave the offset and bytes blank.
+const SOURCE_COLUMN: usize = 40;
+
+const DATA_DIRECTIVES: [&str; 12] = [
+    "db", "dw", "dd", "dq", "dt", "times", "resb", "resw", "resd", "resq", "rest", "incbin",
+];
+
+pub struct SourceLine {
+    pub number: usize,
+    // Offset and length of the bytes the line assembled to.
+    pub offset: Option<u32>,
+    pub length: u32,
+    pub source: String,
+}
+
+impl SourceLine {
+    // Lines that assembled to instructions rather than data.
+    pub fn is_code(&self) -> bool {
+        if self.offset.is_none() || self.length == 0 {
+            return false;
+        }
+        !DATA_DIRECTIVES.contains(&self.mnemonic().as_str())
+    }
+
+    // The first word of the statement after any label, in lowercase.
+    pub fn mnemonic(&self) -> String {
+        let (_, statement) = split_label(&self.source);
+        let mnemonic = statement.split_whitespace().next().unwrap_or("");
+        mnemonic.to_ascii_lowercase()
+    }
+}
+
+// Splits `top: mov ax, 1` into the label and the statement, dropping any comment.
+pub fn split_label(source: &str) -> (Option<&str>, &str) {
+    let source = source.split(';').next().unwrap_or("");
+    match source.split_once(':') {
+        Some((label, rest))
+            if !label.trim().is_empty() && !label.trim().contains(char::is_whitespace) =>
+        {
+            (Some(label.trim()), rest)
+        }
+        _ => (None, source),
+    }
+}
+
+pub fn parse_nasm_listing(text: &str) -> Vec<SourceLine> {
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let Some(number) = line.get(..6).and_then(|number| number.trim().parse().ok()) else {
+            continue;
+        };
+        let fields = line.get(7..).unwrap_or("");
+        let offset = fields
+            .get(..8)
+            .filter(|offset| offset.chars().all(|c| c.is_ascii_hexdigit()))
+            .and_then(|offset| u32::from_str_radix(offset, 16).ok());
+        let source = line.get(SOURCE_COLUMN..).unwrap_or("").to_string();
+
+        // Relocated values are shown in brackets or parentheses, continuations end with a -.
+        let bytes = fields.get(9..).unwrap_or("").split_whitespace().next();
+        let digits = match (offset, bytes) {
+            (Some(_), Some(bytes)) => bytes.chars().filter(char::is_ascii_hexdigit).count(),
+            _ => 0,
+        };
+        // A continuation carries on the bytes of the previous line.
+        if let Some(previous) = lines.last_mut()
+            && let SourceLine {
+                number: previous_number,
+                offset: Some(previous_offset),
+                length,
+                ..
+            } = previous
+            && *previous_number == number
+            && offset == Some(*previous_offset + *length)
+        {
+            *length += digits as u32 / 2;
+            continue;
+        }
+        lines.push(SourceLine {
+            number,
+            offset,
+            length: digits as u32 / 2,
+            source,
+        });
+    }
+    lines
+}
@@ -0,0 +1,260 @@
+use crate::constants::{
+    EFFECTIVE_MEMOERY_ADDRESS, REGISTER_NAMES, REPEAT_PREFIX, SEGMENT_REGISTER_NAMES,
+    SHIFT_INSTRUCTION,
+};
+use crate::decoder::{decode, is_prefix};
+use crate::formatter::size_keyword;
+use crate::instruction::Instruction;
+use crate::machine::Cpu;
+use crate::rm::Rm;
+
+// ndisasm pads the hex column so instructions start at column 28.
+const BYTES_WIDTH: usize = 18;
+
+// ndisasm prefers the flag-based mnemonics.
+const MNEMONICS: [(&str, &str); 10] = [
+    ("je", "jz"),
+    ("jne", "jnz"),
+    ("jb", "jc"),
+    ("jnb", "jnc"),
+    ("jbe", "jna"),
+    ("jp", "jpe"),
+    ("jnp", "jpo"),
+    ("jle", "jng"),
+    ("loopz", "loope"),
+    ("loopnz", "loopne"),
+];
+
+pub struct NdisasmOptions {
+    pub cpu: Cpu,
+    pub origin: u32,
+    pub skip_header: usize,
+    // (start, length) ranges, given as addresses like the ones printed.
+    pub skip_ranges: Vec<(u32, u32)>,
+}
+
+// Accepts the number forms ndisasm does: 0x1f, 1fh and decimal.
+pub fn parse_address(text: &str) -> Option<u32> {
+    let text = text.to_ascii_lowercase();
+    if let Some(hex) = text.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(hex) = text.strip_suffix('h') {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+pub fn print_ndisasm(file: &[u8], options: &NdisasmOptions) {
+    let program = file.get(options.skip_header..).unwrap_or(&[]);
+    let mut position = 0;
+    while position < program.len() {
+        let address = options.origin.wrapping_add(position as u32);
+
+        if let Some((start, length)) = options
+            .skip_ranges
+            .iter()
+            .find(|(start, _)| *start == address)
+        {
+            println!("{:08X}  skipping {:#X} bytes", start, length);
+            position += (*length as usize).max(1);
+            continue;
+        }
+
+        // Instructions never run into the next skipped range.
+        let next_skip = options
+            .skip_ranges
+            .iter()
+            .filter(|(start, _)| *start > address)
+            .map(|(start, _)| (start - address) as usize)
+            .min()
+            .unwrap_or(usize::MAX);
+        let end = program.len().min(position.saturating_add(next_skip));
+        let bytes = &program[position..end];
+
+        let (length, text) = match decode(bytes, options.cpu) {
+            Some(instruction) => (
+                instruction.length as usize,
+                instruction_text(&instruction, bytes, address),
+            ),
+            None => (1, format!("db {:#04x}", bytes[0])),
+        };
+        let hex: String = bytes[..length]
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        println!("{:08X}  {:<BYTES_WIDTH$}{}", address, hex, text);
+        position += length;
+    }
+}
+
+// ndisasm writes a segment override inside the memory operand, `mov ax,[es:bx]`, and in front
+// of the mnemonic with the repeat prefixes when there is none, `rep es movsb`.
+fn instruction_text(instruction: &Instruction, bytes: &[u8], address: u32) -> String {
+    let has_memory_operand = [&instruction.destination, &instruction.source]
+        .into_iter()
+        .flatten()
+        .any(Rm::is_memory);
+    let prefixes = bytes.iter().take_while(|byte| is_prefix(**byte)).count();
+    let mut text = String::new();
+    for byte in &bytes[..prefixes] {
+        if REPEAT_PREFIX == byte & 0b11111110 {
+            text += instruction.repeat.unwrap_or("rep");
+            text += " ";
+        } else if !has_memory_operand {
+            text += SEGMENT_REGISTER_NAMES[(byte >> 3 & 0b11) as usize];
+            text += " ";
+        }
+    }
+    text + &operation_text(instruction, bytes[prefixes], address)
+}
+
+fn operation_text(instruction: &Instruction, opcode: u8, address: u32) -> String {
+    let name = MNEMONICS
+        .iter()
+        .find(|(name, _)| *name == instruction.name)
+        .map_or(instruction.name, |(_, mnemonic)| mnemonic);
+
+    if instruction.is_relative() {
+        let target = (address as u16)
+            .wrapping_add(instruction.length)
+            .wrapping_add(instruction.immediate_value.unwrap() as u16);
+        // Only the two byte jmp is marked, the conditional ones have no other form.
+        let short = if name == "jmp" && instruction.length == 2 {
+            "short "
+        } else {
+            ""
+        };
+        return format!("{} {}{:#x}", name, short, target);
+    }
+    if let (Some(offset), Some(segment)) =
+        (instruction.immediate_value, instruction.second_immediate)
+        && instruction.far
+    {
+        return format!("{} {:#x}:{:#x}", name, segment as u16, offset as u16);
+    }
+    // The base is only written when it isn't the usual 10.
+    if matches!(name, "aam" | "aad") && instruction.immediate_value == Some(10) {
+        return String::from(name);
+    }
+
+    // ndisasm leaves st0 implied: `fadd st1`, `fadd to st1` and `faddp st1`.
+    if let (Some(Rm::FpuReg(destination)), Some(Rm::FpuReg(source))) =
+        (&instruction.destination, &instruction.source)
+    {
+        return if *destination == 0 {
+            format!("{} st{}", name, source)
+        } else if name.ends_with('p') {
+            format!("{} st{}", name, destination)
+        } else {
+            format!("{} to st{}", name, destination)
+        };
+    }
+
+    let mut operands = Vec::new();
+    for rm in [&instruction.destination, &instruction.source]
+        .into_iter()
+        .flatten()
+    {
+        operands.push(operand_text(rm, instruction.segment_override));
+    }
+    // The size is spelled out only when no register operand implies it. Near calls and jumps
+    // always take a word.
+    if let Some(destination) = &instruction.destination
+        && destination.is_memory()
+        && instruction.source.is_none()
+    {
+        let size = match instruction.name {
+            _ if instruction.far => Some("far"),
+            "call" | "jmp" => None,
+            _ => size_keyword(instruction),
+        };
+        if let Some(size) = size {
+            operands[0] = format!("{} {}", size, operands[0]);
+        }
+    }
+    if let Some(value) = instruction.immediate_value {
+        operands.push(if SHIFT_INSTRUCTION == opcode & 0b11111100 {
+            String::from("1")
+        } else if instruction.signed_immediate {
+            let sign = if value < 0 { '-' } else { '+' };
+            format!("byte {}{:#x}", sign, value.unsigned_abs())
+        } else if instruction.w == 0 {
+            format!("{:#x}", value as u8)
+        } else if instruction.destination.is_none() && instruction.name == "push" {
+            format!("word {:#x}", value as u16)
+        } else {
+            format!("{:#x}", value as u16)
+        });
+    }
+    // An immediate port comes first in out.
+    if instruction.name == "out" && instruction.immediate_value.is_some() {
+        operands.rotate_left(1);
+    }
+    if let Some(level) = instruction.second_immediate {
+        operands.push(format!("{:#x}", level));
+    }
+
+    if operands.is_empty() {
+        String::from(name)
+    } else {
+        format!("{} {}", name, operands.join(","))
+    }
+}
+
+fn operand_text(rm: &Rm, segment: Option<usize>) -> String {
+    let segment = segment.map_or(String::new(), |segment| {
+        format!("{}:", SEGMENT_REGISTER_NAMES[segment])
+    });
+    match rm {
+        Rm::Reg { w, reg } => String::from(REGISTER_NAMES[*w][*reg]),
+        Rm::SegmentReg(reg) => String::from(SEGMENT_REGISTER_NAMES[*reg]),
+        Rm::FpuReg(i) => format!("st{}", i),
+        Rm::DirectMemory(address) => format!("[{}{:#x}]", segment, address),
+        Rm::MemoryNoDisplacment(rm) => {
+            format!("[{}{}]", segment, EFFECTIVE_MEMOERY_ADDRESS[*rm])
+        }
+        Rm::MemoryWithDisplacment { rm, displacment } => {
+            let displacment = *displacment as i16;
+            let sign = if displacment < 0 { '-' } else { '+' };
+            format!(
+                "[{}{}{}{:#x}]",
+                segment,
+                EFFECTIVE_MEMOERY_ADDRESS[*rm],
+                sign,
+                displacment.unsigned_abs()
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(bytes: &[u8], address: u32) -> String {
+        let instruction = decode(bytes, Cpu::Intel80186).unwrap();
+        instruction_text(&instruction, bytes, address)
+    }
+
+    #[test]
+    fn numbers_are_read_in_the_ndisasm_forms() {
+        assert_eq!(parse_address("0x1F"), Some(0x1f));
+        assert_eq!(parse_address("1fh"), Some(0x1f));
+        assert_eq!(parse_address("31"), Some(31));
+        assert_eq!(parse_address("zz"), None);
+    }
+
+    #[test]
+    fn instructions_are_written_like_ndisasm() {
+        assert_eq!(text(&[0x26, 0x8b, 0x07], 0), "mov ax,[es:bx]");
+        assert_eq!(text(&[0xf3, 0x26, 0xa4], 0), "rep es movsb");
+        assert_eq!(text(&[0x74, 0x02], 0x100), "jz 0x104");
+        assert_eq!(text(&[0xeb, 0xfe], 0x100), "jmp short 0x100");
+        assert_eq!(text(&[0x83, 0xc0, 0xff], 0), "add ax,byte -0x1");
+        assert_eq!(text(&[0xfe, 0x07], 0), "inc byte [bx]");
+        assert_eq!(text(&[0xd4, 0x0a], 0), "aam");
+        assert_eq!(text(&[0x68, 0x34, 0x12], 0), "push word 0x1234");
+        assert_eq!(text(&[0xe6, 0x60], 0), "out 0x60,al");
+    }
+}
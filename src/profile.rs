@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::call_stack::{CallStack, address_text};
+use crate::machine::ExecutedInstruction;
+
+// How many hot spots the report lists.
+const HOT_SPOTS: usize = 20;
+
+type Address = (u16, u16);
+
+#[derive(Default)]
+struct Counts {
+    executed: u64,
+    clocks: u64,
+}
+
+struct Block {
+    counts: Counts,
+    // Address of the last instruction seen in the block.
+    end: Address,
+}
+
+// Executed counts and clocks per instruction and basic block, and clocks per call stack.
+// Blocks end at every jump, call, return or interrupt, and start wherever execution lands.
+#[derive(Default)]
+pub struct Profile {
+    instructions: HashMap<Address, (Counts, String)>,
+    blocks: HashMap<Address, Block>,
+    current_block: Option<Address>,
+    // Where the program started and the targets of the calls and interrupts entered since, as
+    // the machine's call stack has them.
+    stack: Vec<Address>,
+    stacks: HashMap<String, u64>,
+}
+
+impl Profile {
+    // before is cs:ip before the step, after is cs:ip after it and call_stack the frames left
+    // by it.
+    pub fn record(
+        &mut self,
+        before: Address,
+        executed: &ExecutedInstruction,
+        after: Address,
+        call_stack: &CallStack,
+    ) {
+        let address = (executed.segment, executed.offset);
+        let clocks = executed.output.number_of_cycles as u64;
+        if self.stack.is_empty() {
+            self.stack.push(address);
+        }
+        // A hardware interrupt was delivered before the instruction.
+        if address != before {
+            self.stack.push(address);
+        }
+
+        let (counts, _) = self
+            .instructions
+            .entry(address)
+            .or_insert_with(|| (Counts::default(), executed.instruction.to_string()));
+        counts.executed += 1;
+        counts.clocks += clocks;
+
+        let start = *self.current_block.get_or_insert(address);
+        let block = self.blocks.entry(start).or_insert(Block {
+            counts: Counts::default(),
+            end: address,
+        });
+        if start == address {
+            block.counts.executed += 1;
+        }
+        block.counts.clocks += clocks;
+        block.end = block.end.max(address);
+
+        let stack: Vec<String> = self
+            .stack
+            .iter()
+            .map(|entry| address_text(*entry))
+            .collect();
+        *self.stacks.entry(stack.join(";")).or_default() += clocks;
+
+        let instruction = &executed.instruction;
+        let next = (address.0, address.1.wrapping_add(instruction.length));
+        if after != next || instruction.is_jump() || executed.output.interrupt.is_some() {
+            self.current_block = None;
+        }
+
+        // The next instruction runs in the frames the calls, interrupts and returns of this one
+        // left.
+        self.stack.truncate(1);
+        self.stack
+            .extend(call_stack.frames.iter().map(|frame| frame.target));
+    }
+
+    pub fn report(&self) -> String {
+        let total: u64 = self
+            .instructions
+            .values()
+            .map(|(counts, _)| counts.clocks)
+            .sum();
+        let executed: u64 = self
+            .instructions
+            .values()
+            .map(|(counts, _)| counts.executed)
+            .sum();
+        let percent = |clocks: u64| 100.0 * clocks as f64 / total.max(1) as f64;
+
+        let mut text = format!("Profile: {} instructions, {} clocks\n", executed, total);
+        text += "\nHot instructions:\n";
+        text += "  address      executed     clocks       %  instruction\n";
+        let mut instructions: Vec<_> = self.instructions.iter().collect();
+        instructions
+            .sort_by_key(|(address, (counts, _))| (std::cmp::Reverse(counts.clocks), **address));
+        for (address, (counts, instruction)) in instructions.into_iter().take(HOT_SPOTS) {
+            writeln!(
+                text,
+                "  {}  {:>9}  {:>9}  {:>5.1}%  {}",
+                address_text(*address),
+                counts.executed,
+                counts.clocks,
+                percent(counts.clocks),
+                instruction
+            )
+            .unwrap();
+        }
+
+        text += "\nHot blocks:\n";
+        text += "  start      end          entries     clocks       %\n";
+        let mut blocks: Vec<_> = self.blocks.iter().collect();
+        blocks.sort_by_key(|(start, block)| (std::cmp::Reverse(block.counts.clocks), **start));
+        for (start, block) in blocks.into_iter().take(HOT_SPOTS) {
+            writeln!(
+                text,
+                "  {}  {}  {:>9}  {:>9}  {:>5.1}%",
+                address_text(*start),
+                address_text(block.end),
+                block.counts.executed,
+                block.counts.clocks,
+                percent(block.counts.clocks)
+            )
+            .unwrap();
+        }
+        text
+    }
+
+    // One `entry;entry;entry clocks` line per call stack, the input of flamegraph.pl and friends.
+    pub fn folded_stacks(&self) -> String {
+        let mut stacks: Vec<_> = self.stacks.iter().collect();
+        stacks.sort();
+        stacks
+            .into_iter()
+            .map(|(stack, clocks)| format!("{} {}\n", stack, clocks))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{CS, SP};
+    use crate::machine::Machine;
+
+    fn folded_stacks(program: &[u8]) -> Vec<String> {
+        let mut machine = Machine::new();
+        machine.load(0, 0, program);
+        machine.simulation_registers[SP] = 0x1000;
+        let mut profile = Profile::default();
+        while !machine.halted {
+            let before = (machine.segment_registers[CS], machine.ip);
+            let executed = machine.step().unwrap();
+            let after = (machine.segment_registers[CS], machine.ip);
+            profile.record(before, &executed, after, &machine.call_stack);
+        }
+        profile
+            .folded_stacks()
+            .lines()
+            .map(|line| line.rsplit_once(' ').unwrap().0.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn stacks_follow_calls_and_returns() {
+        let stacks = folded_stacks(&[
+            0xe8, 0x01, 0x00, // call 0x4
+            0xf4, // hlt
+            0xe8, 0x01, 0x00, // call 0x8
+            0xc3, // ret
+            0xc3, // ret
+        ]);
+        assert_eq!(
+            stacks,
+            [
+                "0000:0000",
+                "0000:0000;0000:0004",
+                "0000:0000;0000:0004;0000:0008"
+            ]
+        );
+    }
+
+    #[test]
+    fn a_return_used_as_a_jump_is_not_a_call() {
+        let stacks = folded_stacks(&[
+            0xb8, 0x06, 0x00, // mov ax, 0x6
+            0x50, // push ax
+            0xc3, // ret
+            0x90, // nop
+            0xf4, // hlt
+        ]);
+        assert_eq!(stacks, ["0000:0000"]);
+    }
+}
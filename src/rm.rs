@@ -1,13 +1,16 @@
-use std::{fmt::Display, fs::File, io::Read};
+use std::io::Read;
 
-use crate::{REGISTER_NAMES, constants::EFFECTIVE_MEMOERY_ADDRESS};
+use crate::constants::{DS, SS};
 
 #[derive(Debug, Clone)]
 pub enum Rm {
     Reg { w: usize, reg: usize },
+    SegmentReg(usize),
     DirectMemory(u16),
     MemoryWithDisplacment { rm: usize, displacment: u16 },
     MemoryNoDisplacment(usize),
+    // st(i) of the 8087 register stack.
+    FpuReg(usize),
 }
 
 pub const MAPPTING_TO_EFFECTIVE_MEMORY_ADDRESS: [(usize, Option<usize>); 8] = [
@@ -21,81 +24,90 @@ pub const MAPPTING_TO_EFFECTIVE_MEMORY_ADDRESS: [(usize, Option<usize>); 8] = [
     (3, None),
 ];
 
-pub const NO_DISPLACEMENT_CYCLES_ESTIMATIONS: [i16; 8] = [
-    7, 7, 8, 8, 5, 5, 5, 5
-];
-
-pub const DISPLACEMENT_CYCLES_ESTIMATIONS: [i16; 8] = [
-    11, 12, 12, 11, 9, 9, 9, 9
-];
+pub const NO_DISPLACEMENT_CYCLES_ESTIMATIONS: [i16; 8] = [7, 8, 8, 7, 5, 5, 5, 5];
 
+pub const DISPLACEMENT_CYCLES_ESTIMATIONS: [i16; 8] = [11, 12, 12, 11, 9, 9, 9, 9];
 
 impl Rm {
-    pub fn new(file: &mut File, mod_value: u8, w: usize, rm: usize) -> Rm {
+    pub fn new(file: &mut impl Read, mod_value: u8, w: usize, rm: usize) -> Option<Rm> {
         if mod_value == 0b00 {
             // Memory mode no displacment
             if rm == 0b110 {
                 // Direct memory
                 let mut displacment = [0u8, 0u8];
-                file.read_exact(&mut displacment).unwrap();
-                return Rm::DirectMemory(((displacment[1] as u16) << 8) | displacment[0] as u16);
+                file.read_exact(&mut displacment).ok()?;
+                Some(Rm::DirectMemory(
+                    ((displacment[1] as u16) << 8) | displacment[0] as u16,
+                ))
             } else {
-                return Rm::MemoryNoDisplacment(rm);
+                Some(Rm::MemoryNoDisplacment(rm))
             }
         } else if mod_value == 0b01 {
-            // Memory mode, 8-bit displacment
+            // Memory mode, 8-bit displacment, sign extended to 16 bits
             let mut displacment = [0u8];
-            file.read_exact(&mut displacment).unwrap();
-            return Rm::MemoryWithDisplacment {
+            file.read_exact(&mut displacment).ok()?;
+            Some(Rm::MemoryWithDisplacment {
                 rm,
-                displacment: displacment[0] as u16,
-            };
+                displacment: displacment[0] as i8 as u16,
+            })
         } else if mod_value == 0b10 {
             // Memory mode, 16-bit displacment
             let mut displacment = [0u8, 0u8];
-            file.read_exact(&mut displacment).unwrap();
-            return Rm::MemoryWithDisplacment {
+            file.read_exact(&mut displacment).ok()?;
+            Some(Rm::MemoryWithDisplacment {
                 rm,
                 displacment: ((displacment[1] as u16) << 8) | displacment[0] as u16,
-            };
+            })
         } else {
-            return Rm::Reg { w, reg: rm };
+            Some(Rm::Reg { w, reg: rm })
         }
     }
 
-    pub fn calculate_memory_index(&self, simulation_registers: &[i16; 8]) -> i16 {
-        let Rm::MemoryNoDisplacment(index) = self else {
-            panic!("Function only works on memory mode no displacement");
+    pub fn calculate_memory_index(&self, simulation_registers: &[i16; 8]) -> u16 {
+        let (rm, displacment) = match self {
+            Rm::DirectMemory(displacment) => return *displacment,
+            Rm::MemoryNoDisplacment(rm) => (*rm, 0),
+            Rm::MemoryWithDisplacment { rm, displacment } => (*rm, *displacment),
+            Rm::Reg { .. } | Rm::SegmentReg(_) | Rm::FpuReg(_) => {
+                panic!("Function only works on memory modes")
+            }
         };
-        let mut answer = simulation_registers[MAPPTING_TO_EFFECTIVE_MEMORY_ADDRESS[*index].0];
+        let mut answer = simulation_registers[MAPPTING_TO_EFFECTIVE_MEMORY_ADDRESS[rm].0] as u16;
+
+        if let Some(val) = MAPPTING_TO_EFFECTIVE_MEMORY_ADDRESS[rm].1 {
+            answer = answer.wrapping_add(simulation_registers[val] as u16);
+        }
+
+        answer.wrapping_add(displacment)
+    }
 
-        if let Some(val) = MAPPTING_TO_EFFECTIVE_MEMORY_ADDRESS[*index].1 {
-            answer += simulation_registers[val];
+    // Addresses based on bp are relative to the stack segment, everything else to the data segment.
+    pub fn default_segment(&self) -> usize {
+        match self {
+            Rm::MemoryNoDisplacment(rm) | Rm::MemoryWithDisplacment { rm, .. }
+                if MAPPTING_TO_EFFECTIVE_MEMORY_ADDRESS[*rm].0 == 5 =>
+            {
+                SS
+            }
+            _ => DS,
         }
+    }
 
-        return answer;
+    pub fn is_memory(&self) -> bool {
+        !matches!(self, Rm::Reg { .. } | Rm::SegmentReg(_) | Rm::FpuReg(_))
     }
 
     pub fn estimate_cycles(&self) -> i16 {
         match self {
-            Rm::Reg { .. } => 6,
+            Rm::Reg { .. } | Rm::SegmentReg(_) | Rm::FpuReg(_) => 0,
             Rm::DirectMemory(_) => 6,
             Rm::MemoryNoDisplacment(i) => NO_DISPLACEMENT_CYCLES_ESTIMATIONS[*i],
+            // [bp] can only be encoded with a zero displacement, it is timed like [bx].
+            Rm::MemoryWithDisplacment {
+                rm: 6,
+                displacment: 0,
+            } => NO_DISPLACEMENT_CYCLES_ESTIMATIONS[6],
             Rm::MemoryWithDisplacment { rm, .. } => DISPLACEMENT_CYCLES_ESTIMATIONS[*rm],
         }
     }
 }
-
-impl Display for Rm {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let string = match self {
-            Rm::Reg { w, reg } => String::from(REGISTER_NAMES[*w][*reg]),
-            Rm::DirectMemory(displacment) => format!("[{}]", displacment),
-            Rm::MemoryWithDisplacment { rm, displacment } => {
-                format!("[{} + {}]", EFFECTIVE_MEMOERY_ADDRESS[*rm], displacment)
-            }
-            Rm::MemoryNoDisplacment(rm) => format!("[{}]", EFFECTIVE_MEMOERY_ADDRESS[*rm]),
-        };
-        write!(f, "{}", string)
-    }
-}
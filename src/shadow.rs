@@ -0,0 +1,553 @@
+use crate::call_stack::address_text;
+use crate::constants::{AH, AL, AX, BP, BX, CL, CX, DI, DS, DX, ES, REGISTER_NAMES, SI, SP};
+use crate::instruction::Instruction;
+use crate::machine::Machine;
+use crate::memory::{MEMORY_SIZE, physical_address};
+use crate::rm::{MAPPTING_TO_EFFECTIVE_MEMORY_ADDRESS, Rm};
+
+type Address = (u16, u16);
+
+// State an instruction reads or writes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Location {
+    // Indexed like REGISTER_NAMES.
+    Register {
+        w: usize,
+        reg: usize,
+    },
+    Memory {
+        segment: u16,
+        offset: u16,
+        length: u16,
+    },
+}
+
+// Which bytes of memory and of the general registers have been written, for --memcheck. Reads of
+// anything never written are reported as problems, much like valgrind's memcheck. Anything
+// reported counts as written from then on, so each missing initialization shows up once. The
+// segment registers and flags have known values from reset and aren't tracked.
+pub struct Shadow {
+    memory: Vec<bool>,
+    // Bit 0 for the low byte and bit 1 for the high byte of each word register.
+    registers: [u8; 8],
+    // The address and text of the instruction running, for the problems its reads find.
+    current: Option<(Address, String)>,
+    problems: Vec<String>,
+    pub problem_count: u64,
+}
+
+impl Shadow {
+    pub fn new() -> Shadow {
+        Shadow {
+            memory: vec![false; MEMORY_SIZE],
+            registers: [0; 8],
+            current: None,
+            problems: Vec::new(),
+            problem_count: 0,
+        }
+    }
+
+    pub fn define_all(&mut self) {
+        self.memory.fill(true);
+        self.registers = [0b11; 8];
+    }
+
+    pub fn define_memory(&mut self, address: usize, length: usize) {
+        for i in 0..length {
+            self.memory[(address + i) % MEMORY_SIZE] = true;
+        }
+    }
+
+    pub fn define(&mut self, location: &Location) {
+        match location {
+            Location::Register { w, reg } => {
+                let (reg, bytes) = register_bytes(*w, *reg);
+                self.registers[reg] |= bytes;
+            }
+            Location::Memory {
+                segment,
+                offset,
+                length,
+            } => {
+                for i in 0..*length {
+                    self.memory[physical_address(*segment, offset.wrapping_add(i))] = true;
+                }
+            }
+        }
+    }
+
+    fn is_defined(&self, location: &Location) -> bool {
+        match location {
+            Location::Register { w, reg } => {
+                let (reg, bytes) = register_bytes(*w, *reg);
+                self.registers[reg] & bytes == bytes
+            }
+            Location::Memory {
+                segment,
+                offset,
+                length,
+            } => (0..*length)
+                .all(|i| self.memory[physical_address(*segment, offset.wrapping_add(i))]),
+        }
+    }
+
+    // Called before each instruction runs, with the code it was decoded from.
+    pub fn begin(&mut self, at: Address, instruction: &Instruction) {
+        let code = Location::Memory {
+            segment: at.0,
+            offset: at.1,
+            length: instruction.length,
+        };
+        self.current = Some((at, instruction.to_string()));
+        if !self.is_defined(&code) {
+            self.define(&code);
+            let text = format!(
+                "{} at {} runs code that was never written",
+                instruction,
+                address_text(at)
+            );
+            self.problem(text);
+        }
+    }
+
+    pub fn read(&mut self, location: &Location) {
+        if self.is_defined(location) {
+            return;
+        }
+        self.define(location);
+        let what = match location {
+            Location::Register { w, reg } => String::from(REGISTER_NAMES[*w][*reg]),
+            Location::Memory {
+                segment,
+                offset,
+                length,
+            } => {
+                let size = match length {
+                    1 => String::from("byte"),
+                    2 => String::from("word"),
+                    length => format!("{} bytes", length),
+                };
+                format!("the {} at {}", size, address_text((*segment, *offset)))
+            }
+        };
+        let text = match &self.current {
+            Some((at, instruction)) => format!(
+                "{} at {} reads {}, which was never written",
+                instruction,
+                address_text(*at),
+                what
+            ),
+            None => format!("Read of {}, which was never written", what),
+        };
+        self.problem(text);
+    }
+
+    pub fn take_problems(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.problems)
+    }
+
+    fn problem(&mut self, text: String) {
+        self.problems.push(text);
+        self.problem_count += 1;
+    }
+}
+
+impl Default for Shadow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The word register holding a register of REGISTER_NAMES and which of its bytes it is.
+fn register_bytes(w: usize, reg: usize) -> (usize, u8) {
+    match (w, reg) {
+        (1, reg) => (reg, 0b11),
+        (_, reg) if reg < 4 => (reg, 0b01),
+        (_, reg) => (reg - 4, 0b10),
+    }
+}
+
+// Instructions that read sp to push or pop something.
+const STACK_INSTRUCTIONS: [&str; 13] = [
+    "push", "pop", "pushf", "popf", "call", "ret", "retf", "iret", "int", "int3", "pusha", "popa",
+    "enter",
+];
+
+// Stores of the 8087 write their memory operand, everything else reads it.
+const FPU_STORES: [&str; 9] = [
+    "fst", "fstp", "fist", "fistp", "fbstp", "fnstcw", "fnstsw", "fnstenv", "fnsave",
+];
+
+// The registers and memory an instruction reads and writes, found before it runs. Values on the
+// stack are left to push_word and pop_word.
+pub fn accesses(machine: &Machine, instruction: &Instruction) -> (Vec<Location>, Vec<Location>) {
+    let mut reads = Vec::new();
+    let mut writes = Vec::new();
+    let word = |reg| Location::Register { w: 1, reg };
+    let length = match instruction.memory_size {
+        Some(size) => size,
+        None if instruction.far || matches!(instruction.name, "bound" | "lds" | "les") => 4,
+        None => instruction.w as u16 + 1,
+    };
+    let mut locate =
+        |rm: &Option<Rm>| operand(machine, instruction, rm.as_ref()?, length, &mut reads);
+    let destination = locate(&instruction.destination);
+    let source = locate(&instruction.source);
+
+    let name = instruction.name;
+    if STACK_INSTRUCTIONS.contains(&name) {
+        reads.push(word(SP));
+    }
+    match name {
+        "jcxz" => reads.push(word(CX)),
+        "loop" | "loopz" | "loopnz" => {
+            reads.push(word(CX));
+            writes.push(word(CX));
+        }
+        // Without an 8087 nothing is transferred.
+        _ if instruction.is_fpu() => {
+            if machine.fpu.is_some() {
+                let operands = destination.into_iter().chain(source);
+                if FPU_STORES.contains(&name) {
+                    writes.extend(operands);
+                } else {
+                    reads.extend(operands);
+                }
+            }
+        }
+        "push" | "call" | "jmp" => reads.extend(destination),
+        "pop" => writes.extend(destination),
+        "pusha" => reads.extend((AX..=DI).map(word)),
+        "popa" => writes.extend((AX..=DI).filter(|reg| *reg != SP).map(word)),
+        "enter" => {
+            reads.push(word(BP));
+            writes.push(word(BP));
+        }
+        "leave" => {
+            reads.push(word(BP));
+            writes.extend([word(SP), word(BP)]);
+        }
+        "salc" => writes.push(Location::Register { w: 0, reg: AL }),
+        "movsb" | "movsw" | "cmpsb" | "cmpsw" | "scasb" | "scasw" | "lodsb" | "lodsw" | "stosb"
+        | "stosw" | "insb" | "insw" | "outsb" | "outsw" => {
+            string_accesses(machine, instruction, &mut reads, &mut writes);
+        }
+        "lea" => writes.extend(destination),
+        // Neither reads the register it sets.
+        "lds" | "les" | "in" => {
+            reads.extend(source);
+            writes.extend(destination);
+        }
+        "xchg" => {
+            let operands: Vec<Location> = destination.into_iter().chain(source).collect();
+            reads.extend(operands.iter().cloned());
+            writes.extend(operands);
+        }
+        "cbw" => {
+            reads.push(Location::Register { w: 0, reg: AL });
+            writes.push(word(AX));
+        }
+        "cwd" => {
+            reads.push(word(AX));
+            writes.push(word(DX));
+        }
+        "daa" | "das" => {
+            reads.push(Location::Register { w: 0, reg: AL });
+            writes.push(Location::Register { w: 0, reg: AL });
+        }
+        // aam only looks at al, the others at ah as well.
+        "aaa" | "aas" | "aam" | "aad" => {
+            reads.push(if name == "aam" {
+                Location::Register { w: 0, reg: AL }
+            } else {
+                word(AX)
+            });
+            writes.push(word(AX));
+        }
+        "lahf" => writes.push(Location::Register { w: 0, reg: AH }),
+        "sahf" => reads.push(Location::Register { w: 0, reg: AH }),
+        "xlatb" => {
+            let al = Location::Register { w: 0, reg: AL };
+            let offset = (machine.simulation_registers[BX] as u16)
+                .wrapping_add(machine.simulation_registers[AX] as u8 as u16);
+            reads.extend([word(BX), al.clone()]);
+            reads.push(Location::Memory {
+                segment: machine.segment_registers[instruction.segment_override.unwrap_or(DS)],
+                offset,
+                length: 1,
+            });
+            writes.push(al);
+        }
+        _ if clears_register(instruction) => writes.extend(destination),
+        "mul" | "imul" | "div" | "idiv" if instruction.immediate_value.is_none() => {
+            reads.extend(destination);
+            let divide = name.ends_with("div");
+            match (instruction.w, divide) {
+                (0, false) => reads.push(Location::Register { w: 0, reg: AL }),
+                (0, true) => reads.push(word(AX)),
+                (_, false) => reads.push(word(AX)),
+                (_, true) => reads.extend([word(AX), word(DX)]),
+            }
+            writes.push(word(AX));
+            if instruction.w == 1 {
+                writes.push(word(DX));
+            }
+        }
+        // imul reg, rm, immediate only reads its source.
+        "mov" | "imul" => {
+            reads.extend(source);
+            writes.extend(destination);
+        }
+        "cmp" | "test" | "bound" | "out" => reads.extend(destination.into_iter().chain(source)),
+        // The result doesn't depend on the operand.
+        "setmo" | "setmoc" => {
+            if name == "setmoc" {
+                reads.push(Location::Register { w: 0, reg: CL });
+            }
+            writes.extend(destination);
+        }
+        "rol" | "ror" | "rcl" | "rcr" | "shl" | "shr" | "sar" => {
+            if instruction.immediate_value.is_none() {
+                reads.push(Location::Register { w: 0, reg: CL });
+            }
+            if let Some(destination) = destination {
+                reads.push(destination.clone());
+                writes.push(destination);
+            }
+        }
+        _ => {
+            if let Some(destination) = destination {
+                reads.push(destination.clone());
+                reads.extend(source);
+                writes.push(destination);
+            }
+        }
+    }
+    (reads, writes)
+}
+
+// xor or sub of a register with itself, which sets it to 0 whatever it held.
+fn clears_register(instruction: &Instruction) -> bool {
+    let operands = (&instruction.destination, &instruction.source);
+    matches!(instruction.name, "xor" | "sub")
+        && match operands {
+            (Some(Rm::Reg { w, reg }), Some(Rm::Reg { w: w2, reg: reg2 })) => {
+                (w, reg) == (w2, reg2)
+            }
+            _ => false,
+        }
+}
+
+// The registers and memory of one element of a string instruction, none when a repeat prefix
+// finds cx already 0.
+fn string_accesses(
+    machine: &Machine,
+    instruction: &Instruction,
+    reads: &mut Vec<Location>,
+    writes: &mut Vec<Location>,
+) {
+    let word = |reg| Location::Register { w: 1, reg };
+    let accumulator = Location::Register {
+        w: instruction.w,
+        reg: AL,
+    };
+    if instruction.repeat.is_some() {
+        reads.push(word(CX));
+        if machine.simulation_registers[CX] == 0 {
+            return;
+        }
+        writes.push(word(CX));
+    }
+    let length = instruction.w as u16 + 1;
+    let source = Location::Memory {
+        segment: machine.segment_registers[instruction.segment_override.unwrap_or(DS)],
+        offset: machine.simulation_registers[SI] as u16,
+        length,
+    };
+    let destination = Location::Memory {
+        segment: machine.segment_registers[ES],
+        offset: machine.simulation_registers[DI] as u16,
+        length,
+    };
+    let name = instruction.name;
+    let (uses_si, uses_di) = match &name[..name.len() - 1] {
+        "movs" => {
+            reads.push(source);
+            writes.push(destination);
+            (true, true)
+        }
+        "cmps" => {
+            reads.extend([source, destination]);
+            (true, true)
+        }
+        "scas" => {
+            reads.extend([accumulator, destination]);
+            (false, true)
+        }
+        "lods" => {
+            reads.push(source);
+            writes.push(accumulator);
+            (true, false)
+        }
+        "stos" => {
+            reads.push(accumulator);
+            writes.push(destination);
+            (false, true)
+        }
+        "ins" => {
+            reads.push(word(DX));
+            writes.push(destination);
+            (false, true)
+        }
+        _ => {
+            reads.extend([word(DX), source]);
+            (true, false)
+        }
+    };
+    for (used, reg) in [(uses_si, SI), (uses_di, DI)] {
+        if used {
+            reads.push(word(reg));
+            writes.push(word(reg));
+        }
+    }
+}
+
+// A register or memory operand, reading the registers its address is made of. None for segment
+// registers and the 8087 stack.
+fn operand(
+    machine: &Machine,
+    instruction: &Instruction,
+    rm: &Rm,
+    length: u16,
+    reads: &mut Vec<Location>,
+) -> Option<Location> {
+    match rm {
+        Rm::Reg { w, reg } => Some(Location::Register { w: *w, reg: *reg }),
+        Rm::SegmentReg(_) | Rm::FpuReg(_) => None,
+        _ => {
+            if let Rm::MemoryNoDisplacment(index) | Rm::MemoryWithDisplacment { rm: index, .. } = rm
+            {
+                let (base, other) = MAPPTING_TO_EFFECTIVE_MEMORY_ADDRESS[*index];
+                reads.extend(
+                    [Some(base), other]
+                        .into_iter()
+                        .flatten()
+                        .map(|reg| Location::Register { w: 1, reg }),
+                );
+            }
+            Some(Location::Memory {
+                segment: machine.segment_registers[instruction.segment(rm)],
+                offset: rm.calculate_memory_index(&machine.simulation_registers),
+                length,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::decode;
+    use crate::machine::Cpu;
+
+    fn register(w: usize, reg: usize) -> Location {
+        Location::Register { w, reg }
+    }
+
+    fn memory(segment: u16, offset: u16, length: u16) -> Location {
+        Location::Memory {
+            segment,
+            offset,
+            length,
+        }
+    }
+
+    fn classify(machine: &Machine, bytes: &[u8]) -> (Vec<Location>, Vec<Location>) {
+        accesses(machine, &decode(bytes, Cpu::Intel8086).unwrap())
+    }
+
+    fn machine() -> Machine {
+        let mut machine = Machine::new();
+        machine.segment_registers = [0x2000, 0x1000, 0x3000, 0x4000];
+        machine.simulation_registers[BX] = 0x10;
+        machine.simulation_registers[SI] = 0x20;
+        machine.simulation_registers[DI] = 0x30;
+        machine
+    }
+
+    #[test]
+    fn a_load_reads_the_address_and_memory_and_writes_the_register() {
+        // mov ax, [bx+si]
+        let (reads, writes) = classify(&machine(), &[0x8b, 0x00]);
+        assert_eq!(
+            reads,
+            [register(1, BX), register(1, SI), memory(0x4000, 0x30, 2)]
+        );
+        assert_eq!(writes, [register(1, AX)]);
+    }
+
+    #[test]
+    fn a_segment_override_changes_the_memory_segment() {
+        // mov al, es:[bx]
+        let (reads, writes) = classify(&machine(), &[0x26, 0x8a, 0x07]);
+        assert_eq!(reads, [register(1, BX), memory(0x2000, 0x10, 1)]);
+        assert_eq!(writes, [register(0, AL)]);
+    }
+
+    #[test]
+    fn clearing_a_register_only_writes_it() {
+        // xor ax, ax
+        let (reads, writes) = classify(&machine(), &[0x31, 0xc0]);
+        assert!(reads.is_empty());
+        assert_eq!(writes, [register(1, AX)]);
+    }
+
+    #[test]
+    fn lea_doesnt_read_memory() {
+        // lea bx, [si+4]
+        let (reads, writes) = classify(&machine(), &[0x8d, 0x5c, 0x04]);
+        assert_eq!(reads, [register(1, SI)]);
+        assert_eq!(writes, [register(1, BX)]);
+    }
+
+    #[test]
+    fn xchg_reads_and_writes_both_operands() {
+        // xchg [bx], cx
+        let (reads, writes) = classify(&machine(), &[0x87, 0x0f]);
+        for location in [memory(0x4000, 0x10, 2), register(1, CX)] {
+            assert!(reads.contains(&location));
+            assert!(writes.contains(&location));
+        }
+    }
+
+    #[test]
+    fn repeated_strings_read_cx_and_an_element_unless_cx_is_0() {
+        // rep movsw
+        let bytes = [0xf3, 0xa5];
+        let mut machine = machine();
+        let (reads, writes) = classify(&machine, &bytes);
+        assert_eq!(reads, [register(1, CX)]);
+        assert!(writes.is_empty());
+
+        machine.simulation_registers[CX] = 2;
+        let (reads, writes) = classify(&machine, &bytes);
+        assert_eq!(
+            reads,
+            [
+                register(1, CX),
+                memory(0x4000, 0x20, 2),
+                register(1, SI),
+                register(1, DI)
+            ]
+        );
+        assert_eq!(
+            writes,
+            [
+                register(1, CX),
+                memory(0x2000, 0x30, 2),
+                register(1, SI),
+                register(1, DI)
+            ]
+        );
+    }
+}
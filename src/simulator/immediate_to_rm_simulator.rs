@@ -1,4 +1,4 @@
-use crate::rm::Rm;
+use crate::flag::Flags;
 
 use super::{SimulatorInput, SimulatorOutput};
 
@@ -9,74 +9,66 @@ pub trait ImmediateToRMSimulator {
 pub struct MovImmediateToRMSimulator;
 
 impl ImmediateToRMSimulator for MovImmediateToRMSimulator {
-    fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
-        let SimulatorInput {
-            destination,
-            simulation_registers,
-            memory,
-            flags,
-            immediate_value,
-            ..
-        } = input;
-        let mut output = SimulatorOutput::default();
-
-        match destination {
-            Rm::Reg {
-                reg: destination, ..
-            } => {
-                output.old_value = simulation_registers[*destination as usize];
-                simulation_registers[*destination as usize] += immediate_value.unwrap();
-            }
-            Rm::MemoryWithDisplacment {
-                rm: register_index,
-                displacment,
-            } => {
-                let memory_index =
-                    (simulation_registers[*register_index] + *displacment as i16) as usize;
-                output.old_value = memory[memory_index] as i16;
-                memory[memory_index] = (immediate_value.unwrap() & 0b11111111) as u8;
-            }
-            Rm::MemoryNoDisplacment(index) => {
-                output.old_value = memory[*index as usize] as i16;
-                memory[*index as usize] = (immediate_value.unwrap() & 0b11111111) as u8;
-            }
-            Rm::DirectMemory(index) => {
-                output.old_value = memory[*index as usize] as i16;
-                memory[*index as usize] = (immediate_value.unwrap() & 0b11111111) as u8;
-            }
-        }
-
-        flags.update_from_number(immediate_value.unwrap());
-        output.new_value = immediate_value.unwrap();
+    fn simulate(&self, mut input: SimulatorInput) -> SimulatorOutput {
+        let destination = input.destination;
+        let mut output = SimulatorOutput {
+            old_value: input.read(destination),
+            ..Default::default()
+        };
+
+        input.write(destination, input.immediate_value.unwrap());
+        output.new_value = input.read(destination);
+        output.number_of_cycles = if destination.is_memory() {
+            10 + destination.estimate_cycles()
+        } else {
+            4
+        };
         output
     }
 }
 
+// The arithmetic and logic operations that write their result, all timed like add.
+fn simulate_operation(
+    mut input: SimulatorInput,
+    operation: fn(&mut Flags, i16, i16, usize) -> i16,
+) -> SimulatorOutput {
+    let destination = input.destination;
+    let mut output = SimulatorOutput {
+        old_value: input.read(destination),
+        ..Default::default()
+    };
+
+    let result = operation(
+        input.flags,
+        output.old_value,
+        input.immediate_value.unwrap(),
+        input.w,
+    );
+    input.write(destination, result);
+    output.new_value = input.read(destination);
+    output.number_of_cycles = if destination.is_memory() {
+        17 + destination.estimate_cycles()
+    } else {
+        4
+    };
+    output
+}
+
 pub struct AddImmediateToRMSimulator;
 
 impl ImmediateToRMSimulator for AddImmediateToRMSimulator {
     fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
-        let SimulatorInput {
-            destination,
-            simulation_registers,
-            flags,
-            immediate_value,
-            ..
-        } = input;
-        let mut output = SimulatorOutput::default();
-
-        if let Rm::Reg {
-            reg: destination, ..
-        } = destination
-        {
-            output.old_value = simulation_registers[*destination as usize];
-            simulation_registers[*destination as usize] += immediate_value.unwrap();
-            output.new_value = simulation_registers[*destination as usize];
-            output.number_of_cycles = 4;
-        }
-
-        flags.update_from_number(immediate_value.unwrap());
-        output
+        simulate_operation(input, Flags::update_from_addition)
+    }
+}
+
+pub struct AdcImmediateToRMSimulator;
+
+impl ImmediateToRMSimulator for AdcImmediateToRMSimulator {
+    fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
+        simulate_operation(input, |flags, left, right, w| {
+            flags.update_from_addition_with_carry(left, right, flags.cf, w)
+        })
     }
 }
 
@@ -84,26 +76,47 @@ pub struct SubImmediateToRMSimulator;
 
 impl ImmediateToRMSimulator for SubImmediateToRMSimulator {
     fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
-        let SimulatorInput {
-            destination,
-            simulation_registers,
-            flags,
-            immediate_value,
-            ..
-        } = input;
-        let mut output = SimulatorOutput::default();
-
-        if let Rm::Reg {
-            reg: destination, ..
-        } = destination
-        {
-            output.old_value = simulation_registers[*destination as usize];
-            simulation_registers[*destination as usize] -= immediate_value.unwrap();
-            output.new_value = simulation_registers[*destination as usize];
-        }
-
-        flags.update_from_number(immediate_value.unwrap());
-        output
+        simulate_operation(input, Flags::update_from_subtraction)
+    }
+}
+
+pub struct SbbImmediateToRMSimulator;
+
+impl ImmediateToRMSimulator for SbbImmediateToRMSimulator {
+    fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
+        simulate_operation(input, |flags, left, right, w| {
+            flags.update_from_subtraction_with_borrow(left, right, flags.cf, w)
+        })
+    }
+}
+
+pub struct AndImmediateToRMSimulator;
+
+impl ImmediateToRMSimulator for AndImmediateToRMSimulator {
+    fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
+        simulate_operation(input, |flags, left, right, w| {
+            flags.update_from_logic(left & right, w)
+        })
+    }
+}
+
+pub struct OrImmediateToRMSimulator;
+
+impl ImmediateToRMSimulator for OrImmediateToRMSimulator {
+    fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
+        simulate_operation(input, |flags, left, right, w| {
+            flags.update_from_logic(left | right, w)
+        })
+    }
+}
+
+pub struct XorImmediateToRMSimulator;
+
+impl ImmediateToRMSimulator for XorImmediateToRMSimulator {
+    fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
+        simulate_operation(input, |flags, left, right, w| {
+            flags.update_from_logic(left ^ right, w)
+        })
     }
 }
 
@@ -111,27 +124,73 @@ pub struct CmpImmediateToRMSimulator;
 
 impl ImmediateToRMSimulator for CmpImmediateToRMSimulator {
     fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
-        let SimulatorInput {
-            destination,
-            simulation_registers,
-            flags,
-            immediate_value,
-            ..
-        } = input;
-        let mut output = SimulatorOutput::default();
-
-        if let Rm::Reg {
-            reg: destination, ..
-        } = destination
-        {
-            flags.update_from_number(
-                simulation_registers[*destination as usize] - immediate_value.unwrap(),
-            );
-        }
-
-        flags.update_from_number(immediate_value.unwrap());
-        output.old_value = immediate_value.unwrap();
-        output.new_value = immediate_value.unwrap();
+        let destination = input.destination;
+        let mut output = SimulatorOutput {
+            old_value: input.read(destination),
+            ..Default::default()
+        };
+
+        input.flags.update_from_subtraction(
+            output.old_value,
+            input.immediate_value.unwrap(),
+            input.w,
+        );
+        output.new_value = output.old_value;
+        output.number_of_cycles = if destination.is_memory() {
+            10 + destination.estimate_cycles()
+        } else {
+            4
+        };
+        output
+    }
+}
+
+pub struct TestImmediateToRMSimulator;
+
+impl ImmediateToRMSimulator for TestImmediateToRMSimulator {
+    fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
+        let destination = input.destination;
+        let mut output = SimulatorOutput {
+            old_value: input.read(destination),
+            ..Default::default()
+        };
+
+        let result = output.old_value & input.immediate_value.unwrap();
+        input.flags.update_from_value(result, input.w);
+        input.flags.cf = false;
+        input.flags.of = false;
+        output.new_value = output.old_value;
+        output.number_of_cycles = if destination.is_memory() {
+            11 + destination.estimate_cycles()
+        } else {
+            5
+        };
+        output
+    }
+}
+
+// imul reg, r/m, imm: the 80186 three operand form keeps only the low word of the product.
+pub struct ImulImmediateToRMSimulator;
+
+impl ImmediateToRMSimulator for ImulImmediateToRMSimulator {
+    fn simulate(&self, mut input: SimulatorInput) -> SimulatorOutput {
+        let destination = input.destination;
+        let source = input.source.unwrap();
+        let mut output = SimulatorOutput {
+            old_value: input.read(destination),
+            ..Default::default()
+        };
+
+        let product = input.read(source) as i32 * input.immediate_value.unwrap() as i32;
+        input.write(destination, product as i16);
+        input.flags.cf = product != product as i16 as i32;
+        input.flags.of = input.flags.cf;
+        output.new_value = input.read(destination);
+        output.number_of_cycles = if source.is_memory() {
+            25 + source.estimate_cycles()
+        } else {
+            22
+        };
         output
     }
 }
@@ -1,15 +1,24 @@
-use crate::{flag::Flags, rm::Rm};
+use crate::{
+    flag::Flags,
+    memory::{physical_address, read_word, write_word},
+    rm::Rm,
+};
 
 pub mod immediate_to_rm_simulator;
+pub mod rm_simulator;
 pub mod rm_to_rm_simulator;
+pub mod shift_simulator;
 
 pub struct SimulatorInput<'a> {
     pub simulation_registers: &'a mut [i16; 8],
-    pub memory: &'a mut [u8; 65536],
+    pub segment_registers: &'a mut [u16; 4],
+    pub memory: &'a mut [u8],
     pub flags: &'a mut Flags,
     pub source: Option<&'a Rm>,
     pub destination: &'a Rm,
     pub immediate_value: Option<i16>,
+    pub w: usize,
+    pub segment_override: Option<usize>,
 }
 
 #[derive(Default)]
@@ -17,4 +26,65 @@ pub struct SimulatorOutput {
     pub old_value: i16,
     pub new_value: i16,
     pub number_of_cycles: i16,
+    // Set when the instruction raises an exception, such as a divide error.
+    pub interrupt: Option<u8>,
+}
+
+impl SimulatorInput<'_> {
+    pub fn read(&self, rm: &Rm) -> i16 {
+        match rm {
+            Rm::Reg { w, reg } => read_register(self.simulation_registers, *w, *reg),
+            Rm::SegmentReg(reg) => self.segment_registers[*reg] as i16,
+            _ => {
+                let segment =
+                    self.segment_registers[self.segment_override.unwrap_or(rm.default_segment())];
+                let offset = rm.calculate_memory_index(self.simulation_registers);
+                if self.w == 1 {
+                    read_word(self.memory, segment, offset) as i16
+                } else {
+                    self.memory[physical_address(segment, offset)] as i16
+                }
+            }
+        }
+    }
+
+    pub fn write(&mut self, rm: &Rm, value: i16) {
+        match rm {
+            Rm::Reg { w, reg } => write_register(self.simulation_registers, *w, *reg, value),
+            Rm::SegmentReg(reg) => self.segment_registers[*reg] = value as u16,
+            _ => {
+                let segment =
+                    self.segment_registers[self.segment_override.unwrap_or(rm.default_segment())];
+                let offset = rm.calculate_memory_index(self.simulation_registers);
+                if self.w == 1 {
+                    write_word(self.memory, segment, offset, value as u16);
+                } else {
+                    self.memory[physical_address(segment, offset)] = value as u8;
+                }
+            }
+        }
+    }
+}
+
+// Byte registers al..bl are the low halves and ah..bh the high halves of ax..bx.
+pub fn read_register(simulation_registers: &[i16; 8], w: usize, reg: usize) -> i16 {
+    if w == 1 {
+        simulation_registers[reg]
+    } else if reg < 4 {
+        simulation_registers[reg] & 0xff
+    } else {
+        (simulation_registers[reg - 4] >> 8) & 0xff
+    }
+}
+
+pub fn write_register(simulation_registers: &mut [i16; 8], w: usize, reg: usize, value: i16) {
+    if w == 1 {
+        simulation_registers[reg] = value;
+    } else if reg < 4 {
+        let register = &mut simulation_registers[reg];
+        *register = (*register & !0xff) | (value & 0xff);
+    } else {
+        let register = &mut simulation_registers[reg - 4];
+        *register = (*register & 0xff) | ((value & 0xff) << 8);
+    }
 }
@@ -0,0 +1,243 @@
+use crate::constants::{AX, DX};
+use crate::flag::Flags;
+use crate::interrupts::DIVIDE_ERROR;
+use crate::rm::Rm;
+
+use super::{SimulatorInput, SimulatorOutput};
+
+pub trait RmSimulator {
+    fn simulate(&self, input: SimulatorInput) -> SimulatorOutput;
+}
+
+// inc and dec are add and sub of 1 that leave CF alone.
+pub struct IncRmSimulator;
+
+impl RmSimulator for IncRmSimulator {
+    fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
+        step_by_one(input, Flags::update_from_addition)
+    }
+}
+
+pub struct DecRmSimulator;
+
+impl RmSimulator for DecRmSimulator {
+    fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
+        step_by_one(input, Flags::update_from_subtraction)
+    }
+}
+
+fn step_by_one(
+    mut input: SimulatorInput,
+    operation: fn(&mut Flags, i16, i16, usize) -> i16,
+) -> SimulatorOutput {
+    let destination = input.destination;
+    let mut output = SimulatorOutput {
+        old_value: input.read(destination),
+        ..Default::default()
+    };
+
+    let cf = input.flags.cf;
+    let result = operation(input.flags, output.old_value, 1, input.w);
+    input.flags.cf = cf;
+    input.write(destination, result);
+    output.new_value = input.read(destination);
+    output.number_of_cycles = match destination {
+        _ if destination.is_memory() => 15 + destination.estimate_cycles(),
+        Rm::Reg { w: 1, .. } => 2,
+        _ => 3,
+    };
+    output
+}
+
+pub struct NotRmSimulator;
+
+impl RmSimulator for NotRmSimulator {
+    fn simulate(&self, mut input: SimulatorInput) -> SimulatorOutput {
+        let destination = input.destination;
+        let mut output = SimulatorOutput {
+            old_value: input.read(destination),
+            ..Default::default()
+        };
+
+        input.write(destination, !output.old_value);
+        output.new_value = input.read(destination);
+        output.number_of_cycles = if destination.is_memory() {
+            16 + destination.estimate_cycles()
+        } else {
+            3
+        };
+        output
+    }
+}
+
+pub struct NegRmSimulator;
+
+impl RmSimulator for NegRmSimulator {
+    fn simulate(&self, mut input: SimulatorInput) -> SimulatorOutput {
+        let destination = input.destination;
+        let mut output = SimulatorOutput {
+            old_value: input.read(destination),
+            ..Default::default()
+        };
+
+        let result = input
+            .flags
+            .update_from_subtraction(0, output.old_value, input.w);
+        input.write(destination, result);
+        output.new_value = input.read(destination);
+        output.number_of_cycles = if destination.is_memory() {
+            16 + destination.estimate_cycles()
+        } else {
+            3
+        };
+        output
+    }
+}
+
+pub struct MulRmSimulator;
+
+impl RmSimulator for MulRmSimulator {
+    fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
+        let source = input.read(input.destination) as u16 as u32;
+        let ax = input.simulation_registers[AX] as u16 as u32;
+        let mut output = SimulatorOutput {
+            old_value: ax as i16,
+            ..Default::default()
+        };
+
+        if input.w == 0 {
+            let result = (ax & 0xff) * source;
+            input.simulation_registers[AX] = result as i16;
+            input.flags.cf = result > 0xff;
+        } else {
+            let result = ax * source;
+            input.simulation_registers[AX] = result as i16;
+            input.simulation_registers[DX] = (result >> 16) as i16;
+            input.flags.cf = result > 0xffff;
+        }
+        input.flags.of = input.flags.cf;
+
+        output.new_value = input.simulation_registers[AX];
+        output.number_of_cycles = multiply_cycles(&input, 70, 118);
+        output
+    }
+}
+
+pub struct ImulRmSimulator;
+
+impl RmSimulator for ImulRmSimulator {
+    fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
+        let source = input.read(input.destination);
+        let ax = input.simulation_registers[AX];
+        let mut output = SimulatorOutput {
+            old_value: ax,
+            ..Default::default()
+        };
+
+        if input.w == 0 {
+            let result = (ax as i8 as i16) * (source as i8 as i16);
+            input.simulation_registers[AX] = result;
+            input.flags.cf = result != result as i8 as i16;
+        } else {
+            let result = (ax as i32) * (source as i32);
+            input.simulation_registers[AX] = result as i16;
+            input.simulation_registers[DX] = (result >> 16) as i16;
+            input.flags.cf = result != result as i16 as i32;
+        }
+        input.flags.of = input.flags.cf;
+
+        output.new_value = input.simulation_registers[AX];
+        output.number_of_cycles = multiply_cycles(&input, 80, 128);
+        output
+    }
+}
+
+pub struct DivRmSimulator;
+
+impl RmSimulator for DivRmSimulator {
+    fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
+        let divisor = input.read(input.destination) as u16 as u32;
+        let ax = input.simulation_registers[AX] as u16 as u32;
+        let mut output = SimulatorOutput {
+            old_value: ax as i16,
+            ..Default::default()
+        };
+        output.number_of_cycles = multiply_cycles(&input, 80, 144);
+
+        if input.w == 0 {
+            if divisor == 0 || ax / divisor > 0xff {
+                output.interrupt = Some(DIVIDE_ERROR);
+                return output;
+            }
+            input.simulation_registers[AX] = (((ax % divisor) << 8) | (ax / divisor)) as i16;
+        } else {
+            let dividend = ((input.simulation_registers[DX] as u16 as u32) << 16) | ax;
+            if divisor == 0 || dividend / divisor > 0xffff {
+                output.interrupt = Some(DIVIDE_ERROR);
+                return output;
+            }
+            input.simulation_registers[AX] = (dividend / divisor) as i16;
+            input.simulation_registers[DX] = (dividend % divisor) as i16;
+        }
+
+        output.new_value = input.simulation_registers[AX];
+        output
+    }
+}
+
+pub struct IdivRmSimulator;
+
+impl RmSimulator for IdivRmSimulator {
+    fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
+        let source = input.read(input.destination);
+        let ax = input.simulation_registers[AX];
+        let mut output = SimulatorOutput {
+            old_value: ax,
+            ..Default::default()
+        };
+        output.number_of_cycles = multiply_cycles(&input, 101, 165);
+
+        // The 8086 raises a divide error for the most negative quotient too.
+        if input.w == 0 {
+            let divisor = source as i8 as i32;
+            let quotient = (ax as i32)
+                .checked_div(divisor)
+                .filter(|quotient| (-127..=127).contains(quotient));
+            let Some(quotient) = quotient else {
+                output.interrupt = Some(DIVIDE_ERROR);
+                return output;
+            };
+            let remainder = ax as i32 % divisor;
+            input.simulation_registers[AX] = (((remainder & 0xff) << 8) | (quotient & 0xff)) as i16;
+        } else {
+            let divisor = source as i32;
+            let dividend = ((input.simulation_registers[DX] as i32) << 16) | (ax as u16 as i32);
+            let quotient = dividend
+                .checked_div(divisor)
+                .filter(|quotient| (-32767..=32767).contains(quotient));
+            let Some(quotient) = quotient else {
+                output.interrupt = Some(DIVIDE_ERROR);
+                return output;
+            };
+            input.simulation_registers[AX] = quotient as i16;
+            input.simulation_registers[DX] = (dividend % divisor) as i16;
+        }
+
+        output.new_value = input.simulation_registers[AX];
+        output
+    }
+}
+
+// Multiplications and divisions take 6 more clocks with a memory operand, plus the EA time.
+fn multiply_cycles(input: &SimulatorInput, byte_cycles: i16, word_cycles: i16) -> i16 {
+    let cycles = if input.w == 0 {
+        byte_cycles
+    } else {
+        word_cycles
+    };
+    if input.destination.is_memory() {
+        cycles + 6 + input.destination.estimate_cycles()
+    } else {
+        cycles
+    }
+}
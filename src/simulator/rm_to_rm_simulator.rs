@@ -1,3 +1,5 @@
+use crate::constants::AX;
+use crate::flag::Flags;
 use crate::rm::Rm;
 
 use super::{SimulatorInput, SimulatorOutput};
@@ -9,116 +11,68 @@ pub trait RMToRmSimulator {
 pub struct MovRmToRmSimulator;
 
 impl RMToRmSimulator for MovRmToRmSimulator {
-    fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
-        let SimulatorInput {
-            destination,
-            simulation_registers,
-            source,
-            memory,
-            flags,
-            ..
-        } = input;
-        let mut output = SimulatorOutput::default();
-        let source = source.unwrap();
-
-        if let Rm::Reg {
-            reg: destination, ..
-        } = destination
-        {
-            output.old_value = simulation_registers[*destination as usize];
-            if let Rm::Reg { reg: source, .. } = source {
-                simulation_registers[*destination as usize] =
-                    simulation_registers[*source as usize];
-                output.number_of_cycles = 2;
-            } else if let Rm::DirectMemory(index) = source {
-                simulation_registers[*destination as usize] = memory[*index as usize] as i16;
-                output.number_of_cycles = 8 + source.estimate_cycles();
-            } else if let Rm::MemoryNoDisplacment(_) = source {
-                let memory_index = source.calculate_memory_index(simulation_registers);
-                simulation_registers[*destination as usize] = memory[memory_index as usize] as i16;
-                output.number_of_cycles = 8 + source.estimate_cycles();
-            } else if let Rm::MemoryWithDisplacment { rm: register_index, displacment } = source {
-                let memory_index =
-                    (simulation_registers[*register_index] + *displacment as i16) as usize;
-                simulation_registers[*destination as usize] = memory[memory_index] as i16;
-                output.number_of_cycles = 8 + source.estimate_cycles(); 
-            }
-            output.new_value = simulation_registers[*destination as usize];
-            flags.update_from_number(simulation_registers[*destination as usize]);
-        } else if let Rm::MemoryNoDisplacment(_) = destination {
-            let memory_index = destination.calculate_memory_index(simulation_registers) as usize;
-            output.old_value = memory[memory_index] as i16;
-            if let Rm::Reg { reg: source, .. } = source {
-                memory[memory_index] = simulation_registers[*source as usize] as u8;
-            }
-            output.number_of_cycles = 9 + destination.estimate_cycles(); 
-            output.old_value = memory[memory_index] as i16;
-        } else if let Rm::MemoryWithDisplacment { rm: register_index, displacment } = destination {
-            let memory_index =
-                (simulation_registers[*register_index] + *displacment as i16) as usize;
-            output.old_value = memory[memory_index] as i16;
-
-            if let Rm::Reg { reg: source, .. } = source {
-                memory[memory_index] = simulation_registers[*source as usize] as u8;
-                output.new_value = simulation_registers[*source as usize];
-                output.number_of_cycles = 9 + destination.estimate_cycles();
-            }
-        }
-
+    fn simulate(&self, mut input: SimulatorInput) -> SimulatorOutput {
+        let destination = input.destination;
+        let source = input.source.unwrap();
+        let mut output = SimulatorOutput {
+            old_value: input.read(destination),
+            ..Default::default()
+        };
+
+        input.write(destination, input.read(source));
+        output.new_value = input.read(destination);
+        output.number_of_cycles = if destination.is_memory() {
+            9 + destination.estimate_cycles()
+        } else if source.is_memory() {
+            8 + source.estimate_cycles()
+        } else {
+            2
+        };
         output
     }
 }
 
+// The arithmetic and logic operations that write their result, all timed like add.
+fn simulate_operation(
+    mut input: SimulatorInput,
+    operation: fn(&mut Flags, i16, i16, usize) -> i16,
+) -> SimulatorOutput {
+    let destination = input.destination;
+    let source = input.source.unwrap();
+    let mut output = SimulatorOutput {
+        old_value: input.read(destination),
+        ..Default::default()
+    };
+
+    let source_value = input.read(source);
+    let result = operation(input.flags, output.old_value, source_value, input.w);
+    input.write(destination, result);
+    output.new_value = input.read(destination);
+    output.number_of_cycles = if destination.is_memory() {
+        16 + destination.estimate_cycles()
+    } else if source.is_memory() {
+        9 + source.estimate_cycles()
+    } else {
+        3
+    };
+    output
+}
+
 pub struct AddRmToRmSimulator;
 
 impl RMToRmSimulator for AddRmToRmSimulator {
     fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
-        let SimulatorInput {
-            destination,
-            simulation_registers,
-            source,
-            flags,
-            memory,
-            ..
-        } = input;
-        let mut output = SimulatorOutput::default();
-        let source = source.unwrap();
-
-        if let Rm::Reg {
-            reg: destination, ..
-        } = destination
-        {
-            output.old_value = simulation_registers[*destination as usize];
-            if let Rm::Reg { reg: source, .. } = source {
-                simulation_registers[*destination as usize] +=
-                    simulation_registers[*source as usize];
-                output.number_of_cycles = 3;
-            } else if let Rm::MemoryNoDisplacment(_) = source {
-                let memory_index = source.calculate_memory_index(simulation_registers);
-                simulation_registers[*destination as usize] += memory[memory_index as usize] as i16;
-            }
-            output.new_value = simulation_registers[*destination as usize];
-        } else if let Rm::MemoryNoDisplacment(_) = destination {
-            let memory_index = destination.calculate_memory_index(simulation_registers) as usize;
-            output.old_value = memory[memory_index] as i16;
-            if let Rm::Reg { reg: source, .. } = source {
-                memory[memory_index] += simulation_registers[*source as usize] as u8;
-            }
-            output.old_value = memory[memory_index] as i16;
-        } else if let Rm::MemoryWithDisplacment { rm: register_index, displacment } = destination {
-            let memory_index =
-                (simulation_registers[*register_index] + *displacment as i16) as usize;
-            output.old_value = memory[memory_index] as i16;
-
-            if let Rm::Reg { reg: source, .. } = source {
-                memory[memory_index] += simulation_registers[*source as usize] as u8;
-                output.new_value = simulation_registers[*source as usize];
-                output.number_of_cycles = 16 + destination.estimate_cycles();
-            }
-        }
-
-        flags.update_from_number(output.new_value);
-        output
+        simulate_operation(input, Flags::update_from_addition)
+    }
+}
+
+pub struct AdcRmToRmSimulator;
+
+impl RMToRmSimulator for AdcRmToRmSimulator {
+    fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
+        simulate_operation(input, |flags, left, right, w| {
+            flags.update_from_addition_with_carry(left, right, flags.cf, w)
+        })
     }
 }
 
@@ -126,29 +80,47 @@ pub struct SubRmToRmSimulator;
 
 impl RMToRmSimulator for SubRmToRmSimulator {
     fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
-        let SimulatorInput {
-            destination,
-            simulation_registers,
-            source,
-            flags,
-            ..
-        } = input;
-        let mut output = SimulatorOutput::default();
-
-        if let Rm::Reg {
-            reg: destination, ..
-        } = destination
-        {
-            output.old_value = simulation_registers[*destination as usize];
-            if let Rm::Reg { reg: source, .. } = source.unwrap() {
-                simulation_registers[*destination as usize] -=
-                    simulation_registers[*source as usize];
-            }
-            output.new_value = simulation_registers[*destination as usize];
-            flags.update_from_number(simulation_registers[*destination as usize]);
-        }
+        simulate_operation(input, Flags::update_from_subtraction)
+    }
+}
 
-        output
+pub struct SbbRmToRmSimulator;
+
+impl RMToRmSimulator for SbbRmToRmSimulator {
+    fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
+        simulate_operation(input, |flags, left, right, w| {
+            flags.update_from_subtraction_with_borrow(left, right, flags.cf, w)
+        })
+    }
+}
+
+pub struct AndRmToRmSimulator;
+
+impl RMToRmSimulator for AndRmToRmSimulator {
+    fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
+        simulate_operation(input, |flags, left, right, w| {
+            flags.update_from_logic(left & right, w)
+        })
+    }
+}
+
+pub struct OrRmToRmSimulator;
+
+impl RMToRmSimulator for OrRmToRmSimulator {
+    fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
+        simulate_operation(input, |flags, left, right, w| {
+            flags.update_from_logic(left | right, w)
+        })
+    }
+}
+
+pub struct XorRmToRmSimulator;
+
+impl RMToRmSimulator for XorRmToRmSimulator {
+    fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
+        simulate_operation(input, |flags, left, right, w| {
+            flags.update_from_logic(left ^ right, w)
+        })
     }
 }
 
@@ -156,29 +128,78 @@ pub struct CmpRmToRmSimulator;
 
 impl RMToRmSimulator for CmpRmToRmSimulator {
     fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
-        let SimulatorInput {
-            destination,
-            simulation_registers,
-            source,
-            flags,
-            ..
-        } = input;
-        let mut output = SimulatorOutput::default();
-
-        if let Rm::Reg {
-            reg: destination, ..
-        } = destination
-        {
-            output.old_value = simulation_registers[*destination as usize];
-            if let Rm::Reg { reg: source, .. } = source.unwrap() {
-                flags.update_from_number(
-                    simulation_registers[*destination as usize]
-                        - simulation_registers[*source as usize],
-                );
-            }
-            output.new_value = simulation_registers[*destination as usize];
-        }
+        let destination = input.destination;
+        let source = input.source.unwrap();
+        let mut output = SimulatorOutput {
+            old_value: input.read(destination),
+            ..Default::default()
+        };
+
+        let source_value = input.read(source);
+        input
+            .flags
+            .update_from_subtraction(output.old_value, source_value, input.w);
+        output.new_value = output.old_value;
+        output.number_of_cycles = if destination.is_memory() {
+            9 + destination.estimate_cycles()
+        } else if source.is_memory() {
+            9 + source.estimate_cycles()
+        } else {
+            3
+        };
+        output
+    }
+}
+
+pub struct TestRmToRmSimulator;
+
+impl RMToRmSimulator for TestRmToRmSimulator {
+    fn simulate(&self, input: SimulatorInput) -> SimulatorOutput {
+        let destination = input.destination;
+        let source = input.source.unwrap();
+        let mut output = SimulatorOutput {
+            old_value: input.read(destination),
+            ..Default::default()
+        };
+
+        let source_value = input.read(source);
+        input
+            .flags
+            .update_from_logic(output.old_value & source_value, input.w);
+        output.new_value = output.old_value;
+        output.number_of_cycles = if destination.is_memory() {
+            9 + destination.estimate_cycles()
+        } else {
+            3
+        };
+        output
+    }
+}
 
+pub struct XchgRmToRmSimulator;
+
+impl RMToRmSimulator for XchgRmToRmSimulator {
+    fn simulate(&self, mut input: SimulatorInput) -> SimulatorOutput {
+        let destination = input.destination;
+        let source = input.source.unwrap();
+        let mut output = SimulatorOutput {
+            old_value: input.read(destination),
+            ..Default::default()
+        };
+
+        let source_value = input.read(source);
+        input.write(source, output.old_value);
+        input.write(destination, source_value);
+        output.new_value = input.read(destination);
+        // The manual times exchanges with ax apart from other registers.
+        let accumulator = matches!(destination, Rm::Reg { w: 1, reg: AX });
+        output.number_of_cycles = if source.is_memory() {
+            17 + source.estimate_cycles()
+        } else if accumulator {
+            3
+        } else {
+            4
+        };
         output
     }
 }
@@ -0,0 +1,157 @@
+use crate::flag::{Flags, operand_masks};
+
+use super::{SimulatorInput, SimulatorOutput};
+
+pub trait ShiftSimulator {
+    // Moves the value, masked to the operand size, by one bit and sets CF and OF.
+    fn shift_once(&self, value: u32, w: usize, flags: &mut Flags) -> u32;
+
+    // Rotates leave SF, ZF and PF alone.
+    fn sets_result_flags(&self) -> bool {
+        true
+    }
+}
+
+pub struct RolShiftSimulator;
+
+impl ShiftSimulator for RolShiftSimulator {
+    fn shift_once(&self, value: u32, w: usize, flags: &mut Flags) -> u32 {
+        let (mask, sign) = operand_masks(w);
+        flags.cf = value & sign != 0;
+        let result = ((value << 1) | flags.cf as u32) & mask;
+        flags.of = (result & sign != 0) != flags.cf;
+        result
+    }
+
+    fn sets_result_flags(&self) -> bool {
+        false
+    }
+}
+
+pub struct RorShiftSimulator;
+
+impl ShiftSimulator for RorShiftSimulator {
+    fn shift_once(&self, value: u32, w: usize, flags: &mut Flags) -> u32 {
+        let (_, sign) = operand_masks(w);
+        flags.cf = value & 1 != 0;
+        let result = (value >> 1) | if flags.cf { sign } else { 0 };
+        flags.of = (result & sign != 0) != (result & (sign >> 1) != 0);
+        result
+    }
+
+    fn sets_result_flags(&self) -> bool {
+        false
+    }
+}
+
+pub struct RclShiftSimulator;
+
+impl ShiftSimulator for RclShiftSimulator {
+    fn shift_once(&self, value: u32, w: usize, flags: &mut Flags) -> u32 {
+        let (mask, sign) = operand_masks(w);
+        let result = ((value << 1) | flags.cf as u32) & mask;
+        flags.cf = value & sign != 0;
+        flags.of = (result & sign != 0) != flags.cf;
+        result
+    }
+
+    fn sets_result_flags(&self) -> bool {
+        false
+    }
+}
+
+pub struct RcrShiftSimulator;
+
+impl ShiftSimulator for RcrShiftSimulator {
+    fn shift_once(&self, value: u32, w: usize, flags: &mut Flags) -> u32 {
+        let (_, sign) = operand_masks(w);
+        let result = (value >> 1) | if flags.cf { sign } else { 0 };
+        flags.cf = value & 1 != 0;
+        flags.of = (result & sign != 0) != (result & (sign >> 1) != 0);
+        result
+    }
+
+    fn sets_result_flags(&self) -> bool {
+        false
+    }
+}
+
+pub struct ShlShiftSimulator;
+
+impl ShiftSimulator for ShlShiftSimulator {
+    fn shift_once(&self, value: u32, w: usize, flags: &mut Flags) -> u32 {
+        let (mask, sign) = operand_masks(w);
+        flags.cf = value & sign != 0;
+        let result = (value << 1) & mask;
+        flags.of = (result & sign != 0) != flags.cf;
+        result
+    }
+}
+
+pub struct ShrShiftSimulator;
+
+impl ShiftSimulator for ShrShiftSimulator {
+    fn shift_once(&self, value: u32, w: usize, flags: &mut Flags) -> u32 {
+        let (_, sign) = operand_masks(w);
+        flags.cf = value & 1 != 0;
+        flags.of = value & sign != 0;
+        value >> 1
+    }
+}
+
+pub struct SarShiftSimulator;
+
+impl ShiftSimulator for SarShiftSimulator {
+    fn shift_once(&self, value: u32, w: usize, flags: &mut Flags) -> u32 {
+        let (_, sign) = operand_masks(w);
+        flags.cf = value & 1 != 0;
+        flags.of = false;
+        (value >> 1) | (value & sign)
+    }
+}
+
+// setmo and setmoc, the undocumented operation between shr and sar. The operand becomes all
+// ones and the flags are set as if it had been ored with them.
+pub struct SetMinusOneShiftSimulator;
+
+impl ShiftSimulator for SetMinusOneShiftSimulator {
+    fn shift_once(&self, _value: u32, w: usize, flags: &mut Flags) -> u32 {
+        flags.cf = false;
+        flags.of = false;
+        flags.af = false;
+        operand_masks(w).0
+    }
+}
+
+// Shifts one bit at a time like the microcode does, so a count of 0 leaves the flags alone.
+// Shifts by 1 take the short path, everything else is timed like a shift by cl.
+pub fn simulate_shift(
+    simulator: &dyn ShiftSimulator,
+    mut input: SimulatorInput,
+    count: u8,
+    by_one: bool,
+) -> SimulatorOutput {
+    let destination = input.destination;
+    let mut output = SimulatorOutput {
+        old_value: input.read(destination),
+        ..Default::default()
+    };
+
+    let (mask, _) = operand_masks(input.w);
+    let mut value = output.old_value as u16 as u32 & mask;
+    for _ in 0..count {
+        value = simulator.shift_once(value, input.w, input.flags);
+    }
+    if count != 0 && simulator.sets_result_flags() {
+        input.flags.update_from_value(value as i16, input.w);
+    }
+    input.write(destination, value as i16);
+    output.new_value = input.read(destination);
+    output.number_of_cycles = match (destination.is_memory(), by_one) {
+        (true, true) => 15 + destination.estimate_cycles(),
+        (false, true) => 2,
+        (true, false) => 20 + destination.estimate_cycles() + 4 * count as i16,
+        (false, false) => 8 + 4 * count as i16,
+    };
+    output
+}
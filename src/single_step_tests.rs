@@ -0,0 +1,307 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::constants::{AX, BP, BX, CS, CX, DI, DS, DX, ES, SI, SP, SS};
+use crate::flag::Flags;
+use crate::json::Json;
+use crate::machine::{Cpu, Machine};
+
+// Registers as the test vectors name them.
+const REGISTERS: [&str; 13] = [
+    "ax", "bx", "cx", "dx", "sp", "bp", "si", "di", "cs", "ss", "ds", "es", "ip",
+];
+
+// CF, PF, AF, ZF, SF, TF, IF, DF and OF. The other bits read back as fixed values.
+const DEFINED_FLAGS: u16 = 0x0fd5;
+
+pub struct SingleStepOptions {
+    pub cpu: Cpu,
+    // Per-opcode masks of the flags the instruction leaves defined.
+    pub metadata: Option<PathBuf>,
+    // Only run these files, by name without the .json extension.
+    pub only: Vec<String>,
+    pub details: bool,
+}
+
+#[derive(Default)]
+struct Tally {
+    tests: usize,
+    passed: usize,
+    unsupported: usize,
+    registers: usize,
+    flags: usize,
+    memory: usize,
+    cycles: usize,
+    first_failure: Option<String>,
+}
+
+impl Tally {
+    fn add(&mut self, other: &Tally) {
+        self.tests += other.tests;
+        self.passed += other.passed;
+        self.unsupported += other.unsupported;
+        self.registers += other.registers;
+        self.flags += other.flags;
+        self.memory += other.memory;
+        self.cycles += other.cycles;
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "{:>6} tests {:>6} passed | registers {} flags {} memory {} cycles {} unsupported {}",
+            self.tests,
+            self.passed,
+            self.registers,
+            self.flags,
+            self.memory,
+            self.cycles,
+            self.unsupported
+        )
+    }
+}
+
+// Runs every <opcode>.json file of a SingleStepTests directory, one instruction per test, and
+// prints the mismatches per opcode. Cycle counts are reported but do not fail a test, the bus
+// cycles of the vectors include prefetching that the clock model leaves out.
+pub fn run_single_step_tests(
+    directory: &Path,
+    options: &SingleStepOptions,
+) -> Result<bool, String> {
+    let metadata_path = options
+        .metadata
+        .clone()
+        .unwrap_or_else(|| directory.join("metadata.json"));
+    let metadata = match fs::read_to_string(&metadata_path) {
+        Ok(text) => Some(
+            Json::parse(&text)
+                .map_err(|error| format!("{}: {}", metadata_path.display(), error))?,
+        ),
+        Err(_) if options.metadata.is_none() => None,
+        Err(error) => return Err(format!("{}: {}", metadata_path.display(), error)),
+    };
+
+    let mut files: Vec<(String, PathBuf)> = fs::read_dir(directory)
+        .map_err(|error| format!("{}: {}", directory.display(), error))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .filter_map(|path| Some((path.file_stem()?.to_str()?.to_string(), path)))
+        .filter(|(name, _)| name != "metadata")
+        .filter(|(name, _)| options.only.is_empty() || options.only.contains(name))
+        .collect();
+    files.sort();
+    if files.is_empty() {
+        return Err(format!(
+            "No test files in {}, the .json.gz files have to be decompressed first",
+            directory.display()
+        ));
+    }
+
+    let mut total = Tally::default();
+    for (name, path) in &files {
+        let text =
+            fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        let tests = Json::parse(&text).map_err(|error| format!("{}: {}", path.display(), error))?;
+        let tests = tests
+            .as_array()
+            .ok_or_else(|| format!("{}: expected an array of tests", path.display()))?;
+        let flags_mask = metadata
+            .as_ref()
+            .and_then(|metadata| flags_mask(metadata, name))
+            .unwrap_or(0xffff);
+
+        let mut tally = Tally::default();
+        for test in tests {
+            run_test(test, options.cpu, flags_mask, &mut tally)
+                .map_err(|error| format!("{}: {}", path.display(), error))?;
+        }
+        println!("{:<8}{}", name, tally.summary());
+        if options.details
+            && let Some(failure) = &tally.first_failure
+        {
+            println!("        {}", failure);
+        }
+        total.add(&tally);
+    }
+
+    println!("{:<8}{}", "total", total.summary());
+    Ok(total.passed == total.tests)
+}
+
+// Group opcodes are split by the reg field into files like F6.4.json.
+fn flags_mask(metadata: &Json, name: &str) -> Option<u16> {
+    let (opcode, reg) = match name.split_once('.') {
+        Some((opcode, reg)) => (opcode, Some(reg)),
+        None => (name, None),
+    };
+    let mut entry = metadata.get("opcodes")?.get(opcode)?;
+    if let Some(reg) = reg {
+        entry = entry.get("reg")?.get(reg)?;
+    }
+    Some(entry.get("flags-mask")?.as_u32()? as u16)
+}
+
+fn run_test(test: &Json, cpu: Cpu, flags_mask: u16, tally: &mut Tally) -> Result<(), String> {
+    let name = test.get("name").and_then(Json::as_str).unwrap_or("?");
+    let initial = test.get("initial").ok_or("Missing initial state")?;
+    let expected = test.get("final").ok_or("Missing final state")?;
+
+    let mut machine = Machine::new();
+    machine.cpu = cpu;
+    // The vectors bring their own interrupt table, nothing is serviced by the host.
+    machine.host_interrupts = [false; 256];
+    for register in REGISTERS {
+        let value = register_value(initial, register)?.ok_or("Missing initial register")?;
+        set_register(&mut machine, register, value);
+    }
+    let initial_flags = register_value(initial, "flags")?.ok_or("Missing initial flags")?;
+    machine.flags = Flags::from_word(initial_flags);
+    for (address, value) in ram(initial)? {
+        machine.memory[address] = value;
+    }
+
+    tally.tests += 1;
+    let executed = match machine.step() {
+        Ok(executed) => executed,
+        Err(error) => {
+            tally.unsupported += 1;
+            tally
+                .first_failure
+                .get_or_insert_with(|| format!("{}: {}", name, error));
+            return Ok(());
+        }
+    };
+
+    let mut mismatches = Vec::new();
+    for register in REGISTERS {
+        let expected_value = match register_value(expected, register)? {
+            Some(value) => value,
+            None => register_value(initial, register)?.unwrap(),
+        };
+        let actual = get_register(&machine, register);
+        if actual != expected_value {
+            mismatches.push(format!(
+                "{} {:#06x} expected {:#06x}",
+                register, actual, expected_value
+            ));
+        }
+    }
+    if !mismatches.is_empty() {
+        tally.registers += 1;
+    }
+
+    let expected_flags = register_value(expected, "flags")?.unwrap_or(initial_flags);
+    let actual_flags = machine.flags.to_word();
+    if (actual_flags ^ expected_flags) & DEFINED_FLAGS & flags_mask != 0 {
+        tally.flags += 1;
+        mismatches.push(format!(
+            "flags {} expected {}",
+            machine.flags,
+            Flags::from_word(expected_flags)
+        ));
+    }
+
+    let memory_mismatches = mismatches.len();
+    for (address, value) in ram(expected)? {
+        if machine.memory[address] != value {
+            mismatches.push(format!(
+                "[{:#07x}] {:#04x} expected {:#04x}",
+                address, machine.memory[address], value
+            ));
+        }
+    }
+    if mismatches.len() != memory_mismatches {
+        tally.memory += 1;
+    }
+
+    if let Some(cycles) = test.get("cycles").and_then(Json::as_array)
+        && cycles.len() != executed.output.number_of_cycles as usize
+    {
+        tally.cycles += 1;
+    }
+
+    if mismatches.is_empty() {
+        tally.passed += 1;
+    } else {
+        tally
+            .first_failure
+            .get_or_insert_with(|| format!("{}: {}", name, mismatches.join(", ")));
+    }
+    Ok(())
+}
+
+fn register_value(state: &Json, register: &str) -> Result<Option<u16>, String> {
+    let registers = state.get("regs").ok_or("Missing registers")?;
+    match registers.get(register) {
+        Some(value) => {
+            let value = value
+                .as_u32()
+                .filter(|value| *value <= 0xffff)
+                .ok_or_else(|| format!("Invalid value for {}", register))?;
+            Ok(Some(value as u16))
+        }
+        None => Ok(None),
+    }
+}
+
+// [[address, value], ...]
+fn ram(state: &Json) -> Result<Vec<(usize, u8)>, String> {
+    let Some(entries) = state.get("ram") else {
+        return Ok(Vec::new());
+    };
+    let invalid = || String::from("Invalid ram entry");
+    let mut ram = Vec::new();
+    for entry in entries.as_array().ok_or_else(invalid)? {
+        let [address, value] = entry.as_array().ok_or_else(invalid)? else {
+            return Err(invalid());
+        };
+        let address = address.as_u32().filter(|address| *address < 0x100000);
+        let value = value.as_u32().filter(|value| *value <= 0xff);
+        let (Some(address), Some(value)) = (address, value) else {
+            return Err(invalid());
+        };
+        ram.push((address as usize, value as u8));
+    }
+    Ok(ram)
+}
+
+fn register_slot(register: &str) -> (bool, usize) {
+    match register {
+        "ax" => (false, AX),
+        "bx" => (false, BX),
+        "cx" => (false, CX),
+        "dx" => (false, DX),
+        "sp" => (false, SP),
+        "bp" => (false, BP),
+        "si" => (false, SI),
+        "di" => (false, DI),
+        "cs" => (true, CS),
+        "ss" => (true, SS),
+        "ds" => (true, DS),
+        "es" => (true, ES),
+        _ => unreachable!(),
+    }
+}
+
+fn get_register(machine: &Machine, register: &str) -> u16 {
+    if register == "ip" {
+        return machine.ip;
+    }
+    match register_slot(register) {
+        (true, index) => machine.segment_registers[index],
+        (false, index) => machine.simulation_registers[index] as u16,
+    }
+}
+
+fn set_register(machine: &mut Machine, register: &str, value: u16) {
+    if register == "ip" {
+        machine.ip = value;
+        return;
+    }
+    match register_slot(register) {
+        (true, index) => machine.segment_registers[index] = value,
+        (false, index) => machine.simulation_registers[index] = value as i16,
+    }
+}
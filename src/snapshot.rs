@@ -0,0 +1,359 @@
+use std::collections::VecDeque;
+
+use crate::call_stack::{Frame, FrameKind};
+use crate::flag::Flags;
+use crate::fpu::{self, Fpu};
+use crate::machine::{Cpu, Machine};
+use crate::memory::MEMORY_SIZE;
+
+const MAGIC: &[u8; 8] = b"PERFSTAT";
+// Bumped whenever the layout below changes, older files are refused rather than misread.
+const VERSION: u16 = 4;
+
+// The whole state of a machine as a versioned binary file: registers, flags, memory, the clock,
+// pending interrupts, the call stack and the BIOS, DOS memory and 8087 state, all little-endian.
+// Files opened by the guest are host handles and can't be saved.
+pub fn save_state(machine: &Machine) -> Result<Vec<u8>, String> {
+    if machine.dos.files.iter().any(Option::is_some) {
+        return Err(String::from(
+            "Can't save the state while the program has files open",
+        ));
+    }
+
+    let mut writer = Writer(MAGIC.to_vec());
+    writer.u16(VERSION);
+    writer.bytes(machine.cpu.name().as_bytes());
+    for register in machine.simulation_registers {
+        writer.u16(register as u16);
+    }
+    for register in machine.segment_registers {
+        writer.u16(register);
+    }
+    writer.u16(machine.ip);
+    writer.u16(machine.flags.to_word());
+    writer.u64(machine.current_clock);
+    writer.option(machine.exit_code, |writer, code| writer.u8(code));
+    writer.bytes(
+        &machine
+            .pending_interrupts
+            .iter()
+            .copied()
+            .collect::<Vec<_>>(),
+    );
+    writer.bool(machine.pending_nmi);
+    writer.bool(machine.halted);
+    writer.bool(machine.interrupt_inhibit);
+    for vectors in machine.host_interrupts.chunks(8) {
+        let bits = vectors
+            .iter()
+            .rev()
+            .fold(0, |bits, host| (bits << 1) | *host as u8);
+        writer.u8(bits);
+    }
+    writer.u64(machine.call_stack.frames.len() as u64);
+    for frame in &machine.call_stack.frames {
+        match frame.kind {
+            FrameKind::Near => writer.u8(0),
+            FrameKind::Far => writer.u8(1),
+            FrameKind::Interrupt(vector) => {
+                writer.u8(2);
+                writer.u8(vector);
+            }
+        }
+        for (segment, offset) in [frame.target, frame.return_address, frame.slot] {
+            writer.u16(segment);
+            writer.u16(offset);
+        }
+    }
+
+    let bios = &machine.bios;
+    writer.u8(bios.video_mode);
+    writer.u16(bios.cursor_row);
+    writer.u16(bios.cursor_column);
+    writer.option(bios.keys.as_ref(), |writer, keys| {
+        writer.bytes(&keys.iter().copied().collect::<Vec<_>>())
+    });
+    writer.option(bios.pending_key, |writer, key| writer.u16(key));
+    writer.u64(bios.tick_offset as u64);
+
+    let dos = &machine.dos;
+    writer.u16(dos.psp_segment);
+    writer.u16(dos.memory_end);
+    writer.u64(dos.blocks.len() as u64);
+    for segment in &dos.blocks {
+        writer.u16(*segment);
+    }
+
+    writer.option(machine.fpu.as_ref(), |writer, fpu| {
+        writer.0.extend(fpu.save())
+    });
+    writer.0.extend(&machine.memory);
+    Ok(writer.0)
+}
+
+// Restores a state written by save_state. Host settings such as the DOS root directory and the
+// way guest output is written stay as they are.
+pub fn load_state(machine: &mut Machine, bytes: &[u8]) -> Result<(), String> {
+    let mut reader = Reader { bytes, position: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(String::from("Not a saved machine state"));
+    }
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(format!(
+            "Saved state has version {}, expected {}",
+            version, VERSION
+        ));
+    }
+    let cpu = String::from_utf8_lossy(reader.bytes()?).into_owned();
+    machine.cpu = Cpu::parse(&cpu).ok_or_else(|| format!("Unknown cpu {} in saved state", cpu))?;
+    for register in &mut machine.simulation_registers {
+        *register = reader.u16()? as i16;
+    }
+    for register in &mut machine.segment_registers {
+        *register = reader.u16()?;
+    }
+    machine.ip = reader.u16()?;
+    machine.flags = Flags::from_word(reader.u16()?);
+    machine.current_clock = reader.u64()?;
+    machine.exit_code = reader.option(Reader::u8)?;
+    machine.pending_interrupts = reader.bytes()?.iter().copied().collect();
+    machine.pending_nmi = reader.bool()?;
+    machine.halted = reader.bool()?;
+    machine.interrupt_inhibit = reader.bool()?;
+    for vectors in machine.host_interrupts.chunks_mut(8) {
+        let bits = reader.u8()?;
+        for (bit, host) in vectors.iter_mut().enumerate() {
+            *host = bits & (1 << bit) != 0;
+        }
+    }
+    let frames = reader.u64()?;
+    machine.call_stack.frames.clear();
+    for _ in 0..frames {
+        let kind = match reader.u8()? {
+            0 => FrameKind::Near,
+            1 => FrameKind::Far,
+            2 => FrameKind::Interrupt(reader.u8()?),
+            kind => return Err(format!("Unknown call kind {} in saved state", kind)),
+        };
+        let mut address = || Ok::<_, String>((reader.u16()?, reader.u16()?));
+        machine.call_stack.frames.push(Frame {
+            kind,
+            target: address()?,
+            return_address: address()?,
+            slot: address()?,
+        });
+    }
+
+    let bios = &mut machine.bios;
+    bios.video_mode = reader.u8()?;
+    bios.cursor_row = reader.u16()?;
+    bios.cursor_column = reader.u16()?;
+    bios.keys = reader.option(|reader| Ok(VecDeque::from(reader.bytes()?.to_vec())))?;
+    bios.pending_key = reader.option(Reader::u16)?;
+    bios.tick_offset = reader.u64()? as i64;
+
+    let dos = &mut machine.dos;
+    dos.psp_segment = reader.u16()?;
+    dos.memory_end = reader.u16()?;
+    let blocks = reader.u64()?;
+    dos.blocks = (0..blocks)
+        .map(|_| reader.u16())
+        .collect::<Result<_, _>>()?;
+
+    machine.fpu = reader.option(|reader| {
+        let mut fpu = Fpu::new();
+        fpu.restore(reader.take(fpu::SAVE_SIZE)?);
+        Ok(fpu)
+    })?;
+    machine.memory = reader.take(MEMORY_SIZE)?.to_vec();
+    if reader.position != bytes.len() {
+        return Err(String::from("Unexpected data after the saved state"));
+    }
+    machine.dos.files.clear();
+    // The file doesn't say what was written, --memcheck counts all of it.
+    if let Some(shadow) = &mut machine.shadow {
+        shadow.define_all();
+    }
+    Ok(())
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    // Length first.
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend((bytes.len() as u32).to_le_bytes());
+        self.0.extend(bytes);
+    }
+
+    fn option<T>(&mut self, value: Option<T>, write: impl FnOnce(&mut Writer, T)) {
+        self.bool(value.is_some());
+        if let Some(value) = value {
+            write(self, value);
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + length)
+            .ok_or("Saved state is truncated")?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], String> {
+        let length = u32::from_le_bytes(self.take(4)?.try_into().unwrap());
+        self.take(length as usize)
+    }
+
+    fn option<T>(
+        &mut self,
+        read: impl FnOnce(&mut Reader<'a>) -> Result<T, String>,
+    ) -> Result<Option<T>, String> {
+        match self.bool()? {
+            true => Ok(Some(read(self)?)),
+            false => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::SP;
+
+    // Sums a countdown into ax, storing each sum and calling a subroutine on the way.
+    const PROGRAM: [u8; 28] = [
+        0xb9, 0x0a, 0x00, // mov cx, 10
+        0xbb, 0x00, 0x01, // mov bx, 0x100
+        0xb8, 0x01, 0x00, // mov ax, 1
+        0x01, 0xc8, // add ax, cx
+        0x89, 0x07, // mov [bx], ax
+        0x83, 0xc3, 0x02, // add bx, 2
+        0x50, // push ax
+        0xe8, 0x04, 0x00, // call 0x18
+        0x5a, // pop dx
+        0xe2, 0xf2, // loop 0x9
+        0xf4, // hlt
+        0x2d, 0x03, 0x00, // sub ax, 3
+        0xc3, // ret
+    ];
+
+    fn start() -> Machine {
+        let mut machine = Machine::new();
+        machine.load(0, 0, &PROGRAM);
+        machine.simulation_registers[SP] = 0xfffe_u16 as i16;
+        machine
+    }
+
+    fn finish(machine: &mut Machine) {
+        while !machine.halted {
+            machine.step().unwrap();
+        }
+    }
+
+    #[test]
+    fn resumes_where_it_was_saved() {
+        let mut uninterrupted = start();
+        finish(&mut uninterrupted);
+
+        let mut saved = start();
+        for _ in 0..20 {
+            saved.step().unwrap();
+        }
+        assert!(!saved.halted);
+        let state = save_state(&saved).unwrap();
+        finish(&mut saved);
+        let mut resumed = Machine::new();
+        load_state(&mut resumed, &state).unwrap();
+        finish(&mut resumed);
+
+        for machine in [&saved, &resumed] {
+            assert_eq!(
+                machine.simulation_registers,
+                uninterrupted.simulation_registers
+            );
+            assert_eq!(machine.segment_registers, uninterrupted.segment_registers);
+            assert_eq!(machine.ip, uninterrupted.ip);
+            assert_eq!(machine.flags.to_word(), uninterrupted.flags.to_word());
+            assert_eq!(machine.current_clock, uninterrupted.current_clock);
+            assert!(machine.memory == uninterrupted.memory);
+        }
+    }
+
+    #[test]
+    fn keeps_the_dos_memory_blocks() {
+        let mut machine = start();
+        machine.dos.psp_segment = 0x1000;
+        machine.dos.memory_end = 0x9000;
+        machine.dos.blocks = vec![0x1000, 0x3000];
+        let state = save_state(&machine).unwrap();
+
+        let mut loaded = Machine::new();
+        load_state(&mut loaded, &state).unwrap();
+        assert_eq!(loaded.dos.psp_segment, 0x1000);
+        assert_eq!(loaded.dos.memory_end, 0x9000);
+        assert_eq!(loaded.dos.blocks, [0x1000, 0x3000]);
+        assert_eq!(save_state(&loaded).unwrap(), state);
+    }
+
+    #[test]
+    fn refuses_other_files_and_versions() {
+        let state = save_state(&start()).unwrap();
+        let mut machine = Machine::new();
+
+        let mut other = state.clone();
+        other[0] = b'X';
+        assert!(load_state(&mut machine, &other).is_err());
+
+        let mut older = state.clone();
+        older[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(VERSION - 1).to_le_bytes());
+        assert!(load_state(&mut machine, &older).is_err());
+
+        assert!(load_state(&mut machine, &state[..state.len() - 1]).is_err());
+        let mut longer = state.clone();
+        longer.push(0);
+        assert!(load_state(&mut machine, &longer).is_err());
+    }
+}
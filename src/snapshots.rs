@@ -0,0 +1,139 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::diff::unified_diff;
+use crate::limits::is_stop_status;
+
+pub struct SnapshotOptions {
+    // Write the new traces instead of comparing against the old ones.
+    pub bless: bool,
+    // Where the traces are kept, a snapshots directory next to each program by default.
+    pub directory: Option<PathBuf>,
+    // Passed on to --exec, like --show-clocks or --cpu 8088.
+    pub trace_options: Vec<String>,
+}
+
+enum Outcome {
+    Same,
+    Changed(String),
+    New,
+}
+
+// Golden trace testing: runs each flat program with --exec and compares the trace with the one
+// saved for it, printing a diff when they differ. With bless the new traces are saved instead.
+// Directories stand for the programs in them, files without an extension or with .bin.
+// Returns whether every trace matched.
+pub fn run_snapshots(paths: &[String], options: &SnapshotOptions) -> Result<bool, String> {
+    let mut programs = Vec::new();
+    for path in paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            let mut files: Vec<PathBuf> = fs::read_dir(path)
+                .map_err(|error| format!("{}: {}", path.display(), error))?
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    path.is_file() && path.extension().is_none_or(|extension| extension == "bin")
+                })
+                .collect();
+            files.sort();
+            programs.extend(files);
+        } else {
+            programs.push(path.to_path_buf());
+        }
+    }
+
+    let (mut same, mut failed) = (0, 0);
+    for program in programs {
+        let name = program.file_name().unwrap_or_default().to_string_lossy();
+        let snapshot = snapshot_path(&program, options);
+        let trace = trace(&program, &options.trace_options)?;
+        let outcome = match fs::read_to_string(&snapshot) {
+            Ok(old) => match unified_diff(&old, &trace, "saved", "new") {
+                diff if diff.is_empty() => Outcome::Same,
+                diff => Outcome::Changed(diff),
+            },
+            Err(_) => Outcome::New,
+        };
+
+        match (outcome, options.bless) {
+            (Outcome::Same, _) => {
+                same += 1;
+                println!("ok       {}", name);
+            }
+            (outcome, true) => {
+                if let Some(directory) = snapshot.parent() {
+                    fs::create_dir_all(directory)
+                        .map_err(|error| format!("{}: {}", directory.display(), error))?;
+                }
+                fs::write(&snapshot, &trace)
+                    .map_err(|error| format!("{}: {}", snapshot.display(), error))?;
+                let change = if let Outcome::New = outcome {
+                    "new"
+                } else {
+                    "changed"
+                };
+                println!("blessed  {} ({})", name, change);
+            }
+            (Outcome::Changed(diff), false) => {
+                failed += 1;
+                println!("CHANGED  {}", name);
+                print!("{}", diff);
+            }
+            (Outcome::New, false) => {
+                failed += 1;
+                println!(
+                    "NEW      {}, no saved trace at {}",
+                    name,
+                    snapshot.display()
+                );
+            }
+        }
+    }
+    println!("{} unchanged, {} failed", same, failed);
+    if failed > 0 {
+        println!("Run again with --bless to accept the new traces");
+    }
+    Ok(failed == 0)
+}
+
+fn snapshot_path(program: &Path, options: &SnapshotOptions) -> PathBuf {
+    let directory = match &options.directory {
+        Some(directory) => directory.clone(),
+        None => program.parent().unwrap_or(Path::new("")).join("snapshots"),
+    };
+    let mut name = program.file_name().unwrap_or_default().to_os_string();
+    name.push(".trace");
+    directory.join(name)
+}
+
+// Runs from the program's directory so the trace names it the same wherever it's run from.
+fn trace(program: &Path, trace_options: &[String]) -> Result<String, String> {
+    let executable = env::current_exe().map_err(|error| error.to_string())?;
+    let directory = program
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty());
+    let mut command = Command::new(executable);
+    command
+        .arg("--exec")
+        .args(trace_options)
+        .arg(program.file_name().unwrap_or_default());
+    if let Some(directory) = directory {
+        command.current_dir(directory);
+    }
+    let output = command
+        .output()
+        .map_err(|error| format!("{}: {}", program.display(), error))?;
+    // Stops by hlt or a limit still print the whole trace.
+    let stopped = output.status.code().is_some_and(is_stop_status);
+    if !output.status.success() && !stopped {
+        return Err(format!(
+            "{}: {}",
+            program.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+
+use crate::instruction::Instruction;
+use crate::memory::MEMORY_SIZE;
+use crate::nasm_listing::{parse_nasm_listing, split_label};
+
+// Labels and source lines of the running program, from a nasm listing or a map file of
+// `label = address` lines. Addresses in either are offsets from the physical address base the
+// program was loaded at.
+pub struct Symbols {
+    base: usize,
+    // Physical address per label, in file order.
+    labels: Vec<(String, usize)>,
+    // Source line number and text per physical address of an instruction.
+    lines: HashMap<usize, (usize, String)>,
+    // Every line of the listing, for showing the code around a line.
+    source: Vec<(usize, String)>,
+}
+
+impl Symbols {
+    // No labels or lines, only offsets from the base can be resolved.
+    pub fn empty(base: usize) -> Symbols {
+        Symbols {
+            base,
+            labels: Vec::new(),
+            lines: HashMap::new(),
+            source: Vec::new(),
+        }
+    }
+
+    pub fn load(text: &str, base: usize) -> Result<Symbols, String> {
+        let mut symbols = Symbols::empty(base);
+        if let Some(map) = parse_map(text) {
+            for (label, offset) in map {
+                let address = symbols.address(offset);
+                symbols.labels.push((label, address));
+            }
+            return Ok(symbols);
+        }
+
+        let listing = parse_nasm_listing(text);
+        if listing.is_empty() {
+            return Err(String::from(
+                "Expected a nasm listing or `label = address` lines",
+            ));
+        }
+        // A label on a line of its own names the next line with an address.
+        let mut pending_labels = Vec::new();
+        for line in listing {
+            if let (Some(label), _) = split_label(&line.source) {
+                pending_labels.push(label.to_string());
+            }
+            if let Some(offset) = line.offset {
+                let address = symbols.address(offset);
+                for label in pending_labels.drain(..) {
+                    symbols.labels.push((label, address));
+                }
+                if line.is_code() {
+                    let source = line.source.trim().to_string();
+                    symbols
+                        .lines
+                        .entry(address)
+                        .or_insert((line.number, source));
+                }
+            }
+            symbols.source.push((line.number, line.source));
+        }
+        Ok(symbols)
+    }
+
+    fn address(&self, offset: u32) -> usize {
+        (self.base + offset as usize) % MEMORY_SIZE
+    }
+
+    pub fn label_at(&self, address: usize) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|(_, label_address)| *label_address == address)
+            .map(|(label, _)| label.as_str())
+    }
+
+    pub fn line_at(&self, address: usize) -> Option<(usize, &str)> {
+        self.lines
+            .get(&address)
+            .map(|(number, source)| (*number, source.as_str()))
+    }
+
+    // label, label+offset or an offset into the program, in hex with 0x or h, or decimal.
+    pub fn resolve(&self, text: &str) -> Option<usize> {
+        let (name, offset) = match text.split_once('+') {
+            Some((name, offset)) => (name, parse_offset(offset)?),
+            None => (text, 0),
+        };
+        if let Some((_, address)) = self.labels.iter().find(|(label, _)| label == name) {
+            return Some((address + offset as usize) % MEMORY_SIZE);
+        }
+        if text.contains('+') {
+            return None;
+        }
+        Some(self.address(parse_offset(text)?))
+    }
+
+    // The closest label at or before the address, like `top+3`.
+    pub fn describe(&self, address: usize) -> Option<String> {
+        let (label, label_address) = self
+            .labels
+            .iter()
+            .filter(|(_, label_address)| *label_address <= address)
+            .max_by_key(|(_, label_address)| *label_address)?;
+        Some(match address - label_address {
+            0 => label.clone(),
+            offset => format!("{}+{:#x}", label, offset),
+        })
+    }
+
+    // The label and the source line of the instruction at the address, as comment lines to print
+    // before it.
+    pub fn header(&self, address: usize, comment: &str) -> String {
+        let mut text = String::new();
+        if let Some(label) = self.label_at(address) {
+            text += &format!("{}:\n", label);
+        }
+        if let Some((number, source)) = self.line_at(address) {
+            text += &format!("{} {}: {}\n", comment, number, source);
+        }
+        text
+    }
+
+    // ` ; label` after a jump or call at the address to a labelled target.
+    pub fn jump_note(&self, address: usize, instruction: &Instruction, comment: &str) -> String {
+        let Some(displacement) = instruction
+            .immediate_value
+            .filter(|_| instruction.is_relative())
+        else {
+            return String::new();
+        };
+        let target = (address + instruction.length as usize)
+            .wrapping_add_signed(displacement as isize)
+            % MEMORY_SIZE;
+        match self.label_at(target) {
+            Some(label) => format!(" {} {}", comment, label),
+            None => String::new(),
+        }
+    }
+
+    // The listing lines around a source line.
+    pub fn source_around(&self, number: usize, context: usize) -> String {
+        self.source
+            .iter()
+            .filter(|(line, _)| line.abs_diff(number) <= context)
+            .map(|(line, source)| {
+                let marker = if *line == number { '>' } else { ' ' };
+                format!("{}{:>5}  {}\n", marker, line, source)
+            })
+            .collect()
+    }
+}
+
+pub fn parse_offset(text: &str) -> Option<u32> {
+    let text = text.trim().to_ascii_lowercase();
+    if let Some(hex) = text.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(hex) = text.strip_suffix('h') {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+// None unless every line that is not blank or a ; comment is `label = address`.
+fn parse_map(text: &str) -> Option<Vec<(String, u32)>> {
+    let mut map = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        let (label, offset) = line.split_once('=')?;
+        let label = label.trim();
+        if label.is_empty() || label.contains(char::is_whitespace) {
+            return None;
+        }
+        map.push((label.to_string(), parse_offset(offset)?));
+    }
+    (!map.is_empty()).then_some(map)
+}
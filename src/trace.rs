@@ -0,0 +1,123 @@
+use crate::constants::{AX, BP, BX, CX, DI, DX, REGISTER_NAMES, SEGMENT_REGISTER_NAMES, SI, SP};
+use crate::flag::Flags;
+use crate::machine::{Cpu, ExecutedInstruction, Machine};
+
+// sim86 lists registers in alphabetical rather than encoding order.
+const REGISTER_ORDER: [usize; 8] = [AX, BX, CX, DX, SP, BP, SI, DI];
+
+pub struct TraceOptions {
+    pub show_clocks: bool,
+    pub show_ip: bool,
+}
+
+#[derive(Clone)]
+pub struct RegisterState {
+    simulation_registers: [i16; 8],
+    segment_registers: [u16; 4],
+    ip: u16,
+    flags: Flags,
+}
+
+impl RegisterState {
+    pub fn capture(machine: &Machine) -> RegisterState {
+        RegisterState {
+            simulation_registers: machine.simulation_registers,
+            segment_registers: machine.segment_registers,
+            ip: machine.ip,
+            flags: machine.flags.clone(),
+        }
+    }
+
+    fn registers(&self) -> impl Iterator<Item = (&'static str, u16)> + '_ {
+        let general = REGISTER_ORDER.iter().map(|reg| {
+            (
+                REGISTER_NAMES[1][*reg],
+                self.simulation_registers[*reg] as u16,
+            )
+        });
+        let segment = SEGMENT_REGISTER_NAMES
+            .iter()
+            .zip(self.segment_registers)
+            .map(|(name, value)| (*name, value));
+        general.chain(segment)
+    }
+}
+
+pub fn header(path: &str, cpu: Cpu, options: &TraceOptions) -> String {
+    let mut text = String::new();
+    if options.show_clocks {
+        let name = cpu.name();
+        let stars = "*".repeat(name.len() + 10);
+        text += &format!("{}\n", stars);
+        text += &format!("**** {} ****\n", name);
+        text += &format!("{}\n\n", stars);
+        text += "WARNING: Clocks reported by this utility are strictly from the 8086 manual.\n";
+        text +=
+            "They will be inaccurate, both because the manual clocks are estimates, and because\n";
+        text +=
+            "some of the entries in the manual look highly suspicious and are probably typos.\n\n";
+    }
+    text + &format!("--- {} execution ---", path)
+}
+
+pub fn format_step(
+    executed: &ExecutedInstruction,
+    before: &RegisterState,
+    after: &RegisterState,
+    total_clocks: u64,
+    options: &TraceOptions,
+) -> String {
+    let mut line = format!("{} ; ", executed.instruction);
+    if executed.instruction.undocumented {
+        line += "undocumented ; ";
+    }
+
+    if options.show_clocks {
+        let clocks = executed.output.number_of_cycles;
+        line += &format!("Clocks: +{} = {}", clocks, total_clocks);
+        if executed.effective_address_cycles != 0 {
+            let base = clocks - executed.effective_address_cycles - executed.transfer_penalty;
+            line += &format!(" ({} + {}ea", base, executed.effective_address_cycles);
+            if executed.transfer_penalty != 0 {
+                line += &format!(" + {}p", executed.transfer_penalty);
+            }
+            line += ")";
+        }
+        line += " | ";
+    }
+
+    for ((name, old), (_, new)) in before.registers().zip(after.registers()) {
+        if old != new {
+            line += &format!("{}:{:#x}->{:#x} ", name, old, new);
+        }
+    }
+    if options.show_ip && before.ip != after.ip {
+        line += &format!("ip:{:#x}->{:#x} ", before.ip, after.ip);
+    }
+    if before.flags.to_word() != after.flags.to_word() {
+        line += &format!("flags:{}->{} ", before.flags, after.flags);
+    }
+    line
+}
+
+pub fn final_registers(state: &RegisterState, options: &TraceOptions) -> String {
+    String::from("Final registers:\n") + &registers(state, options)
+}
+
+// The registers that are not zero, one per line.
+pub fn registers(state: &RegisterState, options: &TraceOptions) -> String {
+    let mut text = String::new();
+    for (name, value) in state.registers() {
+        if value != 0 {
+            text += &format!("{:>8}: {:#06x} ({})\n", name, value, value);
+        }
+    }
+    if options.show_ip && state.ip != 0 {
+        text += &format!("{:>8}: {:#06x} ({})\n", "ip", state.ip, state.ip);
+    }
+    let flags = state.flags.to_string();
+    if !flags.is_empty() {
+        text += &format!("{:>8}: {}\n", "flags", flags);
+    }
+    text
+}